@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::collections::HashMap;
+
+use photo_tagger::bench::synthetic_records;
+use photo_tagger::{apply_capture_times, assign_groups};
+
+fn bench_grouping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grouping");
+    for size in [1_000, 10_000] {
+        let records = synthetic_records(size, 42);
+
+        group.bench_with_input(BenchmarkId::new("assign_groups", size), &records, |b, records| {
+            b.iter_batched(|| records.clone(), |mut r| assign_groups(&mut r), BatchSize::LargeInput)
+        });
+
+        group.bench_with_input(BenchmarkId::new("normalize", size), &records, |b, records| {
+            let capture_times = HashMap::new();
+            b.iter_batched(
+                || records.clone(),
+                |mut r| apply_capture_times(&mut r, &capture_times),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_grouping);
+criterion_main!(benches);
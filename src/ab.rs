@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::domain::GroupItem;
+
+/// 比べる項目（description は言い回しが毎回変わるので比べない）
+pub const AB_FIELDS: [&str; 5] = ["role", "machine_type", "machine_id", "has_board", "detected_text"];
+
+/// 項目ごとの一致率
+#[derive(Debug, Clone)]
+pub struct FieldAgreement {
+    pub field: &'static str,
+    /// A・B の両方に結果のあった写真の数
+    pub compared: usize,
+    pub agreed: usize,
+}
+
+impl FieldAgreement {
+    pub fn rate(&self) -> f64 {
+        if self.compared == 0 {
+            return 0.0;
+        }
+        self.agreed as f64 / self.compared as f64
+    }
+}
+
+/// A と B で値の違った 1 項目
+#[derive(Debug, Clone)]
+pub struct AbDiff {
+    pub file: String,
+    pub field: &'static str,
+    pub a: String,
+    pub b: String,
+}
+
+/// 同じ写真を 2 つのプロンプトで分類した結果の比較（`ab`）
+#[derive(Debug, Clone, Default)]
+pub struct AbReport {
+    pub images: usize,
+    /// 結果の無かった写真（バッチの失敗・モデルが返さなかったもの）
+    pub missing_a: Vec<String>,
+    pub missing_b: Vec<String>,
+    pub fields: Vec<FieldAgreement>,
+    pub diffs: Vec<AbDiff>,
+}
+
+fn field_value(item: &GroupItem, field: &str) -> String {
+    match field {
+        "role" => item.role.trim().to_string(),
+        "machine_type" => item.machine_type.trim().to_string(),
+        "machine_id" => item.machine_id.trim().to_string(),
+        "has_board" => item.has_board.to_string(),
+        // 改行・空白の入れ方の違いは数えない
+        "detected_text" => item.detected_text.split_whitespace().collect::<Vec<_>>().join(" "),
+        _ => String::new(),
+    }
+}
+
+/// files の写真について A・B の結果を項目ごとに比べる。a・b はファイル名（レコードのキー）→ 結果。
+pub fn compare(files: &[String], a: &BTreeMap<String, GroupItem>, b: &BTreeMap<String, GroupItem>) -> AbReport {
+    let mut report = AbReport { images: files.len(), ..Default::default() };
+    let mut fields: Vec<FieldAgreement> =
+        AB_FIELDS.iter().map(|&field| FieldAgreement { field, compared: 0, agreed: 0 }).collect();
+    for file in files {
+        let (ra, rb) = (a.get(file), b.get(file));
+        if ra.is_none() {
+            report.missing_a.push(file.clone());
+        }
+        if rb.is_none() {
+            report.missing_b.push(file.clone());
+        }
+        let (Some(ra), Some(rb)) = (ra, rb) else { continue };
+        for f in &mut fields {
+            let (va, vb) = (field_value(ra, f.field), field_value(rb, f.field));
+            f.compared += 1;
+            if va == vb {
+                f.agreed += 1;
+            } else {
+                report.diffs.push(AbDiff { file: file.clone(), field: f.field, a: va, b: vb });
+            }
+        }
+    }
+    report.fields = fields;
+    report
+}
+
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+impl AbReport {
+    pub fn to_markdown(&self, label_a: &str, label_b: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# プロンプト比較\n");
+        let _ = writeln!(out, "- A: {label_a}");
+        let _ = writeln!(out, "- B: {label_b}");
+        let _ = writeln!(out, "- 写真: {}", self.images);
+        let _ = writeln!(out, "- 結果なし: A {} 枚 / B {} 枚", self.missing_a.len(), self.missing_b.len());
+
+        let _ = writeln!(out, "\n## 項目ごとの一致率\n");
+        let _ = writeln!(out, "| 項目 | 一致 | 比較 | 一致率 |");
+        let _ = writeln!(out, "|---|---:|---:|---:|");
+        for f in &self.fields {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {:.1}% |",
+                f.field,
+                f.agreed,
+                f.compared,
+                f.rate() * 100.0
+            );
+        }
+
+        if !self.diffs.is_empty() {
+            let _ = writeln!(out, "\n## 違い\n");
+            let _ = writeln!(out, "| ファイル | 項目 | A | B |");
+            let _ = writeln!(out, "|---|---|---|---|");
+            for d in &self.diffs {
+                let _ = writeln!(out, "| {} | {} | {} | {} |", cell(&d.file), d.field, cell(&d.a), cell(&d.b));
+            }
+        }
+        for (label, missing) in [("A", &self.missing_a), ("B", &self.missing_b)] {
+            if !missing.is_empty() {
+                let _ = writeln!(out, "\n## {label} の結果が無い写真\n");
+                for file in missing {
+                    let _ = writeln!(out, "- {file}");
+                }
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,44 @@
+#[cfg(feature = "ai")]
+use cli_ai_analyzer::{analyze, AnalyzeOptions};
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+
+/// プロンプトと画像群を受け取り、モデルの生出力を返す解析バックエンド
+/// 失敗は `Error::Backend` で返すこと。
+pub trait Backend: Send + Sync {
+    fn analyze(&self, prompt: &str, images: &[PathBuf]) -> Result<String>;
+
+    /// run-meta.json に記録する名前
+    fn name(&self) -> &str {
+        "custom"
+    }
+}
+
+/// cli-ai-analyzer 経由の Gemini バックエンド（既定）
+/// `ai` feature 無しでビルドした場合は常に `Error::BackendDisabled` を返す。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeminiBackend;
+
+#[cfg(feature = "ai")]
+impl Backend for GeminiBackend {
+    fn analyze(&self, prompt: &str, images: &[PathBuf]) -> Result<String> {
+        let options = AnalyzeOptions::default().json();
+        analyze(prompt, images, options).map_err(Error::backend)
+    }
+
+    fn name(&self) -> &str {
+        "gemini"
+    }
+}
+
+#[cfg(not(feature = "ai"))]
+impl Backend for GeminiBackend {
+    fn analyze(&self, _prompt: &str, _images: &[PathBuf]) -> Result<String> {
+        Err(Error::BackendDisabled)
+    }
+
+    fn name(&self) -> &str {
+        "gemini (disabled)"
+    }
+}
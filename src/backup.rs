@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::CONFIG_FILE;
+use crate::error::{Error, Result};
+use crate::fs_ops::{state_dir, GROUP_FILE, GROUP_JOURNAL_FILE, STATE_DIR, TAG_FILE};
+use crate::timestamp::LocalDateTime;
+use crate::trash::{move_to_trash, TRASH_DIR};
+
+/// .photo-tagger/ 内のバックアップ置き場（バックアップ自体は対象外）
+pub(crate) const BACKUP_DIR: &str = "backups";
+
+pub fn backup_dir(base: &Path) -> PathBuf {
+    state_dir(base).join(BACKUP_DIR)
+}
+
+/// バックアップ対象のうち、現在存在するファイル（base からの相対パス）
+pub fn state_files(base: &Path) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = [GROUP_FILE, GROUP_JOURNAL_FILE, TAG_FILE, CONFIG_FILE]
+        .iter()
+        .map(PathBuf::from)
+        .filter(|p| base.join(p).is_file())
+        .collect();
+    if let Ok(entries) = std::fs::read_dir(state_dir(base)) {
+        let mut state: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .map(|e| Path::new(STATE_DIR).join(e.file_name()))
+            .collect();
+        state.sort();
+        out.extend(state);
+    }
+    out
+}
+
+/// レコード・ジャーナル・設定・作業ファイルを .photo-tagger/backups/backup-YYYYMMDD-hhmmss.tar にまとめる
+pub fn create_backup(base: &Path, utc_offset_secs: i64) -> Result<PathBuf> {
+    let dir = backup_dir(base);
+    std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let stamp = LocalDateTime::from_epoch(now, utc_offset_secs);
+    let (y, mo, d) = stamp.date();
+    let name = format!("backup-{y:04}{mo:02}{d:02}-{:02}{:02}{:02}", stamp.hour, stamp.minute, stamp.second);
+    let mut path = dir.join(format!("{name}.tar"));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{name}-{n}.tar"));
+        n += 1;
+    }
+
+    let file = File::create(&path).map_err(|e| Error::io(&path, e))?;
+    let mut archive = tar::Builder::new(file);
+    for rel in state_files(base) {
+        let src = base.join(&rel);
+        archive.append_path_with_name(&src, &rel).map_err(|e| Error::io(&src, e))?;
+    }
+    archive.into_inner().map_err(|e| Error::io(&path, e))?;
+    Ok(path)
+}
+
+/// バックアップ一覧（古い順）
+pub fn list_backups(base: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(backup_dir(base)) else { return Vec::new() };
+    let mut out: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "tar"))
+        .collect();
+    out.sort();
+    out
+}
+
+/// バックアップに入りうる相対パスか（既知の状態ファイルか、.photo-tagger/ 直下のファイル）
+fn is_state_file(rel: &Path) -> bool {
+    use std::path::Component;
+    let names: Vec<_> = rel.components().collect();
+    match names.as_slice() {
+        [Component::Normal(name)] => [GROUP_FILE, GROUP_JOURNAL_FILE, TAG_FILE, CONFIG_FILE]
+            .iter()
+            .any(|known| name == known),
+        [Component::Normal(dir), Component::Normal(name)] => *dir == STATE_DIR && *name != BACKUP_DIR && *name != TRASH_DIR,
+        _ => false,
+    }
+}
+
+/// バックアップを展開して状態を戻す。バックアップに無い対象ファイル（後から作られたジャーナル等）はごみ箱へ移す。
+/// 戻した相対パスを返す。上書き前の状態は呼び出し側で create_backup しておくこと。
+pub fn restore_backup(base: &Path, archive_path: &Path, utc_offset_secs: i64) -> Result<Vec<PathBuf>> {
+    let open = || File::open(archive_path).map_err(|e| Error::io(archive_path, e));
+
+    let mut restored = Vec::new();
+    for entry in tar::Archive::new(open()?).entries().map_err(|e| Error::io(archive_path, e))? {
+        let entry = entry.map_err(|e| Error::io(archive_path, e))?;
+        let rel = entry.path().map_err(|e| Error::io(archive_path, e))?.into_owned();
+        if !entry.header().entry_type().is_file() || !is_state_file(&rel) {
+            return Err(Error::Validation(format!(
+                "{}: unexpected entry {}",
+                archive_path.display(),
+                rel.display()
+            )));
+        }
+        restored.push(rel);
+    }
+
+    let extra: Vec<PathBuf> = state_files(base).into_iter().filter(|rel| !restored.contains(rel)).collect();
+    move_to_trash(base, &extra, "restore", utc_offset_secs)?;
+    std::fs::create_dir_all(state_dir(base)).map_err(|e| Error::io(state_dir(base), e))?;
+    tar::Archive::new(open()?)
+        .unpack(base)
+        .map_err(|e| Error::io(base, e))?;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_base(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("photo-tagger-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    fn archive_with(path: &Path, entries: &[(&str, tar::EntryType)]) {
+        let mut archive = tar::Builder::new(File::create(path).unwrap());
+        for (name, kind) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(*kind);
+            header.set_size(if kind.is_file() { 2 } else { 0 });
+            header.set_mode(0o644);
+            if kind.is_symlink() {
+                header.set_link_name("../../outside").unwrap();
+            }
+            header.set_path(name).unwrap();
+            header.set_cksum();
+            let data: &[u8] = if kind.is_file() { b"{}" } else { b"" };
+            archive.append(&header, data).unwrap();
+        }
+        archive.into_inner().unwrap();
+    }
+
+    #[test]
+    fn state_file_names_are_accepted() {
+        assert!(is_state_file(Path::new(GROUP_FILE)));
+        assert!(is_state_file(Path::new(CONFIG_FILE)));
+        assert!(is_state_file(&Path::new(STATE_DIR).join("retry.json")));
+        assert!(!is_state_file(Path::new("IMG_0001.JPG")));
+        assert!(!is_state_file(Path::new("sub/photo-groups.json")));
+        assert!(!is_state_file(&Path::new(STATE_DIR).join(BACKUP_DIR).join("x.tar")));
+        assert!(!is_state_file(Path::new("../photo-groups.json")));
+    }
+
+    #[test]
+    fn restore_rejects_unexpected_entries() {
+        let base = temp_base("restore-reject");
+        std::fs::write(base.join("IMG_0001.JPG"), b"jpeg").unwrap();
+        let archive = base.join("bad.tar");
+
+        archive_with(&archive, &[(GROUP_FILE, tar::EntryType::Regular), ("IMG_0001.JPG", tar::EntryType::Regular)]);
+        assert!(matches!(restore_backup(&base, &archive, 0), Err(Error::Validation(_))));
+
+        archive_with(&archive, &[(TAG_FILE, tar::EntryType::Symlink)]);
+        assert!(matches!(restore_backup(&base, &archive, 0), Err(Error::Validation(_))));
+
+        assert_eq!(std::fs::read(base.join("IMG_0001.JPG")).unwrap(), b"jpeg");
+        assert!(!base.join(GROUP_FILE).exists());
+        assert!(!base.join(TAG_FILE).exists());
+
+        archive_with(&archive, &[(GROUP_FILE, tar::EntryType::Regular)]);
+        assert_eq!(restore_backup(&base, &archive, 0).unwrap(), vec![PathBuf::from(GROUP_FILE)]);
+        assert_eq!(std::fs::read(base.join(GROUP_FILE)).unwrap(), b"{}");
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::domain::{GroupRecord, GroupRecords, ReviewStatus};
+use crate::error::{Error, Result};
+use crate::exif::CameraInfo;
+use crate::fs_ops::{collect_image_entries, collect_images_flat};
+use crate::grouping::{apply_capture_times, assign_groups};
+use crate::meta::{scan_images, MetaCache, DEFAULT_SCAN_WORKERS};
+use crate::timestamp::TimestampParser;
+
+const MACHINE_TYPES: [&str; 4] = ["タイヤローラー", "マカダムローラー", "アスファルトフィニッシャー", "バックホウ"];
+const ROLES: [&str; 3] = ["機械全景", "特定自主検査証票", "排ガス対策型・低騒音型機械証票"];
+
+/// AI を使わない処理段の計測結果
+#[derive(Debug, Clone)]
+pub struct BenchRow {
+    pub stage: &'static str,
+    pub size: usize,
+    pub elapsed: Duration,
+}
+
+/// 再現可能な合成レコードを作る（同じ n と seed なら同じ内容）
+/// 1 台あたり 3 枚、数台ごとに取付道路の測点を混ぜ、時々 5 分超の間隔を空ける。
+pub fn synthetic_records(n: usize, seed: u64) -> GroupRecords {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    let base_ts: i64 = 1_770_000_000;
+    let mut ts = base_ts;
+    let mut records = GroupRecords::new();
+    for i in 0..n {
+        let machine = i / 3;
+        ts += if i % 3 == 0 && next() % 4 == 0 { 600 } else { 20 + (next() % 40) as i64 };
+        let attach = machine % 7 == 0;
+        let (machine_id, detected_text) = if attach {
+            (format!("No.{}", machine % 50), format!("取付道路 No.{}", machine % 50))
+        } else {
+            (format!("M{machine:06}"), String::new())
+        };
+        records.insert(
+            format!("20260211_{i:06}.jpg"),
+            GroupRecord {
+                role: ROLES[i % 3].to_string(),
+                machine_type: MACHINE_TYPES[machine % MACHINE_TYPES.len()].to_string(),
+                machine_id,
+                group: 0,
+                has_board: attach,
+                detected_text,
+                description: String::new(),
+                captured_at: Some(ts),
+                clock_offset: 0,
+                camera: CameraInfo::default(),
+                content_hash: String::new(),
+                caption: String::new(),
+                locked: false,
+                group_notes: String::new(),
+                group_status: ReviewStatus::Unreviewed,
+            },
+        );
+    }
+    records
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let out = f();
+    (out, start.elapsed())
+}
+
+/// 非 AI パイプラインの各段を size 件の合成データで計測する。
+/// scratch に空の画像ファイルを作って収集・メタデータ走査も測る（終了時に削除）。
+pub fn run_bench(sizes: &[usize], scratch: &Path) -> Result<Vec<BenchRow>> {
+    let mut rows = Vec::new();
+    for &size in sizes {
+        let records = synthetic_records(size, 42);
+
+        let dir = scratch.join(format!("photo-tagger-bench-{size}"));
+        std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        for fname in records.keys() {
+            let p = dir.join(fname);
+            std::fs::write(&p, b"").map_err(|e| Error::io(p, e))?;
+        }
+
+        let (images, elapsed) = time(|| collect_images_flat(&dir));
+        rows.push(BenchRow { stage: "collect", size, elapsed });
+
+        let (meta, elapsed) = time(|| scan_images(&images, &MetaCache::new(), DEFAULT_SCAN_WORKERS));
+        rows.push(BenchRow { stage: "metadata", size, elapsed });
+
+        let (_, elapsed) = time(|| scan_images(&images, &meta, DEFAULT_SCAN_WORKERS));
+        rows.push(BenchRow { stage: "metadata (cached)", size, elapsed });
+
+        let (_, elapsed) = time(|| collect_image_entries(&dir, DEFAULT_SCAN_WORKERS));
+        rows.push(BenchRow { stage: "collect+metadata", size, elapsed });
+
+        std::fs::remove_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+        let mut work = records.clone();
+        let capture_times = crate::meta::capture_times(&meta, &TimestampParser::default());
+        let (_, elapsed) = time(|| apply_capture_times(&mut work, &capture_times));
+        rows.push(BenchRow { stage: "normalize", size, elapsed });
+
+        let (_, elapsed) = time(|| assign_groups(&mut work));
+        rows.push(BenchRow { stage: "assign_groups", size, elapsed });
+
+        let (json, elapsed) = time(|| serde_json::to_string_pretty(&work));
+        rows.push(BenchRow { stage: "materialize", size, elapsed });
+        json.map_err(|e| Error::Parse {
+            message: "Failed to serialize group records".into(),
+            source: Some(e),
+        })?;
+    }
+    Ok(rows)
+}
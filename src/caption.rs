@@ -0,0 +1,49 @@
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::grouping::extract_no;
+use crate::timestamp::LocalDateTime;
+
+/// 既定のキャプション。使える差し込み項目は render_caption を参照。
+pub const DEFAULT_CAPTION_TEMPLATE: &str = "{machine_type} {station} {role}";
+
+/// 測点（No.X / 取付道路 No.X）。読めなければ空
+pub fn station(rec: &GroupRecord) -> String {
+    if rec.machine_id.contains("No") {
+        return rec.machine_id.clone();
+    }
+    extract_no(&rec.detected_text).unwrap_or_default()
+}
+
+/// テンプレートの差し込み項目を置き換える。空の項目で生じた余分な空白は詰める。
+/// `{role}` `{machine_type}` `{machine_id}` `{station}` `{text}` `{description}` `{date}` `{time}`
+pub fn render_caption(template: &str, rec: &GroupRecord, utc_offset_secs: i64) -> String {
+    let at = rec.captured_at.map(|ts| LocalDateTime::from_epoch(ts, utc_offset_secs));
+    let text = rec.detected_text.replace(['\n', '\r'], " ");
+    let rendered = template
+        .replace("{role}", &rec.role)
+        .replace("{machine_type}", &rec.machine_type)
+        .replace("{machine_id}", &rec.machine_id)
+        .replace("{station}", &station(rec))
+        .replace("{text}", &text)
+        .replace("{description}", &rec.description)
+        .replace("{date}", &at.map(|a| a.date_string()).unwrap_or_default())
+        .replace("{time}", &at.map(|a| a.time_string()).unwrap_or_default());
+    rendered.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 全レコードにキャプションを付け、変わった件数を返す。
+/// use_description なら分類時に AI が書いた description を優先する（追加の AI 呼び出しは無い）。
+pub fn fill_captions(records: &mut GroupRecords, template: &str, use_description: bool, utc_offset_secs: i64) -> usize {
+    let mut changed = 0;
+    for rec in records.values_mut() {
+        let caption = if use_description && !rec.description.is_empty() {
+            rec.description.clone()
+        } else {
+            render_caption(template, rec, utc_offset_secs)
+        };
+        if rec.caption != caption {
+            rec.caption = caption;
+            changed += 1;
+        }
+    }
+    changed
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::error::{Error, Result};
+
+/// 撮影順で隣り合う写真をこの類似度（コサイン）以上なら同じクラスタにする
+pub const DEFAULT_CLUSTER_THRESHOLD: f32 = 0.90;
+/// これ以上似ている隣り合う写真は重複の候補として表示する
+pub const DEFAULT_DUPLICATE_THRESHOLD: f32 = 0.98;
+/// CLIP 系の画像エンコーダの入力サイズ
+pub const DEFAULT_INPUT_SIZE: u32 = 224;
+
+/// 画像の埋め込みベクトル（L2 正規化済み）を作るもの
+pub trait Embedder: Send + Sync {
+    fn embed(&self, image: &Path) -> Result<Vec<f32>>;
+}
+
+/// ONNX の画像エンコーダ（CLIP の visual 部分など）。
+/// 入力は [1, 3, size, size] の float（CLIP の平均・分散で正規化）、出力は [1, D] を想定する。
+#[cfg(feature = "cluster")]
+pub struct OnnxEmbedder {
+    model: tract_onnx::prelude::TypedRunnableModel<tract_onnx::prelude::TypedModel>,
+    size: u32,
+}
+
+#[cfg(feature = "cluster")]
+impl OnnxEmbedder {
+    pub fn load(model: &Path, size: u32) -> Result<Self> {
+        use tract_onnx::prelude::*;
+        let err = |e: TractError| Error::Image { path: model.to_path_buf(), message: e.to_string() };
+        let n = size as usize;
+        let model = tract_onnx::onnx()
+            .model_for_path(model)
+            .and_then(|m| m.with_input_fact(0, f32::fact([1, 3, n, n]).into()))
+            .and_then(|m| m.into_optimized())
+            .and_then(|m| m.into_runnable())
+            .map_err(err)?;
+        Ok(OnnxEmbedder { model, size })
+    }
+}
+
+#[cfg(feature = "cluster")]
+impl Embedder for OnnxEmbedder {
+    fn embed(&self, image: &Path) -> Result<Vec<f32>> {
+        use tract_onnx::prelude::*;
+        const MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+        const STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+        let err = |message: String| Error::Image { path: image.to_path_buf(), message };
+
+        let img = crate::image_io::open_upright(image)?;
+        let img = img.resize_to_fill(self.size, self.size, image::imageops::FilterType::Triangle).to_rgb8();
+        let n = self.size as usize;
+        let input: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, n, n), |(_, c, y, x)| {
+            (img[(x as u32, y as u32)][c] as f32 / 255.0 - MEAN[c]) / STD[c]
+        })
+        .into();
+        let out = self.model.run(tvec!(input.into())).map_err(|e| err(e.to_string()))?;
+        let view = out[0].to_array_view::<f32>().map_err(|e| err(e.to_string()))?;
+        Ok(normalize(view.iter().copied().collect()))
+    }
+}
+
+/// `cluster` feature 無しでビルドした場合のダミー。読み込みは常に失敗する。
+#[cfg(not(feature = "cluster"))]
+pub struct OnnxEmbedder;
+
+#[cfg(not(feature = "cluster"))]
+impl OnnxEmbedder {
+    pub fn load(_model: &Path, _size: u32) -> Result<Self> {
+        Err(Error::Validation("--cluster needs a build with the `cluster` feature".into()))
+    }
+}
+
+#[cfg(not(feature = "cluster"))]
+impl Embedder for OnnxEmbedder {
+    fn embed(&self, _image: &Path) -> Result<Vec<f32>> {
+        Err(Error::Validation("built without the `cluster` feature".into()))
+    }
+}
+
+#[cfg(feature = "cluster")]
+fn normalize(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// 正規化済みベクトルのコサイン類似度
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// images の埋め込みを workers 並列で作る。失敗した画像は Err のまま返す（呼び出し側でクラスタに入れない）。
+pub fn embed_images(embedder: &dyn Embedder, images: &[PathBuf], workers: usize) -> Vec<Result<Vec<f32>>> {
+    let chunk = images.len().div_ceil(workers.max(1)).max(1);
+    thread::scope(|s| {
+        let handles: Vec<_> = images
+            .chunks(chunk)
+            .map(|part| s.spawn(move || part.iter().map(|p| embedder.embed(p)).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("embedding thread panicked"))
+            .collect()
+    })
+}
+
+/// 見た目の似た写真のまとまり。先頭が代表（AI に送る写真）。
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub members: Vec<PathBuf>,
+}
+
+impl Cluster {
+    pub fn representative(&self) -> &Path {
+        &self.members[0]
+    }
+}
+
+/// 重複の候補（撮影順で隣り合う 2 枚）
+#[derive(Debug, Clone)]
+pub struct Duplicate {
+    pub first: PathBuf,
+    pub second: PathBuf,
+    pub similarity: f32,
+}
+
+/// 撮影時刻順（同時刻はファイル名順）に並べ、直前の写真と threshold 以上似ていれば同じクラスタにつなげる。
+/// 同じ機械は続けて撮ることが多いので、全組み合わせではなく隣どうしだけを比べる。
+/// 埋め込みが作れなかった写真は 1 枚だけのクラスタになる。
+pub fn cluster_images(
+    images: &[PathBuf],
+    embeddings: &[Result<Vec<f32>>],
+    capture_times: &HashMap<String, i64>,
+    threshold: f32,
+    duplicate_threshold: f32,
+) -> (Vec<Cluster>, Vec<Duplicate>) {
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    let time = |i: usize| crate::fs_ops::image_key(&images[i]).and_then(|k| capture_times.get(&k).copied());
+    order.sort_by(|&a, &b| time(a).cmp(&time(b)).then_with(|| images[a].cmp(&images[b])));
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut prev: Option<usize> = None;
+    for i in order {
+        let linked = prev.and_then(|p| match (&embeddings[p], &embeddings[i]) {
+            (Ok(a), Ok(b)) => Some((p, similarity(a, b))),
+            _ => None,
+        });
+        match (linked, clusters.last_mut()) {
+            (Some((p, s)), Some(cluster)) if s >= threshold => {
+                if s >= duplicate_threshold {
+                    duplicates.push(Duplicate { first: images[p].clone(), second: images[i].clone(), similarity: s });
+                }
+                cluster.members.push(images[i].clone());
+            }
+            _ => clusters.push(Cluster { members: vec![images[i].clone()] }),
+        }
+        prev = Some(i);
+    }
+    (clusters, duplicates)
+}
+
+/// 代表の写真のファイル名（レコードのキー）→ 同じクラスタの残りの写真のキー
+pub type Followers = HashMap<String, Vec<String>>;
+
+pub fn followers(clusters: &[Cluster]) -> Followers {
+    clusters
+        .iter()
+        .filter(|c| c.members.len() > 1)
+        .filter_map(|c| {
+            let rep = crate::fs_ops::image_key(c.representative())?;
+            let rest = c.members[1..].iter().filter_map(|p| crate::fs_ops::image_key(p)).collect();
+            Some((rep, rest))
+        })
+        .collect()
+}
+
+/// 代表の分類結果を同じクラスタの写真にも付ける。黒板の文字・説明は写真ごとのものなので写さない。
+/// 付けたキーを返す。
+pub fn spread_to_followers(records: &mut GroupRecords, followers: &Followers) -> Vec<String> {
+    let mut added = Vec::new();
+    let reps: Vec<(String, GroupRecord)> = records
+        .iter()
+        .filter(|(k, _)| followers.contains_key(*k))
+        .map(|(k, r)| (k.clone(), r.clone()))
+        .collect();
+    for (rep, rec) in reps {
+        for key in &followers[&rep] {
+            let mut copy = rec.clone();
+            copy.has_board = false;
+            copy.detected_text.clear();
+            copy.description.clear();
+            copy.caption.clear();
+            copy.locked = false;
+            records.insert(key.clone(), copy);
+            added.push(key.clone());
+        }
+    }
+    added
+}
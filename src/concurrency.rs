@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// バックエンドの混み具合に合わせて並列数を調整する AIMD 制御。
+/// エラー、または成功バッチの平均遅延が基準の LATENCY_BACKOFF 倍を超えたら半減し、
+/// それ以外は 1 ずつ増やす（上限 max）。
+#[derive(Debug, Clone)]
+pub struct AimdController {
+    level: usize,
+    max: usize,
+    baseline_ms: Option<f64>,
+}
+
+const LATENCY_BACKOFF: f64 = 1.5;
+
+impl AimdController {
+    pub fn new(initial: usize, max: usize) -> Self {
+        let max = max.max(1);
+        AimdController {
+            level: initial.clamp(1, max),
+            max,
+            baseline_ms: None,
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// 1 ウェーブ分（同時に投げたバッチ群）の結果を反映し、次の並列数を返す
+    pub fn observe(&mut self, errors: usize, mean_latency_ms: Option<f64>) -> usize {
+        let slow = match (mean_latency_ms, self.baseline_ms) {
+            (Some(lat), Some(base)) => lat > base * LATENCY_BACKOFF,
+            _ => false,
+        };
+        if let Some(lat) = mean_latency_ms {
+            self.baseline_ms = Some(self.baseline_ms.map_or(lat, |b| b.min(lat)));
+        }
+
+        if errors > 0 || slow {
+            self.level = (self.level / 2).max(1);
+        } else {
+            self.level = (self.level + 1).min(self.max);
+        }
+        self.level
+    }
+}
+
+/// 既定では直近 5 バッチのうち 4 つ以上が失敗したら打ち切る
+pub const DEFAULT_BREAKER_WINDOW: usize = 5;
+pub const DEFAULT_BREAKER_ERROR_RATE: f64 = 0.8;
+
+/// API キーの誤りやバックエンド停止時に全バッチをエラーで消化しないためのサーキットブレーカー。
+/// 直近 window 回の呼び出しのエラー率が threshold 以上になったら開く（以降の呼び出しを止める）。
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    window: usize,
+    threshold: f64,
+    recent: VecDeque<Option<String>>,
+}
+
+impl CircuitBreaker {
+    /// 呼び出し側で止めたいときはブレーカー自体を作らない（CLI では `--error-window 0`）
+    pub fn new(window: usize, threshold: f64) -> Self {
+        let window = window.max(1);
+        CircuitBreaker { window, threshold, recent: VecDeque::with_capacity(window) }
+    }
+
+    /// 1 回分の結果を記録する。失敗ならエラーメッセージを渡す。
+    pub fn record(&mut self, error: Option<String>) {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(error);
+    }
+
+    fn errors(&self) -> usize {
+        self.recent.iter().filter(|e| e.is_some()).count()
+    }
+
+    /// 直近 window 回が揃っていて、1 件以上失敗し、エラー率がしきい値以上
+    pub fn is_open(&self) -> bool {
+        let errors = self.errors();
+        self.recent.len() == self.window && errors > 0 && errors as f64 / self.window as f64 >= self.threshold
+    }
+
+    /// 打ち切りの理由（直近の失敗件数と最も多かったエラー）
+    pub fn cause(&self) -> String {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for e in self.recent.iter().flatten() {
+            *counts.entry(e.as_str()).or_default() += 1;
+        }
+        let top = counts.iter().max_by_key(|(_, n)| **n).map(|(e, _)| *e).unwrap_or("");
+        format!("{} of the last {} batches failed; most common error: {top}", self.errors(), self.recent.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aimd_clamps_the_initial_level() {
+        assert_eq!(AimdController::new(0, 4).level(), 1);
+        assert_eq!(AimdController::new(9, 4).level(), 4);
+        assert_eq!(AimdController::new(3, 0).level(), 1);
+    }
+
+    #[test]
+    fn aimd_adds_one_per_clean_wave_up_to_max() {
+        let mut aimd = AimdController::new(1, 3);
+        let levels: Vec<usize> = (0..4).map(|_| aimd.observe(0, Some(1000.0))).collect();
+        assert_eq!(levels, [2, 3, 3, 3]);
+        // 遅延の無い（成功バッチの無い）ウェーブでも、エラーが無ければ増やす
+        let mut aimd = AimdController::new(1, 3);
+        assert_eq!(aimd.observe(0, None), 2);
+    }
+
+    #[test]
+    fn aimd_halves_on_errors_but_not_below_one() {
+        let mut aimd = AimdController::new(8, 8);
+        assert_eq!(aimd.observe(1, Some(1000.0)), 4);
+        assert_eq!(aimd.observe(3, None), 2);
+        assert_eq!(aimd.observe(1, None), 1);
+        assert_eq!(aimd.observe(1, None), 1);
+        assert_eq!(aimd.observe(0, Some(1000.0)), 2);
+    }
+
+    #[test]
+    fn aimd_halves_when_latency_exceeds_the_fastest_wave() {
+        let mut aimd = AimdController::new(4, 8);
+        // 最初のウェーブは基準を作るだけ
+        assert_eq!(aimd.observe(0, Some(2000.0)), 5);
+        assert_eq!(aimd.observe(0, Some(1000.0)), 6);
+        // 基準は最速の 1000ms。1.5 倍ちょうどは遅いとみなさない
+        assert_eq!(aimd.observe(0, Some(1500.0)), 7);
+        assert_eq!(aimd.observe(0, Some(1501.0)), 3);
+        assert_eq!(aimd.observe(0, Some(1400.0)), 4);
+    }
+
+    #[test]
+    fn breaker_needs_a_full_window_and_at_least_one_error() {
+        let mut breaker = CircuitBreaker::new(3, 0.5);
+        breaker.record(Some("timeout".into()));
+        breaker.record(Some("timeout".into()));
+        assert!(!breaker.is_open(), "window not full yet");
+        breaker.record(None);
+        assert!(breaker.is_open());
+        assert!(breaker.cause().starts_with("2 of the last 3 batches failed"));
+
+        let mut tiny = CircuitBreaker::new(3, f64::MIN_POSITIVE);
+        (0..3).for_each(|_| tiny.record(None));
+        assert!(!tiny.is_open(), "no errors never opens the breaker");
+        tiny.record(Some("auth".into()));
+        assert!(tiny.is_open());
+    }
+}
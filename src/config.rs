@@ -0,0 +1,285 @@
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::caption::DEFAULT_CAPTION_TEMPLATE;
+use crate::cluster::{DEFAULT_CLUSTER_THRESHOLD, DEFAULT_DUPLICATE_THRESHOLD, DEFAULT_INPUT_SIZE};
+use crate::error::{Error, Result};
+use crate::estimate::{default_model_prices, ModelPrice};
+use crate::layout::{OutputLayout, DEFAULT_OUTPUT_LAYOUT};
+use crate::machine_set::MachineSets;
+use crate::timestamp::{parse_date, TimestampParser, DEFAULT_PATTERNS, DEFAULT_UTC_OFFSET};
+use crate::validate::WorkingHours;
+
+/// 対象フォルダに置く設定ファイル。無ければ既定値で動く。
+pub const CONFIG_FILE: &str = "photo-tagger.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub timestamp: TimestampConfig,
+    pub caption: CaptionConfig,
+    pub package: PackageConfig,
+    pub hooks: HooksConfig,
+    pub cluster: ClusterConfig,
+    pub estimate: EstimateConfig,
+    pub working_hours: WorkingHoursConfig,
+    pub machine_sets: MachineSetsConfig,
+    pub sort: SortConfig,
+}
+
+/// ```toml
+/// [timestamp]
+/// patterns = ["YYYYMMDD_hhmmss", "YYYY-MM-DD hh.mm.ss"]
+/// utc_offset = "+09:00"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimestampConfig {
+    /// ファイル名中の日時パターン（先頭から順に試す）
+    pub patterns: Vec<String>,
+    /// ファイル名の日時のタイムゾーン
+    pub utc_offset: String,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        TimestampConfig {
+            patterns: DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            utc_offset: DEFAULT_UTC_OFFSET.to_string(),
+        }
+    }
+}
+
+/// ```toml
+/// [caption]
+/// template = "{machine_type} {station} {role}"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptionConfig {
+    pub template: String,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        CaptionConfig {
+            template: DEFAULT_CAPTION_TEMPLATE.to_string(),
+        }
+    }
+}
+
+/// 納品 ZIP 内の配置（`package` サブコマンド）。`/` 区切り、空なら ZIP のルート。
+///
+/// ```toml
+/// [package]
+/// root = "PHOTO"
+/// photos = "PIC/{tag}"  # {tag} は振り分け先のフォルダ（階層は / 区切り、直下の写真は空）
+/// documents = ""
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+    pub root: String,
+    pub photos: String,
+    pub documents: String,
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        PackageConfig {
+            root: String::new(),
+            photos: "{tag}".to_string(),
+            documents: String::new(),
+        }
+    }
+}
+
+/// `tag --move` の振り分け先のフォルダ構成（`/` ごとに 1 階層）
+///
+/// ```toml
+/// [sort]
+/// layout = "{写真区分}/{工種}/{date}"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SortConfig {
+    pub layout: String,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig {
+            layout: DEFAULT_OUTPUT_LAYOUT.to_string(),
+        }
+    }
+}
+
+/// `--cluster` の埋め込みモデルとしきい値
+///
+/// ```toml
+/// [cluster]
+/// model = "models/clip-vit-b32-visual.onnx"  # 対象フォルダからの相対パスも可
+/// threshold = 0.90
+/// duplicate_threshold = 0.98
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub model: String,
+    pub input_size: u32,
+    pub threshold: f32,
+    pub duplicate_threshold: f32,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            model: String::new(),
+            input_size: DEFAULT_INPUT_SIZE,
+            threshold: DEFAULT_CLUSTER_THRESHOLD,
+            duplicate_threshold: DEFAULT_DUPLICATE_THRESHOLD,
+        }
+    }
+}
+
+/// `estimate` で費用を出すモデルと単価（米ドル / 100 万トークン）。指定すると既定の一覧を置き換える。
+///
+/// ```toml
+/// [[estimate.models]]
+/// name = "gemini-2.5-flash"
+/// input_per_million = 0.30
+/// output_per_million = 2.50
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EstimateConfig {
+    pub models: Vec<ModelPrice>,
+}
+
+impl Default for EstimateConfig {
+    fn default() -> Self {
+        EstimateConfig { models: default_model_prices() }
+    }
+}
+
+/// 作業時間外・休日の撮影を `validate` で報告する（カメラの時計ずれの検出）。未設定なら検査しない。
+///
+/// ```toml
+/// [working_hours]
+/// start = "07:00"
+/// end = "19:00"              # start より前なら日をまたぐ夜間作業
+/// holidays = "holidays.txt"  # 1 行 1 日 YYYY-MM-DD（# 以降はコメント）。対象フォルダからの相対パスも可
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkingHoursConfig {
+    pub start: String,
+    pub end: String,
+    pub holidays: String,
+}
+
+impl WorkingHoursConfig {
+    /// 時刻と休日ファイルを読む。何も設定されていなければ None。
+    pub fn load(&self, folder: &Path) -> Result<Option<WorkingHours>> {
+        let config_err = |message: String| Error::Config { path: folder.join(CONFIG_FILE), message };
+        let window = match (self.start.trim(), self.end.trim()) {
+            ("", "") => None,
+            (start, end) => {
+                let minutes = |s: &str| {
+                    parse_hhmm(s).ok_or_else(|| config_err(format!("working_hours: expected hh:mm, got {s:?}")))
+                };
+                Some((minutes(start)?, minutes(end)?))
+            }
+        };
+        let mut holidays = BTreeSet::new();
+        if !self.holidays.is_empty() {
+            let path = folder.join(&self.holidays);
+            let text = std::fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+            for (i, line) in text.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                let Some(token) = line.split_whitespace().next() else { continue };
+                let date = parse_date(token).ok_or_else(|| Error::Config {
+                    path: path.clone(),
+                    message: format!("line {}: expected YYYY-MM-DD, got {token:?}", i + 1),
+                })?;
+                holidays.insert(date);
+            }
+        }
+        if window.is_none() && holidays.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(WorkingHours { window, holidays }))
+    }
+}
+
+/// 機械 1 台分として揃えるべき写真を機械種別ごとに決めるテンプレート。未設定なら全景・検査証票・排ガス/低騒音証票。
+///
+/// ```toml
+/// [machine_sets]
+/// templates = "machine-sets.toml"  # 対象フォルダからの相対パスも可
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineSetsConfig {
+    pub templates: String,
+}
+
+/// "hh:mm" → 0 時からの分（24:00 まで）
+fn parse_hhmm(s: &str) -> Option<u32> {
+    let (h, m) = s.split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    (m < 60 && h * 60 + m <= 24 * 60).then_some(h * 60 + m)
+}
+
+/// 後処理フック。レコードや実行結果を 1 行の JSON で標準入力に渡す（社内台帳への登録など）。
+///
+/// ```toml
+/// [hooks]
+/// on_record = ["python push_daicho.py"]  # 分類したレコードごと
+/// on_run_end = ["notify.bat"]            # 実行の終わりに 1 回
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub on_record: Vec<String>,
+    pub on_run_end: Vec<String>,
+}
+
+impl Config {
+    /// folder/photo-tagger.toml を読む。無ければ既定値。
+    pub fn load(folder: &Path) -> Result<Config> {
+        let path = folder.join(CONFIG_FILE);
+        let s = match std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+        toml::from_str(&s).map_err(|e| Error::Config { path, message: e.to_string() })
+    }
+
+    pub fn timestamp_parser(&self) -> Result<TimestampParser> {
+        TimestampParser::new(&self.timestamp.patterns, &self.timestamp.utc_offset)
+    }
+
+    /// [working_hours] の作業時間帯と休日。未設定なら None。
+    pub fn working_hours(&self, folder: &Path) -> Result<Option<WorkingHours>> {
+        self.working_hours.load(folder)
+    }
+
+    /// [sort] の振り分け先のフォルダ構成
+    pub fn output_layout(&self, folder: &Path) -> Result<OutputLayout> {
+        OutputLayout::parse(&self.sort.layout).map_err(|message| Error::Config {
+            path: folder.join(CONFIG_FILE),
+            message: format!("[sort] layout: {message}"),
+        })
+    }
+
+    /// [machine_sets] のテンプレート。未設定なら既定の 3 枚。
+    pub fn machine_sets(&self, folder: &Path) -> Result<MachineSets> {
+        match self.machine_sets.templates.trim() {
+            "" => Ok(MachineSets::default()),
+            file => MachineSets::load(&folder.join(file)),
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use anyhow::{Context, Result};
-use cli_ai_analyzer::{analyze, AnalyzeOptions};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-#[derive(Debug, Deserialize)]
+use crate::backend::{Backend, GeminiBackend};
+use crate::error::{Error, Result};
+use crate::exif::CameraInfo;
+use crate::fs_ops::{record_key, KeyIndex};
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct GroupItem {
     pub file: String,
     pub role: String,
@@ -18,7 +22,11 @@ pub struct GroupItem {
     pub description: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// photo-groups.json の 1 レコード。版番号は持たない:
+/// 項目は `#[serde(default)]` 付きで追加するだけにして古いファイルをそのまま読み、未知の項目は無視して
+/// 新しい版のファイルも古い版で読めるようにする。既存項目の意味や型を変えるときに版番号と
+/// load_group_records での移行を入れる。
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct GroupRecord {
     pub role: String,
     pub machine_type: String,
@@ -32,13 +40,182 @@ pub struct GroupRecord {
     pub description: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub captured_at: Option<i64>,
+    /// captured_at に足してある時計の補正（秒、--time-offset）
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub clock_offset: i64,
+    /// EXIF から読んだ撮影機材（複数の人が同じフォルダに撮るときの区別）
+    #[serde(default, skip_serializing_if = "CameraInfo::is_empty")]
+    pub camera: CameraInfo,
+    /// 分類したときの画像の内容の SHA-256。同じ名前のまま差し替えられていれば次の実行で再分類する
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content_hash: String,
+    /// アルバム用のキャプション（`caption` サブコマンドで付ける）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub caption: String,
+    /// 人が確認済み。再分類・正規化で上書きしない
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub locked: bool,
+    /// グループへのメモ（`groups note`）。グループの全写真に同じ値を入れ、後から加わった写真には
+    /// グループ分けのときに写す
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group_notes: String,
+    /// グループの確認状態（`groups status`）。group_notes と同じく全写真にそろえる
+    #[serde(default, skip_serializing_if = "ReviewStatus::is_unreviewed")]
+    pub group_status: ReviewStatus,
+}
+
+/// グループの確認状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    #[default]
+    Unreviewed,
+    Approved,
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn is_unreviewed(&self) -> bool {
+        *self == ReviewStatus::Unreviewed
+    }
+
+    /// photo-groups.json・CLI での表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Unreviewed => "unreviewed",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReviewStatus::Unreviewed => "未確認",
+            ReviewStatus::Approved => "承認",
+            ReviewStatus::Rejected => "差し戻し",
+        }
+    }
+}
+
+impl From<GroupItem> for GroupRecord {
+    fn from(item: GroupItem) -> Self {
+        GroupRecord {
+            role: item.role,
+            machine_type: item.machine_type,
+            machine_id: item.machine_id,
+            group: 0,
+            has_board: item.has_board,
+            detected_text: item.detected_text,
+            description: item.description,
+            captured_at: None,
+            clock_offset: 0,
+            camera: CameraInfo::default(),
+            content_hash: String::new(),
+            caption: String::new(),
+            locked: false,
+            group_notes: String::new(),
+            group_status: ReviewStatus::Unreviewed,
+        }
+    }
 }
 
 fn is_false(v: &bool) -> bool {
     !v
 }
 
-pub type GroupRecords = HashMap<String, GroupRecord>;
+fn is_zero(v: &i64) -> bool {
+    *v == 0
+}
+
+/// ファイル名 → レコード。キー順で反復・保存されるため出力は実行ごとに安定する。
+pub type GroupRecords = BTreeMap<String, GroupRecord>;
+
+/// ファイル名またはグループ番号で指定したレコードのロックを切り替え、変わった件数を返す
+pub fn set_locked(records: &mut GroupRecords, targets: &[String], locked: bool) -> Result<usize> {
+    let mut files: Vec<String> = Vec::new();
+    let index = KeyIndex::new(records.keys());
+    for target in targets {
+        if let Some((key, _)) = index.find(records, target) {
+            files.push(key.clone());
+            continue;
+        }
+        let members: Vec<String> = match target.parse::<u32>() {
+            Ok(group) => records
+                .iter()
+                .filter(|(_, r)| r.group == group)
+                .map(|(f, _)| f.clone())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if members.is_empty() {
+            return Err(Error::Validation(format!("no record or group matches `{target}`")));
+        }
+        files.extend(members);
+    }
+
+    let mut changed = 0;
+    for fname in files {
+        if let Some(rec) = records.get_mut(&fname).filter(|r| r.locked != locked) {
+            rec.locked = locked;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// グループ番号 group の全レコードに update を適用し、変わった件数を返す
+fn update_group(records: &mut GroupRecords, group: u32, update: impl Fn(&mut GroupRecord)) -> Result<usize> {
+    if group == 0 || !records.values().any(|r| r.group == group) {
+        return Err(Error::Validation(format!("no group {group}")));
+    }
+    let mut changed = 0;
+    for rec in records.values_mut().filter(|r| r.group == group) {
+        let before = (rec.group_notes.clone(), rec.group_status);
+        update(rec);
+        if (rec.group_notes.clone(), rec.group_status) != before {
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// グループのメモを書き換える（空文字で消す）
+pub fn set_group_notes(records: &mut GroupRecords, group: u32, notes: &str) -> Result<usize> {
+    let notes = notes.trim();
+    update_group(records, group, |r| r.group_notes = notes.to_string())
+}
+
+pub fn set_group_status(records: &mut GroupRecords, group: u32, status: ReviewStatus) -> Result<usize> {
+    update_group(records, group, |r| r.group_status = status)
+}
+
+/// 分類結果のうち、既存のロック済みレコードを上書きするものを取り除く
+pub fn drop_locked(records: &GroupRecords, batch: &mut GroupRecords) {
+    let index = KeyIndex::new(records.keys());
+    batch.retain(|fname, _| !index.find(records, fname).is_some_and(|(_, r)| r.locked));
+}
+
+/// 使用機械 1 台分として揃えるべき写真の役割
+pub const ROLE_OVERVIEW: &str = "機械全景";
+pub const ROLE_INSPECTION: &str = "特定自主検査証票";
+pub const ROLE_EMISSION: &str = "排ガス対策型・低騒音型機械証票";
+
+/// 機械の写真であることを示す役割（活動写真と区別する）
+pub fn is_machine_role(role: &str) -> bool {
+    is_inspection_role(role)
+        || is_emission_role(role)
+        || ["機械全景", "ナンバープレート", "始業前点検"].iter().any(|r| role.contains(r))
+}
+
+/// 特定自主検査証票（表記揺れを許容）
+pub fn is_inspection_role(role: &str) -> bool {
+    role.contains("検査証")
+}
+
+/// 排ガス対策型・低騒音型機械証票（どちらか片方の表記でも可）
+pub fn is_emission_role(role: &str) -> bool {
+    role.contains("排ガス") || role.contains("低騒音")
+}
 
 pub fn group_prompt(filenames: &[&str], vocabulary: Option<&[String]>) -> String {
     let list = filenames.join(", ");
@@ -62,6 +239,11 @@ has_board: 黒板が写っていればtrue
 detected_text: 黒板・銘板・証票・出来形管理用紙に書かれたテキストを記録。出来形管理用紙の場合は以下のカンマ区切り形式で記録せよ: 「出来形管理用紙 No.X, 計画高(設計) V1=数値 V2=数値 V3=数値 V4=数値 V5=数値, 計画高(実施) V1=数値 V2=数値 V3=数値 V4=数値 V5=数値, 切削高(設計) V1=数値 V2=数値 V3=数値 V4=数値 V5=数値, 切削高(実施) V1=数値 V2=数値 V3=数値 V4=数値 V5=数値, 左幅員 設計X.XX 実測X.XX, 右幅員 設計X.XX 実測X.XX」
 description: 写真の内容を1文で記述"#
     );
+    push_vocabulary(&mut prompt, vocabulary);
+    prompt
+}
+
+fn push_vocabulary(prompt: &mut String, vocabulary: Option<&[String]>) {
     if let Some(vocab) = vocabulary {
         if !vocab.is_empty() {
             prompt.push_str(&format!(
@@ -70,6 +252,63 @@ description: 写真の内容を1文で記述"#
             ));
         }
     }
+}
+
+/// BatchContext に残す機械の数（プロンプトが長くなりすぎないように）
+pub const BATCH_CONTEXT_LIMIT: usize = 20;
+
+/// 直前までのバッチで分類した機械・対象（machine_type と machine_id）。
+/// 次のバッチのプロンプトに添え、バッチの境目をまたいで撮った同じ機械に同じ machine_id を付けさせる。
+#[derive(Debug, Clone, Default)]
+pub struct BatchContext {
+    /// 古い順。同じ機械をまた見たら末尾に移す。
+    machines: Vec<(String, String)>,
+}
+
+impl BatchContext {
+    /// バッチの結果を覚える。machine_id の無いものは手掛かりにならないので覚えない。
+    pub fn record(&mut self, item: &GroupItem) {
+        if item.machine_id.trim().is_empty() {
+            return;
+        }
+        let key = (item.machine_type.clone(), item.machine_id.clone());
+        self.machines.retain(|m| *m != key);
+        self.machines.push(key);
+        if self.machines.len() > BATCH_CONTEXT_LIMIT {
+            self.machines.remove(0);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    fn push_to(&self, prompt: &mut String) {
+        if self.is_empty() {
+            return;
+        }
+        let list: Vec<String> = self.machines.iter().map(|(t, id)| format!("{t} {id}")).collect();
+        prompt.push_str(&format!(
+            "\n直前のバッチで分類済みの機械・対象（同じものが写っていれば同じ machine_type と machine_id を使え）:\n{}",
+            list.join(", ")
+        ));
+    }
+}
+
+/// 黒板・文字が写っていない見込みの写真用の短いプロンプト（--text-filter）。
+/// 黒板・出来形管理用紙の読み取り指示を省く。
+pub fn group_prompt_no_text(filenames: &[&str], vocabulary: Option<&[String]>) -> String {
+    let list = filenames.join(", ");
+    let mut prompt = format!(
+        r#"工事写真を分類・グループ分けせよ。同一対象の写真をグループにまとめろ。Output ONLY JSON array: [{{"file":"filename","role":"?","machine_type":"?","machine_id":"?","description":""}}, ...]
+ファイル: {list}
+これらの写真には黒板・文字はほぼ写っていない。文字の読み取りは不要。
+role: 写真の役割（例: "機械全景", "点検状況", "安全活動", "作業状況" など）
+machine_type: 機械・対象の種類（例: タイヤローラー, マカダムローラー, アスファルトフィニッシャー, バックホウ）。機械でなければ活動名（例: 安全パトロール, 朝礼）
+machine_id: 同一対象の写真は同じ値にせよ。不明なら空文字。
+description: 写真の内容を1文で記述"#
+    );
+    push_vocabulary(&mut prompt, vocabulary);
     prompt
 }
 
@@ -80,6 +319,68 @@ pub fn extract_json_array(s: &str) -> Option<&str> {
 }
 
 pub fn classify_group_batch(images: &[PathBuf], vocabulary: Option<&[String]>) -> Result<Vec<(String, GroupItem)>> {
+    classify_group_batch_with(&GeminiBackend, images, vocabulary)
+}
+
+/// 任意のバックエンドでバッチを分類する
+pub fn classify_group_batch_with(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    vocabulary: Option<&[String]>,
+) -> Result<Vec<(String, GroupItem)>> {
+    classify_with_prompt(backend, images, |names| group_prompt(names, vocabulary))
+}
+
+/// 前のバッチの結果を添えて分類する（バッチをまたいで撮った機械の machine_id をそろえる）
+pub fn classify_group_batch_in_context_with(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    vocabulary: Option<&[String]>,
+    context: &BatchContext,
+) -> Result<Vec<(String, GroupItem)>> {
+    classify_with_prompt(backend, images, |names| {
+        let mut prompt = group_prompt(names, vocabulary);
+        context.push_to(&mut prompt);
+        prompt
+    })
+}
+
+/// 黒板・文字が無い見込みの写真を短いプロンプトで分類する。has_board / detected_text は付けない。
+pub fn classify_group_batch_no_text_with(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    vocabulary: Option<&[String]>,
+    context: &BatchContext,
+) -> Result<Vec<(String, GroupItem)>> {
+    let mut items = classify_with_prompt(backend, images, |names| {
+        let mut prompt = group_prompt_no_text(names, vocabulary);
+        context.push_to(&mut prompt);
+        prompt
+    })?;
+    for (_, item) in &mut items {
+        item.has_board = false;
+        item.detected_text.clear();
+    }
+    Ok(items)
+}
+
+/// プロンプトのテンプレートで `{files}` をバッチのファイル名（カンマ区切り）に置き換える目印
+pub const PROMPT_FILES_PLACEHOLDER: &str = "{files}";
+
+/// ファイルから読んだプロンプトで分類する（`ab` の比較用）。出力は group_prompt と同じ JSON 配列を想定する。
+pub fn classify_group_batch_with_template(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    template: &str,
+) -> Result<Vec<(String, GroupItem)>> {
+    classify_with_prompt(backend, images, |names| template.replace(PROMPT_FILES_PLACEHOLDER, &names.join(", ")))
+}
+
+fn classify_with_prompt(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    prompt: impl FnOnce(&[&str]) -> String,
+) -> Result<Vec<(String, GroupItem)>> {
     let names: Vec<&str> = images
         .iter()
         .map(|p| {
@@ -89,21 +390,23 @@ pub fn classify_group_batch(images: &[PathBuf], vocabulary: Option<&[String]>) -
         })
         .collect();
 
-    let prompt = group_prompt(&names, vocabulary);
-    let options = AnalyzeOptions::default().json();
-
-    let raw = analyze(&prompt, images, options).context("AI analyze failed")?;
+    let prompt = prompt(&names);
+    let raw = backend.analyze(&prompt, images)?;
 
-    let json_str = extract_json_array(&raw)
-        .with_context(|| format!("No JSON array in: {raw}"))?;
+    let json_str = extract_json_array(&raw).ok_or_else(|| Error::Parse {
+        message: format!("No JSON array in: {raw}"),
+        source: None,
+    })?;
 
-    let items: Vec<GroupItem> =
-        serde_json::from_str(json_str).context("Failed to parse group JSON")?;
+    let items: Vec<GroupItem> = serde_json::from_str(json_str).map_err(|e| Error::Parse {
+        message: "Failed to parse group JSON".into(),
+        source: Some(e),
+    })?;
 
     Ok(items
         .into_iter()
         .map(|g| {
-            let file = g.file.clone();
+            let file = record_key(&g.file);
             (file, g)
         })
         .collect())
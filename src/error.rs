@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// ライブラリのエラー種別。CLI 側では anyhow に包んで表示する。
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// AI バックエンド呼び出しの失敗
+    #[error("AI analyze failed: {0}")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// `ai` feature 無しでビルドされているため AI 解析が使えない
+    #[error("AI backend is disabled (built without the `ai` feature)")]
+    BackendDisabled,
+
+    /// モデル出力の JSON が取り出せない / パースできない
+    #[error("{message}")]
+    Parse {
+        message: String,
+        #[source]
+        source: Option<serde_json::Error>,
+    },
+
+    /// レコードファイル（photo-groups.json など）が壊れている
+    #[error("corrupt record file {}: {source}", path.display())]
+    CorruptRecords {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// photo-tagger.toml の読み込み・検証エラー
+    #[error("invalid config {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// ローカルの画像処理（--cluster の埋め込み、--text-filter の文字判定）の失敗
+    #[error("image processing failed for {}: {message}", path.display())]
+    Image { path: PathBuf, message: String },
+
+    /// [hooks] の外部コマンドが起動できない・失敗した
+    #[error("hook `{command}` failed: {message}")]
+    Hook { command: String, message: String },
+
+    /// --git-commit の git コマンドが起動できない・失敗した
+    #[error("git commit failed: {message}")]
+    Git { message: String },
+
+    /// 引数・設定値の検証エラー
+    #[error("{0}")]
+    Validation(String),
+}
+
+impl Error {
+    pub fn backend(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Backend(err.into())
+    }
+
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io { path: path.into(), source }
+    }
+
+    /// エラー種別の短い名前（errors.html などの集計用）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Backend(_) => "backend",
+            Error::BackendDisabled => "backend disabled",
+            Error::Parse { .. } => "parse",
+            Error::CorruptRecords { .. } => "corrupt records",
+            Error::Config { .. } => "config",
+            Error::Io { .. } => "io",
+            Error::Image { .. } => "image",
+            Error::Hook { .. } => "hook",
+            Error::Git { .. } => "git",
+            Error::Validation(_) => "validation",
+        }
+    }
+}
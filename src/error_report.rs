@@ -0,0 +1,88 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fs_ops::state_dir;
+use crate::summary::html_escape;
+
+const ERROR_REPORT_FILE: &str = "errors.html";
+
+/// 分類に失敗したバッチ 1 件
+#[derive(Debug, Clone)]
+pub struct FailedBatch {
+    pub batch: usize,
+    /// base からの相対パス
+    pub files: Vec<String>,
+    /// Error::kind()
+    pub kind: String,
+    /// エラー全文（JSON が取り出せなかった場合はモデルの生出力を含む）
+    pub message: String,
+}
+
+/// errors.html の img src 用。ファイル名の空白や `#` などだけ符号化する。
+fn url_path(rel: &str) -> String {
+    rel.chars()
+        .map(|c| match c {
+            ' ' => "%20".to_string(),
+            '#' => "%23".to_string(),
+            '?' => "%3F".to_string(),
+            '%' => "%25".to_string(),
+            '"' => "%22".to_string(),
+            '\\' => "/".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// 失敗したバッチごとに、写真のサムネイル・エラー種別・エラー全文を並べた HTML
+pub fn error_report_html(folder: &str, finished_at: &str, failures: &[FailedBatch]) -> String {
+    let photos: usize = failures.iter().map(|f| f.files.len()).sum();
+    let title = format!("photo-tagger errors: {folder} — {finished_at}");
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head><meta charset=\"utf-8\"><title>{0}</title>\n\
+         <style>img{{width:160px;margin:2px}} pre{{white-space:pre-wrap;background:#f4f4f4;padding:8px}}</style>\n\
+         </head>\n<body>\n<h1>{0}</h1>\n<p>{1} failed batch(es), {2} photo(s)</p>",
+        html_escape(&title),
+        failures.len(),
+        photos
+    );
+    for f in failures {
+        let _ = writeln!(
+            out,
+            "<h2>Batch {} <small>({})</small></h2>\n<div>",
+            f.batch,
+            html_escape(&f.kind)
+        );
+        for file in &f.files {
+            // errors.html は .photo-tagger/ に置くので、写真は 1 つ上
+            let src = html_escape(&url_path(&format!("../{file}")));
+            let _ = writeln!(
+                out,
+                "<a href=\"{src}\"><img src=\"{src}\" alt=\"{0}\" title=\"{0}\" loading=\"lazy\"></a>",
+                html_escape(file)
+            );
+        }
+        let _ = writeln!(out, "</div>\n<pre>{}</pre>", html_escape(&f.message));
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+/// .photo-tagger/errors.html に書き出し、そのパスを返す。
+/// 失敗が無ければ前回のレポートを消して None（古いレポートを今回の結果と取り違えないように）。
+pub fn write_error_report(base: &Path, finished_at: &str, failures: &[FailedBatch]) -> Result<Option<PathBuf>> {
+    let dir = state_dir(base);
+    let path = dir.join(ERROR_REPORT_FILE);
+    if failures.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::io(&path, e)),
+            _ => Ok(None),
+        };
+    }
+    std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+    let html = error_report_html(&base.display().to_string(), finished_at, failures);
+    std::fs::write(&path, html).map_err(|e| Error::io(&path, e))?;
+    Ok(Some(path))
+}
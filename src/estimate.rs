@@ -0,0 +1,148 @@
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// 寸法の読めない画像はこのサイズ（一般的なスマホ・デジカメの写真）とみなす
+pub const FALLBACK_IMAGE_SIZE: (u32, u32) = (4000, 3000);
+/// 画像 1 タイル（768×768）あたり、または 384px 以下の小さな画像 1 枚あたりのトークン数（Gemini の数え方）
+pub const TOKENS_PER_TILE: u64 = 258;
+/// 1 枚あたりの出力トークン数の目安（group: 役割・機械・黒板の文字・説明の JSON 1 件）
+pub const GROUP_OUTPUT_TOKENS_PER_IMAGE: u64 = 150;
+/// 1 枚あたりの出力トークン数の目安（tag: タグ・黒板の文字・確信度の JSON 1 件）
+pub const TAG_OUTPUT_TOKENS_PER_IMAGE: u64 = 60;
+
+/// モデルの単価（米ドル / 100 万トークン）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub name: String,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+/// 2025 年時点の Gemini API の公開価格（有料枠・200k トークン以下）。変わったら photo-tagger.toml の [estimate] で上書きする。
+pub fn default_model_prices() -> Vec<ModelPrice> {
+    let price = |name: &str, input_per_million, output_per_million| ModelPrice {
+        name: name.to_string(),
+        input_per_million,
+        output_per_million,
+    };
+    vec![price("gemini-2.5-flash", 0.30, 2.50), price("gemini-2.5-flash-lite", 0.10, 0.40)]
+}
+
+/// JPEG / PNG のヘッダーから (幅, 高さ) を読む。画像全体は読まない。
+pub fn image_size(path: &Path) -> Option<(u32, u32)> {
+    let mut r = BufReader::new(File::open(crate::fs_ops::long_path(path)).ok()?);
+    let mut head = [0u8; 8];
+    r.read_exact(&mut head[..2]).ok()?;
+    if head[..2] == [0xFF, 0xD8] {
+        return jpeg_size(&mut r);
+    }
+    r.read_exact(&mut head[2..]).ok()?;
+    if head == *b"\x89PNG\r\n\x1a\n" {
+        let mut ihdr = [0u8; 16];
+        r.read_exact(&mut ihdr).ok()?;
+        let be = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        return (&ihdr[4..8] == b"IHDR").then(|| (be(&ihdr[8..12]), be(&ihdr[12..16])));
+    }
+    None
+}
+
+/// SOI の後のセグメントをたどり、SOFn の寸法を返す（EXIF などのセグメントは読み飛ばす）
+fn jpeg_size(r: &mut BufReader<File>) -> Option<(u32, u32)> {
+    let mut byte = [0u8; 1];
+    loop {
+        r.read_exact(&mut byte).ok()?;
+        if byte[0] != 0xFF {
+            return None;
+        }
+        // 0xFF の詰め物を飛ばす
+        while byte[0] == 0xFF {
+            r.read_exact(&mut byte).ok()?;
+        }
+        let marker = byte[0];
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            continue;
+        }
+        let mut len = [0u8; 2];
+        r.read_exact(&mut len).ok()?;
+        let len = u16::from_be_bytes(len) as i64;
+        let is_sof = (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_sof {
+            let mut sof = [0u8; 5];
+            r.read_exact(&mut sof).ok()?;
+            let h = u16::from_be_bytes([sof[1], sof[2]]) as u32;
+            let w = u16::from_be_bytes([sof[3], sof[4]]) as u32;
+            return Some((w, h));
+        }
+        if marker == 0xDA || len < 2 {
+            return None;
+        }
+        r.seek(SeekFrom::Current(len - 2)).ok()?;
+    }
+}
+
+/// 画像 1 枚の入力トークン数。両辺 384px 以下なら 1 タイル、それ以外は 768×768 のタイル数。
+pub fn image_tokens((w, h): (u32, u32)) -> u64 {
+    if w <= 384 && h <= 384 {
+        return TOKENS_PER_TILE;
+    }
+    (w.div_ceil(768) as u64) * (h.div_ceil(768) as u64) * TOKENS_PER_TILE
+}
+
+/// プロンプトのトークン数の目安（英数字は 4 文字で 1、日本語などは 1 文字で 1）
+pub fn text_tokens(text: &str) -> u64 {
+    let ascii = text.bytes().filter(u8::is_ascii).count() as u64;
+    let other = text.chars().filter(|c| !c.is_ascii()).count() as u64;
+    ascii.div_ceil(4) + other
+}
+
+/// 未処理の写真を今の設定で送った場合の見積もり（API は呼ばない）
+#[derive(Debug, Clone, Default)]
+pub struct Estimate {
+    pub images: usize,
+    /// 寸法が読めず FALLBACK_IMAGE_SIZE とみなした画像
+    pub unknown_size: usize,
+    pub requests: usize,
+    pub image_tokens: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Estimate {
+    pub fn input_tokens(&self) -> u64 {
+        self.image_tokens + self.prompt_tokens
+    }
+
+    /// 米ドル
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        (self.input_tokens() as f64 * price.input_per_million + self.output_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// batches をそれぞれ 1 リクエストとして見積もる。prompt はバッチのファイル名からプロンプトを作る
+/// （本番と同じ関数を渡す）。
+pub fn estimate_batches(
+    batches: &[Vec<PathBuf>],
+    prompt: impl Fn(&[&str]) -> String,
+    output_tokens_per_image: u64,
+) -> Estimate {
+    let mut est = Estimate { requests: batches.len(), ..Default::default() };
+    for batch in batches {
+        let names: Vec<String> = batch.iter().filter_map(|p| crate::fs_ops::image_key(p)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        est.prompt_tokens += text_tokens(&prompt(&names));
+        for img in batch {
+            let size = image_size(img).unwrap_or_else(|| {
+                est.unknown_size += 1;
+                FALLBACK_IMAGE_SIZE
+            });
+            est.image_tokens += image_tokens(size);
+        }
+        est.images += batch.len();
+        est.output_tokens += batch.len() as u64 * output_tokens_per_image;
+    }
+    est
+}
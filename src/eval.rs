@@ -0,0 +1,179 @@
+use serde::Deserialize;
+use std::fmt::Write;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::error::{Error, Result};
+use crate::fs_ops::record_key;
+
+/// 正解データ（golden.jsonl）の 1 行。書いた項目だけを採点する。
+///
+/// ```json
+/// {"file": "IMG_0001.jpg", "role": "機械全景", "machine_type": "タイヤローラー", "machine_id": "TZ701"}
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoldenEntry {
+    pub file: String,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub machine_type: Option<String>,
+    #[serde(default)]
+    pub machine_id: Option<String>,
+    #[serde(default)]
+    pub has_board: Option<bool>,
+}
+
+/// 採点する項目
+pub const EVAL_FIELDS: [&str; 4] = ["role", "machine_type", "machine_id", "has_board"];
+
+impl GoldenEntry {
+    fn expected(&self, field: &str) -> Option<String> {
+        match field {
+            "role" => self.role.clone(),
+            "machine_type" => self.machine_type.clone(),
+            "machine_id" => self.machine_id.clone(),
+            "has_board" => self.has_board.map(|b| b.to_string()),
+            _ => None,
+        }
+    }
+}
+
+fn actual(rec: &GroupRecord, field: &str) -> String {
+    match field {
+        "role" => rec.role.clone(),
+        "machine_type" => rec.machine_type.clone(),
+        "machine_id" => rec.machine_id.clone(),
+        "has_board" => rec.has_board.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// 全角・半角と空白の違いは正解とみなす（「Ｎｏ．１」と「No.1」など）
+fn normalize(s: &str) -> String {
+    s.nfkc().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// golden.jsonl を読む。空行と `#` で始まる行は飛ばす。
+pub fn load_golden(path: &Path) -> Result<Vec<GoldenEntry>> {
+    let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut entry: GoldenEntry = serde_json::from_str(line).map_err(|e| Error::Parse {
+            message: format!("Invalid golden entry: {} line {}", path.display(), i + 1),
+            source: Some(e),
+        })?;
+        entry.file = record_key(&entry.file);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// 項目ごとの正解率
+#[derive(Debug, Clone)]
+pub struct FieldAccuracy {
+    pub field: &'static str,
+    /// 正解の書いてあった写真（結果の無いものを除く）
+    pub labeled: usize,
+    pub correct: usize,
+}
+
+impl FieldAccuracy {
+    pub fn rate(&self) -> f64 {
+        if self.labeled == 0 {
+            return 0.0;
+        }
+        self.correct as f64 / self.labeled as f64
+    }
+}
+
+/// 正解と違った 1 項目
+#[derive(Debug, Clone)]
+pub struct Miss {
+    pub file: String,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// 正解データに対する分類結果の採点（`eval`）
+#[derive(Debug, Clone, Default)]
+pub struct EvalReport {
+    pub entries: usize,
+    /// 分類結果の無かった写真
+    pub missing: Vec<String>,
+    pub fields: Vec<FieldAccuracy>,
+    pub misses: Vec<Miss>,
+}
+
+pub fn evaluate(golden: &[GoldenEntry], records: &GroupRecords) -> EvalReport {
+    let mut report = EvalReport { entries: golden.len(), ..Default::default() };
+    let mut fields: Vec<FieldAccuracy> =
+        EVAL_FIELDS.iter().map(|&field| FieldAccuracy { field, labeled: 0, correct: 0 }).collect();
+    for entry in golden {
+        let Some(rec) = records.get(&entry.file) else {
+            report.missing.push(entry.file.clone());
+            continue;
+        };
+        for f in &mut fields {
+            let Some(expected) = entry.expected(f.field) else { continue };
+            let actual = actual(rec, f.field);
+            f.labeled += 1;
+            if normalize(&expected) == normalize(&actual) {
+                f.correct += 1;
+            } else {
+                report.misses.push(Miss { file: entry.file.clone(), field: f.field, expected, actual });
+            }
+        }
+    }
+    report.fields = fields;
+    report
+}
+
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+impl EvalReport {
+    pub fn to_markdown(&self, source: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# 正解データとの比較\n");
+        let _ = writeln!(out, "- 分類結果: {source}");
+        let _ = writeln!(out, "- 正解データ: {} 件（結果なし {} 件）", self.entries, self.missing.len());
+
+        let _ = writeln!(out, "\n## 項目ごとの正解率\n");
+        let _ = writeln!(out, "| 項目 | 正解 | 採点 | 正解率 |");
+        let _ = writeln!(out, "|---|---:|---:|---:|");
+        for f in &self.fields {
+            let _ = writeln!(out, "| {} | {} | {} | {:.1}% |", f.field, f.correct, f.labeled, f.rate() * 100.0);
+        }
+
+        if !self.misses.is_empty() {
+            let _ = writeln!(out, "\n## 不正解\n");
+            let _ = writeln!(out, "| ファイル | 項目 | 正解 | 結果 |");
+            let _ = writeln!(out, "|---|---|---|---|");
+            for m in &self.misses {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    cell(&m.file),
+                    m.field,
+                    cell(&m.expected),
+                    cell(&m.actual)
+                );
+            }
+        }
+        if !self.missing.is_empty() {
+            let _ = writeln!(out, "\n## 結果の無い写真\n");
+            for file in &self.missing {
+                let _ = writeln!(out, "- {file}");
+            }
+        }
+        out
+    }
+}
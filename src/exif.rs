@@ -0,0 +1,152 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// EXIF から読んだ撮影機材。読めなかった項目は空。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CameraInfo {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub make: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub model: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub serial: String,
+}
+
+impl CameraInfo {
+    pub fn is_empty(&self) -> bool {
+        self.make.is_empty() && self.model.is_empty() && self.serial.is_empty()
+    }
+
+    /// 機材を区別する名前。同じ機種が複数台あるときのためシリアル番号があれば付ける。
+    pub fn key(&self) -> String {
+        match (self.model.is_empty(), self.serial.is_empty()) {
+            (_, true) => self.model.clone(),
+            (true, false) => self.serial.clone(),
+            (false, false) => format!("{}#{}", self.model, self.serial),
+        }
+    }
+
+    /// 機種名・シリアル番号・key() のどれかが name と一致するか（大文字小文字は区別しない）
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.trim();
+        !name.is_empty()
+            && [&self.model, &self.serial, &self.key()]
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(name))
+    }
+}
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_BODY_SERIAL: u16 = 0xA431;
+/// DNG などが IFD0 に持つシリアル番号
+const TAG_CAMERA_SERIAL: u16 = 0xC62F;
+
+/// JPEG の APP1（Exif）から機材を読む。ファイルが開けなければ None、EXIF が無ければ空の CameraInfo。
+pub fn read_camera(path: &Path) -> Option<CameraInfo> {
+    let mut r = BufReader::new(File::open(crate::fs_ops::long_path(path)).ok()?);
+    let mut soi = [0u8; 2];
+    if r.read_exact(&mut soi).is_err() || soi != [0xFF, 0xD8] {
+        return Some(CameraInfo::default());
+    }
+    Some(exif_segment(&mut r).map(|tiff| parse_tiff(&tiff)).unwrap_or_default())
+}
+
+/// SOI の後のセグメントをたどり、"Exif\0\0" で始まる APP1 の TIFF 部分を返す
+fn exif_segment(r: &mut BufReader<File>) -> Option<Vec<u8>> {
+    loop {
+        let mut marker = [0u8; 2];
+        r.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 {
+            return None;
+        }
+        let mut len = [0u8; 2];
+        r.read_exact(&mut len).ok()?;
+        let len = u16::from_be_bytes(len) as usize;
+        if len < 2 {
+            return None;
+        }
+        if marker[1] == 0xE1 {
+            let mut data = vec![0u8; len - 2];
+            r.read_exact(&mut data).ok()?;
+            if let Some(tiff) = data.strip_prefix(b"Exif\0\0") {
+                return Some(tiff.to_vec());
+            }
+        } else {
+            r.seek(SeekFrom::Current(len as i64 - 2)).ok()?;
+        }
+    }
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    /// IFD のエントリを (タグ, エントリの位置) で返す
+    fn entries(&self, ifd: usize) -> Vec<(u16, usize)> {
+        let count = self.u16(ifd).unwrap_or(0) as usize;
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .filter_map(|at| Some((self.u16(at)?, at)))
+            .collect()
+    }
+
+    /// ASCII（型 2）の値。4 バイト以下は値欄に直接入っている。
+    fn ascii(&self, entry: usize) -> String {
+        let read = || {
+            if self.u16(entry + 2)? != 2 {
+                return None;
+            }
+            let count = self.u32(entry + 4)? as usize;
+            let start = if count <= 4 { entry + 8 } else { self.u32(entry + 8)? as usize };
+            let bytes = self.data.get(start..start.checked_add(count)?)?;
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            Some(String::from_utf8_lossy(&bytes[..end]).trim().to_string())
+        };
+        read().unwrap_or_default()
+    }
+}
+
+fn parse_tiff(data: &[u8]) -> CameraInfo {
+    let little_endian = match data.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return CameraInfo::default(),
+    };
+    let tiff = Tiff { data, little_endian };
+    let mut camera = CameraInfo::default();
+    let Some(ifd0) = tiff.u32(4) else { return camera };
+    for (tag, entry) in tiff.entries(ifd0 as usize) {
+        match tag {
+            TAG_MAKE => camera.make = tiff.ascii(entry),
+            TAG_MODEL => camera.model = tiff.ascii(entry),
+            TAG_CAMERA_SERIAL if camera.serial.is_empty() => camera.serial = tiff.ascii(entry),
+            TAG_EXIF_IFD => {
+                let Some(exif_ifd) = tiff.u32(entry + 8) else { continue };
+                for (tag, entry) in tiff.entries(exif_ifd as usize) {
+                    if tag == TAG_BODY_SERIAL {
+                        camera.serial = tiff.ascii(entry);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    camera
+}
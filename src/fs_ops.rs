@@ -1,9 +1,245 @@
-use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use crate::domain::GroupRecords;
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::error::{Error, Result};
+use crate::tag::TagRecords;
+use unicode_normalization::UnicodeNormalization;
 
-const GROUP_FILE: &str = "photo-groups.json";
+pub(crate) const GROUP_FILE: &str = "photo-groups.json";
+pub(crate) const TAG_FILE: &str = "photo-tags.json";
+/// キャッシュ等の作業ファイルを置くフォルダ内ディレクトリ
+pub const STATE_DIR: &str = ".photo-tagger";
+/// バッチ完了ごとに追記するジャーナル。save_group_records で本体に畳み込んで削除する。
+pub(crate) const GROUP_JOURNAL_FILE: &str = "photo-groups.journal.jsonl";
+/// --sample の結果（.photo-tagger/ 内。photo-groups.json とは別に毎回上書き）
+pub const SAMPLE_GROUP_FILE: &str = "photo-groups.sample.json";
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    file: String,
+    #[serde(flatten)]
+    record: GroupRecord,
+}
+
+/// `tag --move` が作った振り分け先フォルダに置く目印。中身は元のタグ（フォルダ名はサニタイズ済みのため）。
+pub const SORTED_MARKER_FILE: &str = ".photo-tagger-sorted";
+
+pub fn state_dir(base: &Path) -> PathBuf {
+    base.join(STATE_DIR)
+}
+
+/// dir をツールが作った振り分け先として記録する。既に目印があれば書き換えない。
+pub fn mark_sorted_dir(dir: &Path, tag: &str) -> Result<()> {
+    let path = dir.join(SORTED_MARKER_FILE);
+    if long_path(&path).exists() {
+        return Ok(());
+    }
+    std::fs::write(long_path(&path), tag).map_err(|e| Error::io(path, e))
+}
+
+/// ツールが作った振り分け先フォルダなら、振り分けに使ったタグを返す
+pub fn sorted_dir_tag(dir: &Path) -> Option<String> {
+    let tag = std::fs::read_to_string(long_path(&dir.join(SORTED_MARKER_FILE))).ok()?;
+    Some(tag.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// base の下のすべてのサブフォルダ（隠しフォルダとその中を除く）を名前順で。
+/// 振り分け先は [sort] layout によって何階層にもなるため、目印や写真を探すときに使う。
+pub fn nested_subdirs(base: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::fs::read_dir(long_path(base))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| base.join(e.file_name()))
+        .collect();
+    dirs.sort();
+    dirs.into_iter().flat_map(|dir| std::iter::once(dir.clone()).chain(nested_subdirs(&dir))).collect()
+}
+
+/// 作成するフォルダ名の上限（文字数）。NAS の深い階層でもパス長に余裕を残す。
+pub const MAX_DIR_NAME_CHARS: usize = 64;
+
+/// 64 ビット FNV-1a（識別用の短いハッシュ。暗号用途ではない）
+pub(crate) fn fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// 黒板やカテゴリ名から作るフォルダ名を Windows / macOS / Linux で安全な名前にする。
+/// 禁止文字・制御文字は `_` に、末尾の空白とピリオドは削り、予約名（CON, COM1 など）には `_` を付ける。
+/// MAX_DIR_NAME_CHARS を超える名前は切り詰め、元の名前のハッシュを付けて衝突を避ける。
+pub fn sanitize_dir_name(name: &str) -> String {
+    let mut out: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    out.truncate(out.trim_end_matches([' ', '.']).len());
+    if out.is_empty() {
+        return "_".into();
+    }
+
+    let stem = out.split('.').next().unwrap_or("").to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        // 拡張子付き（CON.txt）も予約名なので、拡張子の前に付ける
+        out.insert(stem.len(), '_');
+    }
+
+    if out.chars().count() > MAX_DIR_NAME_CHARS {
+        let suffix = format!("~{:06x}", fnv1a64(name.as_bytes()) & 0xff_ffff);
+        let keep = MAX_DIR_NAME_CHARS - suffix.len();
+        out = out.chars().take(keep).collect::<String>().trim_end_matches([' ', '.']).to_string() + &suffix;
+    }
+    out
+}
+
+/// Windows で MAX_PATH（260 文字）を超える NAS の深い階層も扱えるよう、`\\?\` 付きの拡張パスにする。
+/// ファイル操作の呼び出しにだけ使い、表示やエラーには元のパスを使う。Windows 以外ではそのまま返す。
+pub fn long_path(p: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let abs = std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        match abs.to_str().and_then(windows_extended_path) {
+            Some(s) => PathBuf::from(s),
+            None => abs,
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        p.to_path_buf()
+    }
+}
+
+/// `C:\a\..\b` → `\\?\C:\b`、`\\server\share\x` → `\\?\UNC\server\share\x`。
+/// 拡張パスでは `/` や `..` が解釈されないため、ここで字句的に解決する。
+/// 既に拡張パスならそのまま、相対パスなど解釈できない形式は None。
+pub fn windows_extended_path(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") {
+        return Some(path.to_string());
+    }
+    let s = path.replace('/', "\\");
+    let (mut out, rest) = if let Some(unc) = s.strip_prefix(r"\\") {
+        let mut parts = unc.splitn(3, '\\');
+        let server = parts.next().filter(|p| !p.is_empty())?;
+        let share = parts.next().filter(|p| !p.is_empty())?;
+        (format!(r"\\?\UNC\{server}\{share}"), parts.next().unwrap_or(""))
+    } else {
+        let b = s.as_bytes();
+        if b.len() < 3 || !b[0].is_ascii_alphabetic() || b[1] != b':' || b[2] != b'\\' {
+            return None;
+        }
+        (format!(r"\\?\{}", &s[..2]), &s[3..])
+    };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in rest.split('\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    if parts.is_empty() && !out.contains(r"\UNC\") {
+        out.push('\\');
+    }
+    for p in parts {
+        out.push('\\');
+        out.push_str(p);
+    }
+    Some(out)
+}
+
+/// レコードのキー。macOS のファイル名（NFD）と Windows で保存したレコード（NFC）が一致するよう NFC に揃える。
+pub fn record_key(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// 画像パスのファイル名からレコードのキーを作る。
+/// UTF-8 でない名前（Shift_JIS のまま展開した ZIP など）は置換文字入りのキーにすると元のファイルに戻れないため None。
+pub fn image_key(p: &Path) -> Option<String> {
+    p.file_name()?.to_str().map(record_key)
+}
+
+/// ファイル名が UTF-8 でない画像。レコードに記録できないので分類の対象から外す。
+pub fn non_utf8_images(images: &[PathBuf]) -> Vec<&Path> {
+    images
+        .iter()
+        .filter(|p| image_key(p).is_none())
+        .map(PathBuf::as_path)
+        .collect()
+}
+
+/// Windows ではファイル名の大文字小文字を区別しない
+pub(crate) const FOLD_CASE: bool = cfg!(windows);
+
+/// 照合用のキー。record_key で NFC に揃え、fold_case なら小文字にする
+fn match_key(name: &str, fold_case: bool) -> String {
+    let key = record_key(name);
+    if fold_case {
+        key.to_lowercase()
+    } else {
+        key
+    }
+}
+
+/// 照合用のキー → 保存されているキーの索引。レコードやファイル名のマップを読むたびに 1 回作り、
+/// 写真ごとの照合はこれで引く（Windows では大文字小文字だけ違う名前も同じレコードに当たる）。
+#[derive(Debug, Clone, Default)]
+pub struct KeyIndex {
+    fold_case: bool,
+    keys: HashMap<String, String>,
+}
+
+impl KeyIndex {
+    pub fn new<'a>(keys: impl IntoIterator<Item = &'a String>) -> Self {
+        Self::with_fold_case(keys, FOLD_CASE)
+    }
+
+    pub(crate) fn with_fold_case<'a>(keys: impl IntoIterator<Item = &'a String>, fold_case: bool) -> Self {
+        let mut index = HashMap::new();
+        for key in keys {
+            // 畳むと重なるキーはキー順で先のものに当てる
+            index.entry(match_key(key, fold_case)).or_insert_with(|| key.clone());
+        }
+        KeyIndex { fold_case, keys: index }
+    }
+
+    /// records から name のレコードを、保存されているキーと一緒に引く。完全一致（NFC）を優先する。
+    /// 索引は records のキーから作ったものを使う。
+    pub fn find<'a, V>(&self, records: &'a BTreeMap<String, V>, name: &str) -> Option<(&'a String, &'a V)> {
+        let key = record_key(name);
+        if let Some(hit) = records.get_key_value(&key) {
+            return Some(hit);
+        }
+        records.get_key_value(self.keys.get(&match_key(&key, self.fold_case))?)
+    }
+
+    /// find のファイル名 → 値のマップ（撮影時刻・ハッシュ・撮影機材）版
+    pub fn get<'a, V>(&self, map: &'a HashMap<String, V>, name: &str) -> Option<&'a V> {
+        let key = record_key(name);
+        if let Some(hit) = map.get(&key) {
+            return Some(hit);
+        }
+        map.get(self.keys.get(&match_key(&key, self.fold_case))?)
+    }
+}
+
+/// 読み込んだレコードのキーを NFC に揃える（他の OS で保存したファイルも同じキーで引けるように）
+fn normalize_keys<V>(records: BTreeMap<String, V>) -> BTreeMap<String, V> {
+    records.into_iter().map(|(k, v)| (record_key(&k), v)).collect()
+}
 
 pub fn is_image(p: &Path) -> bool {
     matches!(
@@ -15,33 +251,344 @@ pub fn is_image(p: &Path) -> bool {
     )
 }
 
-pub fn load_group_records(base: &Path) -> GroupRecords {
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(long_path(path)) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::io(path, e)),
+    }
+}
+
+/// photo-groups.json を読み、未圧縮のジャーナルがあれば上書き適用する。
+/// ファイルが無ければ空、本体が壊れていればエラー。
+pub fn load_group_records(base: &Path) -> Result<GroupRecords> {
     let path = base.join(GROUP_FILE);
-    std::fs::read_to_string(&path)
-        .ok()
-        .and_then(|s| serde_json::from_str(&s).ok())
-        .unwrap_or_default()
+    let mut records = match read_optional(&path)? {
+        Some(s) => normalize_keys(
+            serde_json::from_str(&s).map_err(|source| Error::CorruptRecords { path, source })?,
+        ),
+        None => GroupRecords::default(),
+    };
+
+    if let Some(journal) = read_optional(&base.join(GROUP_JOURNAL_FILE))? {
+        for line in journal.lines().filter(|l| !l.trim().is_empty()) {
+            // 書き込み途中で落ちた末尾行などは読み飛ばす（その写真は再分類される）
+            if let Ok(entry) = serde_json::from_str::<JournalEntry>(line) {
+                records.insert(record_key(&entry.file), entry.record);
+            }
+        }
+    }
+    Ok(records)
 }
 
+/// photo-groups.json / photo-tags.json の書き方。どちらもキー（ファイル名）順。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordFormat {
+    /// 字下げした JSON（従来の形式）
+    #[default]
+    Pretty,
+    /// 1 レコード 1 行の JSON。Git の差分が写真単位になる
+    Compact,
+}
+
+impl RecordFormat {
+    /// 既存ファイルの書き方（無ければ Pretty）。形式を指定しない保存はこれに合わせる。
+    pub fn detect(path: &Path) -> Self {
+        let mut head = [0u8; 3];
+        let read = std::fs::File::open(long_path(path)).and_then(|mut f| std::io::Read::read_exact(&mut f, &mut head));
+        match read {
+            Ok(()) if head == *b"{\n\"" => RecordFormat::Compact,
+            _ => RecordFormat::Pretty,
+        }
+    }
+}
+
+fn records_json<T: Serialize>(records: &BTreeMap<String, T>, format: RecordFormat, what: &str) -> Result<String> {
+    let err = |e| Error::Parse { message: format!("Failed to serialize {what}"), source: Some(e) };
+    match format {
+        RecordFormat::Pretty => serde_json::to_string_pretty(records).map_err(err),
+        RecordFormat::Compact => {
+            let mut lines = Vec::with_capacity(records.len());
+            for (key, rec) in records {
+                let key = serde_json::to_string(key).map_err(err)?;
+                let rec = serde_json::to_string(rec).map_err(err)?;
+                lines.push(format!("{key}:{rec}"));
+            }
+            if lines.is_empty() {
+                return Ok("{}".to_string());
+            }
+            Ok(format!("{{\n{}\n}}\n", lines.join(",\n")))
+        }
+    }
+}
+
+/// 同じフォルダの一時ファイルに書いてから置き換える。書き込み中に落ちても元のファイルは壊れない。
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(long_path(&tmp), contents).map_err(|e| Error::io(&tmp, e))?;
+    std::fs::rename(long_path(&tmp), long_path(path)).map_err(|e| Error::io(path, e))
+}
+
+/// 分類の途中で photo-groups.json にジャーナルを畳み込む間隔（秒）
+pub const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 300;
+
+/// 全レコードを photo-groups.json に書き出し、ジャーナルを削除する（既存ファイルと同じ形式）
 pub fn save_group_records(base: &Path, records: &GroupRecords) -> Result<()> {
+    save_group_records_as(base, records, RecordFormat::detect(&base.join(GROUP_FILE)))
+}
+
+pub fn save_group_records_as(base: &Path, records: &GroupRecords, format: RecordFormat) -> Result<()> {
     let path = base.join(GROUP_FILE);
-    let json =
-        serde_json::to_string_pretty(records).context("Failed to serialize group records")?;
-    std::fs::write(&path, json)
-        .with_context(|| format!("Failed to write {}", path.display()))?;
-    Ok(())
+    let json = records_json(records, format, "group records")?;
+    write_atomic(&path, json.as_bytes())?;
+
+    let journal = base.join(GROUP_JOURNAL_FILE);
+    match std::fs::remove_file(long_path(&journal)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::io(journal, e)),
+        _ => Ok(()),
+    }
+}
+
+/// --sample の結果を .photo-tagger/photo-groups.sample.json に書き、そのパスを返す
+pub fn save_sample_records(base: &Path, records: &GroupRecords) -> Result<PathBuf> {
+    let dir = state_dir(base);
+    std::fs::create_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+    let path = dir.join(SAMPLE_GROUP_FILE);
+    let json = serde_json::to_string_pretty(records).map_err(|e| Error::Parse {
+        message: "Failed to serialize sample records".into(),
+        source: Some(e),
+    })?;
+    std::fs::write(long_path(&path), json).map_err(|e| Error::io(&path, e))?;
+    Ok(path)
+}
+
+/// photo-tags.json を読む。無ければ空、壊れていればエラー。
+pub fn load_tag_records(base: &Path) -> Result<TagRecords> {
+    let path = base.join(TAG_FILE);
+    match read_optional(&path)? {
+        Some(s) => serde_json::from_str(&s)
+            .map(normalize_keys)
+            .map_err(|source| Error::CorruptRecords { path, source }),
+        None => Ok(TagRecords::default()),
+    }
+}
+
+/// 既存ファイルと同じ形式で photo-tags.json に書き出す
+pub fn save_tag_records(base: &Path, records: &TagRecords) -> Result<()> {
+    save_tag_records_as(base, records, RecordFormat::detect(&base.join(TAG_FILE)))
+}
+
+pub fn save_tag_records_as(base: &Path, records: &TagRecords, format: RecordFormat) -> Result<()> {
+    let path = base.join(TAG_FILE);
+    let json = records_json(records, format, "tag records")?;
+    write_atomic(&path, json.as_bytes())
+}
+
+/// 分類済みレコードをジャーナルに追記する（全体を書き直さない）
+pub fn append_group_journal<'a>(
+    base: &Path,
+    entries: impl IntoIterator<Item = (&'a String, &'a GroupRecord)>,
+) -> Result<()> {
+    let lines: Vec<JournalEntry> = entries
+        .into_iter()
+        .map(|(file, record)| JournalEntry { file: file.clone(), record: record.clone() })
+        .collect();
+    append_jsonl(&base.join(GROUP_JOURNAL_FILE), &lines)
+}
+
+/// 1 要素 1 行の JSON として追記する。まとめて 1 回で、ファイルをロックして書き込む。
+pub fn append_jsonl<T: Serialize>(path: &Path, items: &[T]) -> Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let mut buf = String::new();
+    for item in items {
+        let line = serde_json::to_string(item).map_err(|e| Error::Parse {
+            message: format!("Failed to serialize {}", path.display()),
+            source: Some(e),
+        })?;
+        buf.push_str(&line);
+        buf.push('\n');
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(long_path(path))
+        .map_err(|e| Error::io(path, e))?;
+    // 同じフォルダへの並行実行（別プロセス）の追記と行が混ざらないよう、書き終えるまで排他ロックを取る。
+    // ロックに対応しないファイルシステム（一部の NAS）ではロック無しで書く。ロックは file を閉じると外れる。
+    match file.lock() {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {}
+        Err(e) => return Err(Error::io(path, e)),
+    }
+    file.write_all(buf.as_bytes()).map_err(|e| Error::io(path, e))
 }
 
 /// Collect image files directly under dir only (NOT recursive)
 pub fn collect_images_flat(dir: &Path) -> Vec<PathBuf> {
-    let mut out = Vec::new();
-    let Ok(entries) = std::fs::read_dir(dir) else { return out };
-    for entry in entries.flatten() {
-        let p = entry.path();
-        if !p.is_dir() && is_image(&p) {
-            out.push(p);
-        }
-    }
+    let mut out: Vec<PathBuf> = image_dir_entries(dir)
+        .iter()
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
     out.sort();
     out
 }
+
+/// 走査で見つけた画像と、そのときに読んだサイズ・更新時刻（読めなければ None）
+#[derive(Debug, Clone)]
+pub struct ImageEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: Option<i64>,
+}
+
+/// collect_images_flat と同じ画像を、サイズ・更新時刻付きで集める。
+/// ディレクトリは 1 回だけ読み、メタデータは DirEntry から取る（Windows では列挙結果に含まれるので
+/// 追加の stat が要らない）。それ以外では workers 本のスレッドで並列に読む。
+pub fn collect_image_entries(dir: &Path, workers: usize) -> Vec<ImageEntry> {
+    let entries = image_dir_entries(dir);
+    if entries.is_empty() {
+        return Vec::new();
+    }
+    let read = |entry: &std::fs::DirEntry| {
+        let md = entry.metadata().ok();
+        ImageEntry {
+            path: dir.join(entry.file_name()),
+            size: md.as_ref().map_or(0, |m| m.len()),
+            mtime: md
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64),
+        }
+    };
+    let workers = if cfg!(windows) { 1 } else { workers.max(1) };
+    let mut out: Vec<ImageEntry> = if workers == 1 {
+        entries.iter().map(read).collect()
+    } else {
+        let chunk_size = entries.len().div_ceil(workers);
+        std::thread::scope(|s| {
+            let handles: Vec<_> = entries
+                .chunks(chunk_size)
+                .map(|chunk| s.spawn(move || chunk.iter().map(read).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("image scan thread panicked"))
+                .collect()
+        })
+    };
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    out
+}
+
+/// dir 直下の画像ファイルの DirEntry。種別は DirEntry から判定し、ファイルごとの stat はしない。
+fn image_dir_entries(dir: &Path) -> Vec<std::fs::DirEntry> {
+    let Ok(entries) = std::fs::read_dir(long_path(dir)) else { return Vec::new() };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let is_dir = match entry.file_type() {
+                // シンボリックリンクはリンク先で判定する
+                Ok(t) if t.is_symlink() => entry.path().is_dir(),
+                Ok(t) => t.is_dir(),
+                Err(_) => entry.path().is_dir(),
+            };
+            !is_dir && is_image(Path::new(&entry.file_name()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extended_path_of_unc_share() {
+        assert_eq!(windows_extended_path(r"\\nas\photos\2026\a.jpg").as_deref(), Some(r"\\?\UNC\nas\photos\2026\a.jpg"));
+        assert_eq!(windows_extended_path(r"\\nas\photos").as_deref(), Some(r"\\?\UNC\nas\photos"));
+        assert_eq!(windows_extended_path("//nas/photos/x/../a.jpg").as_deref(), Some(r"\\?\UNC\nas\photos\a.jpg"));
+        assert_eq!(windows_extended_path(r"\\nas"), None);
+        assert_eq!(windows_extended_path(r"\\\photos"), None);
+    }
+
+    #[test]
+    fn extended_path_of_drive_path() {
+        assert_eq!(windows_extended_path(r"C:\a\..\b\.\c.jpg").as_deref(), Some(r"\\?\C:\b\c.jpg"));
+        assert_eq!(windows_extended_path("d:/photos/a.jpg").as_deref(), Some(r"\\?\d:\photos\a.jpg"));
+        assert_eq!(windows_extended_path(r"C:\").as_deref(), Some(r"\\?\C:\"));
+        assert_eq!(windows_extended_path(r"C:\..").as_deref(), Some(r"\\?\C:\"));
+        assert_eq!(windows_extended_path("C:relative"), None);
+        assert_eq!(windows_extended_path(r"photos\a.jpg"), None);
+    }
+
+    #[test]
+    fn sanitize_replaces_forbidden_characters_and_trims() {
+        assert_eq!(sanitize_dir_name("舗装工 No.3"), "舗装工 No.3");
+        assert_eq!(sanitize_dir_name(r#"a<b>c:d"e/f\g|h?i*j"#), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(sanitize_dir_name("tab\there\n"), "tab_here");
+        assert_eq!(sanitize_dir_name("  完成. . "), "完成");
+        assert_eq!(sanitize_dir_name(""), "_");
+        assert_eq!(sanitize_dir_name(" ... "), "_");
+    }
+
+    #[test]
+    fn sanitize_escapes_reserved_names() {
+        assert_eq!(sanitize_dir_name("CON"), "CON_");
+        assert_eq!(sanitize_dir_name("nul"), "nul_");
+        assert_eq!(sanitize_dir_name("com1"), "com1_");
+        assert_eq!(sanitize_dir_name("LPT9.txt"), "LPT9_.txt");
+        assert_eq!(sanitize_dir_name("CONSOLE"), "CONSOLE");
+        assert_eq!(sanitize_dir_name("COM10"), "COM10");
+        assert_eq!(sanitize_dir_name("COMX"), "COMX");
+    }
+
+    #[test]
+    fn sanitize_truncates_long_names_with_a_hash() {
+        let long = "測".repeat(100);
+        let out = sanitize_dir_name(&long);
+        assert_eq!(out.chars().count(), MAX_DIR_NAME_CHARS);
+        assert!(out.starts_with(&"測".repeat(10)));
+        assert_ne!(out, sanitize_dir_name(&format!("{long}2")), "different names keep different folders");
+        assert_eq!(out, sanitize_dir_name(&long), "stable across runs");
+        // 切り詰めた位置に空白・ピリオドが来ても末尾に残さない
+        let dotted = format!("{}. {}", "a".repeat(MAX_DIR_NAME_CHARS - 9), "b".repeat(20));
+        assert!(sanitize_dir_name(&dotted).starts_with(&format!("{}~", "a".repeat(MAX_DIR_NAME_CHARS - 9))));
+        assert_eq!(sanitize_dir_name(&"a".repeat(MAX_DIR_NAME_CHARS)), "a".repeat(MAX_DIR_NAME_CHARS));
+    }
+
+    #[test]
+    fn key_index_normalizes_the_name() {
+        let records: BTreeMap<String, u32> = [(record_key("ガードレール.jpg"), 1)].into_iter().collect();
+        let nfd: String = "ガードレール.jpg".nfd().collect();
+        assert_ne!(nfd, "ガードレール.jpg");
+        let index = KeyIndex::new(records.keys());
+        assert_eq!(index.find(&records, &nfd), Some((&"ガードレール.jpg".to_string(), &1)));
+    }
+
+    #[test]
+    fn key_index_folds_case_only_when_asked() {
+        let records: BTreeMap<String, u32> = [("IMG_0001.JPG".to_string(), 1)].into_iter().collect();
+        let folded = KeyIndex::with_fold_case(records.keys(), true);
+        assert_eq!(folded.find(&records, "img_0001.jpg").map(|(k, _)| k.as_str()), Some("IMG_0001.JPG"));
+        assert_eq!(KeyIndex::with_fold_case(records.keys(), false).find(&records, "img_0001.jpg"), None);
+
+        let times: HashMap<String, i64> = [("img_0001.jpg".to_string(), 42)].into_iter().collect();
+        assert_eq!(KeyIndex::with_fold_case(times.keys(), true).get(&times, "IMG_0001.JPG"), Some(&42));
+
+        // 大文字小文字だけ違うキーが両方あれば完全一致を優先する
+        let both: BTreeMap<String, u32> = [("A.jpg".to_string(), 1), ("a.jpg".to_string(), 2)].into_iter().collect();
+        let index = KeyIndex::with_fold_case(both.keys(), true);
+        assert_eq!(index.find(&both, "a.jpg").map(|(_, v)| *v), Some(2));
+        assert_eq!(index.find(&both, "A.JPG").map(|(_, v)| *v), Some(1));
+    }
+
+    #[test]
+    fn extended_path_is_left_alone() {
+        for path in [r"\\?\C:\a\..\b", r"\\?\UNC\nas\photos\a.jpg"] {
+            assert_eq!(windows_extended_path(path).as_deref(), Some(path));
+        }
+    }
+}
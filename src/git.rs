@@ -0,0 +1,83 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::backup::BACKUP_DIR;
+use crate::error::{Error, Result};
+use crate::fs_ops::{long_path, state_dir, GROUP_FILE, STATE_DIR, TAG_FILE};
+use crate::trash::TRASH_DIR;
+
+/// `--git-commit` のコミットメッセージに書く実行結果
+#[derive(Debug, Clone, Default)]
+pub struct RunCommit {
+    /// "group" / "tag"
+    pub mode: String,
+    pub images: usize,
+    pub classified: usize,
+    pub errors: usize,
+    /// 実行時の設定（名前, 値）
+    pub params: Vec<(String, String)>,
+}
+
+impl RunCommit {
+    pub fn message(&self) -> String {
+        let mut out = format!(
+            "photo-tagger {}: {} classified, {} errors\n\n",
+            self.mode, self.classified, self.errors
+        );
+        let _ = writeln!(out, "mode: {}", self.mode);
+        let _ = writeln!(out, "images: {}", self.images);
+        let _ = writeln!(out, "classified: {}", self.classified);
+        let _ = writeln!(out, "errors: {}", self.errors);
+        for (name, value) in &self.params {
+            let _ = writeln!(out, "{name}: {value}");
+        }
+        out
+    }
+}
+
+fn git(base: &Path, args: &[&str]) -> Result<std::process::Output> {
+    Command::new("git")
+        .current_dir(long_path(base))
+        .args(args)
+        .output()
+        .map_err(|e| Error::Git { message: format!("cannot run git: {e}") })
+}
+
+fn git_ok(base: &Path, args: &[&str]) -> Result<()> {
+    let out = git(base, args)?;
+    if !out.status.success() {
+        return Err(Error::Git { message: String::from_utf8_lossy(&out.stderr).trim().to_string() });
+    }
+    Ok(())
+}
+
+/// レコードファイルと .photo-tagger/（バックアップ・ゴミ箱を除く）をステージしてコミットし、
+/// コミットのハッシュを返す。変更が無ければコミットせず None。
+/// ほかにステージ済みの変更があっても、このパス以外はコミットに含めない。
+pub fn commit_metadata(base: &Path, run: &RunCommit) -> Result<Option<String>> {
+    let mut paths: Vec<String> = [GROUP_FILE, TAG_FILE]
+        .into_iter()
+        .filter(|f| long_path(&base.join(f)).exists())
+        .map(str::to_string)
+        .collect();
+    if long_path(&state_dir(base)).is_dir() {
+        paths.push(STATE_DIR.to_string());
+        paths.push(format!(":(exclude){STATE_DIR}/{BACKUP_DIR}"));
+        paths.push(format!(":(exclude){STATE_DIR}/{TRASH_DIR}"));
+    }
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+
+    git_ok(base, &[&["add", "-A", "--"], paths.as_slice()].concat())?;
+    let staged = git(base, &[&["diff", "--cached", "--quiet", "--"], paths.as_slice()].concat())?;
+    if staged.status.success() {
+        return Ok(None);
+    }
+    let message = run.message();
+    git_ok(base, &[&["commit", "--quiet", "-m", message.as_str(), "--"], paths.as_slice()].concat())?;
+    let head = git(base, &["rev-parse", "--short", "HEAD"])?;
+    Ok(Some(String::from_utf8_lossy(&head.stdout).trim().to_string()))
+}
@@ -0,0 +1,581 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::domain::{GroupRecord, GroupRecords, ReviewStatus};
+use crate::exif::CameraInfo;
+use crate::fs_ops::KeyIndex;
+use crate::meta;
+use crate::timestamp::{ClockOffsets, TimestampParser};
+
+pub const GROUP_GAP_SECS: i64 = 5 * 60;
+
+/// 撮影時刻順（時刻不明は末尾、同時刻はファイル名順）に並べたレコード列。
+/// グループ付与・取付道路の伝播はこの 1 回のソート結果を共有し、機械・測点ごとに再ソートしない。
+fn time_order(records: &GroupRecords) -> Vec<(&String, &GroupRecord)> {
+    let mut order: Vec<(&String, &GroupRecord)> = records.iter().collect();
+    // BTreeMap の反復はファイル名順なので、安定ソートで同時刻の順序が保たれる
+    order.sort_by_key(|(_, rec)| rec.captured_at.unwrap_or(i64::MAX));
+    order
+}
+
+fn gap_secs(prev: &GroupRecord, curr: &GroupRecord) -> i64 {
+    match (prev.captured_at, curr.captured_at) {
+        (Some(p), Some(c)) if p != i64::MAX && c != i64::MAX => (c - p).abs(),
+        _ => 0,
+    }
+}
+
+struct Segment<'a> {
+    head_ts: i64,
+    machine_id: &'a str,
+    files: Vec<&'a String>,
+}
+
+/// 時刻順のレコード列を machine_id ごとのセグメントに分ける（生成順で返す）。
+/// 間隔が GROUP_GAP_SECS を超えるか、取付道路の有無が変わったら別セグメント。
+fn segments<'a>(order: impl IntoIterator<Item = (&'a String, &'a GroupRecord)>) -> Vec<Segment<'a>> {
+    // machine_id → (直前のレコード, 現在のセグメント番号)
+    let mut open: HashMap<&str, (&GroupRecord, usize)> = HashMap::new();
+    let mut out: Vec<Segment> = Vec::new();
+
+    for (fname, rec) in order {
+        let machine_id = rec.machine_id.as_str();
+        let seg = match open.get(machine_id) {
+            Some(&(prev, seg))
+                if gap_secs(prev, rec) <= GROUP_GAP_SECS
+                    && has_attachment_hint(prev) == has_attachment_hint(rec) =>
+            {
+                seg
+            }
+            _ => {
+                out.push(Segment {
+                    head_ts: rec.captured_at.unwrap_or(i64::MAX),
+                    machine_id,
+                    files: Vec::new(),
+                });
+                out.len() - 1
+            }
+        };
+        out[seg].files.push(fname);
+        open.insert(machine_id, (rec, seg));
+    }
+    out
+}
+
+/// 先頭時刻順（同時刻は machine_id 順、同一機械内は生成順）に並べる
+fn sort_segments(segs: &mut [Segment]) {
+    // 生成順は同一機械内では時刻順なので、安定ソートで十分
+    segs.sort_by(|a, b| a.head_ts.cmp(&b.head_ts).then(a.machine_id.cmp(b.machine_id)));
+}
+
+/// machine_id ごとに撮影時刻で区切ってグループ番号を振り直す
+/// 番号は各グループ先頭の撮影時刻順（同時刻は machine_id 順）に 1 から振る。
+pub fn assign_groups(records: &mut GroupRecords) {
+    let mut segs = segments(time_order(records));
+    sort_segments(&mut segs);
+
+    let assigned: HashMap<String, u32> = segs
+        .iter()
+        .enumerate()
+        .flat_map(|(rank, seg)| seg.files.iter().map(move |f| ((*f).clone(), (rank + 1) as u32)))
+        .collect();
+    for (fname, rec) in records.iter_mut() {
+        rec.group = assigned.get(fname).copied().unwrap_or(0);
+    }
+    carry_group_reviews(records);
+}
+
+/// (メモ, 状態) → (写真数, 最初に現れた位置)
+type ReviewVotes<'a> = HashMap<(&'a str, ReviewStatus), (usize, usize)>;
+
+/// グループのメモ・確認状態を同じグループの全写真にそろえる。
+/// 写真は前のグループの値を持っているので、値を持つ写真に最も多い組（同数なら撮影時刻の早いほう）を採り、
+/// 後から加わった写真（値なし）や、区切り直しで別のグループから合流した写真にも入れる。
+fn carry_group_reviews(records: &mut GroupRecords) {
+    let reviewed = |rec: &GroupRecord| !rec.group_notes.is_empty() || !rec.group_status.is_unreviewed();
+    if !records.values().any(reviewed) {
+        return;
+    }
+    let mut votes: HashMap<u32, ReviewVotes> = HashMap::new();
+    for (seen, (_, rec)) in time_order(records).into_iter().enumerate() {
+        if rec.group == 0 || !reviewed(rec) {
+            continue;
+        }
+        let vote = votes
+            .entry(rec.group)
+            .or_default()
+            .entry((rec.group_notes.as_str(), rec.group_status))
+            .or_insert((0, seen));
+        vote.0 += 1;
+    }
+    let chosen: HashMap<u32, (String, ReviewStatus)> = votes
+        .into_iter()
+        .filter_map(|(group, votes)| {
+            let ((notes, status), _) =
+                votes.into_iter().max_by(|a, b| a.1 .0.cmp(&b.1 .0).then(b.1 .1.cmp(&a.1 .1)))?;
+            Some((group, (notes.to_string(), status)))
+        })
+        .collect();
+    for rec in records.values_mut() {
+        if let Some((notes, status)) = chosen.get(&rec.group) {
+            rec.group_notes.clone_from(notes);
+            rec.group_status = *status;
+        }
+    }
+}
+
+/// machine_id → 所属写真の (ファイル名, 撮影時刻, 取付道路か)
+type MachineSignature<'a> = BTreeMap<&'a str, Vec<(&'a str, Option<i64>, bool)>>;
+
+fn machine_signature(records: &GroupRecords) -> MachineSignature<'_> {
+    let mut out: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+    for (fname, rec) in records {
+        out.entry(rec.machine_id.as_str())
+            .or_default()
+            .push((fname.as_str(), rec.captured_at, has_attachment_hint(rec)));
+    }
+    out
+}
+
+/// 前回保存時のレコード（previous）から写真の構成が変わった machine_id だけを区切り直す。
+/// 変化の無い機械のグループ番号はそのまま残し、区切り直したグループも前回と同じ写真集合なら
+/// 前回の番号を引き継ぐ。新しいグループには既存の最大番号の次から振る（欠番は詰めない）。
+/// 前回レコードが無い・番号未付与のものがある場合は assign_groups と同じ全体再計算になる。
+pub fn assign_groups_incremental(records: &mut GroupRecords, previous: &GroupRecords) {
+    if previous.is_empty() || previous.values().any(|r| r.group == 0) {
+        assign_groups(records);
+        return;
+    }
+
+    let before = machine_signature(previous);
+    let after = machine_signature(records);
+    let affected: BTreeSet<&str> = after
+        .iter()
+        .filter(|(machine_id, sig)| before.get(*machine_id) != Some(*sig))
+        .map(|(machine_id, _)| *machine_id)
+        .collect();
+
+    let mut assigned: HashMap<String, u32> = HashMap::new();
+    for (fname, rec) in records.iter() {
+        if !affected.contains(rec.machine_id.as_str()) {
+            assigned.insert(fname.clone(), previous[fname].group);
+        }
+    }
+
+    let kept: BTreeSet<u32> = assigned.values().copied().collect();
+    let mut old_groups: BTreeMap<u32, BTreeSet<&str>> = BTreeMap::new();
+    for (fname, rec) in previous {
+        old_groups.entry(rec.group).or_default().insert(fname.as_str());
+    }
+    let mut next_group = old_groups.keys().chain(kept.iter()).max().copied().unwrap_or(0) + 1;
+    let old_by_files: HashMap<BTreeSet<&str>, u32> =
+        old_groups.into_iter().map(|(no, files)| (files, no)).collect();
+
+    let order = time_order(records)
+        .into_iter()
+        .filter(|(_, rec)| affected.contains(rec.machine_id.as_str()));
+    let mut segs = segments(order);
+    sort_segments(&mut segs);
+
+    let mut reused: BTreeSet<u32> = BTreeSet::new();
+    for seg in &segs {
+        let files: BTreeSet<&str> = seg.files.iter().map(|f| f.as_str()).collect();
+        let previous_no = old_by_files
+            .get(&files)
+            .copied()
+            .filter(|no| !kept.contains(no) && !reused.contains(no));
+        let group = previous_no.unwrap_or_else(|| {
+            next_group += 1;
+            next_group - 1
+        });
+        reused.insert(group);
+        for f in &seg.files {
+            assigned.insert((*f).clone(), group);
+        }
+    }
+
+    for (fname, rec) in records.iter_mut() {
+        rec.group = assigned.get(fname).copied().unwrap_or(0);
+    }
+    carry_group_reviews(records);
+}
+
+fn has_attachment_hint(rec: &GroupRecord) -> bool {
+    rec.machine_id.contains("取付")
+        || rec.detected_text.contains("取付")
+}
+
+pub(crate) fn extract_no(text: &str) -> Option<String> {
+    for marker in ["No.", "No ", "NO.", "NO "] {
+        if let Some(pos) = text.find(marker) {
+            let rest = &text[pos + marker.len()..];
+            let digits: String = rest
+                .chars()
+                .skip_while(|c| !c.is_ascii_digit())
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if !digits.is_empty() {
+                return Some(format!("No.{}", digits));
+            }
+        }
+    }
+    None
+}
+
+fn normalize_machine_id(rec: &mut GroupRecord) {
+    let merged = format!("{} {}", rec.detected_text, rec.description);
+    if merged.contains("取付") {
+        if let Some(no) = extract_no(&merged).or_else(|| extract_no(&rec.machine_id)) {
+            rec.machine_id = format!("取付道路 {}", no);
+        }
+    }
+}
+
+/// ファイル名の日時（既定パターン）または更新時刻を撮影時刻として集める（キャッシュ無し）
+/// フォルダ単位で繰り返し実行する場合は meta::scan_capture_times を使う。
+pub fn collect_capture_times(images: &[PathBuf]) -> HashMap<String, i64> {
+    let scanned = meta::scan_images(images, &meta::MetaCache::new(), meta::DEFAULT_SCAN_WORKERS);
+    meta::capture_times(&scanned, &TimestampParser::default())
+}
+
+/// 写真を撮影時刻順に並べ、撮影の間隔が空いたところで batch_size 枚以下のバッチに区切る。
+/// 1 台分の写真（全景・証票など）が 2 つのバッチに分かれにくくなる。
+///
+/// 各バッチは batch_size の 2/3 から batch_size 枚の範囲で、次の写真との間隔が最も長い位置で切る
+/// （同じ長さなら多く入る方）。撮影時刻の無い写真は最後にファイル名順で batch_size 枚ずつ。
+pub fn time_batches(images: &[PathBuf], capture_times: &HashMap<String, i64>, batch_size: usize) -> Vec<Vec<PathBuf>> {
+    let batch_size = batch_size.max(1);
+    let time = |p: &PathBuf| crate::fs_ops::image_key(p).and_then(|k| capture_times.get(&k).copied());
+    let mut timed: Vec<(i64, &PathBuf)> = images.iter().filter_map(|p| Some((time(p)?, p))).collect();
+    timed.sort();
+    let mut untimed: Vec<&PathBuf> = images.iter().filter(|p| time(p).is_none()).collect();
+    untimed.sort();
+
+    let min_fill = (batch_size - batch_size / 3).max(1);
+    let mut batches = Vec::new();
+    let mut start = 0;
+    while start < timed.len() {
+        let end = if timed.len() - start <= batch_size {
+            timed.len()
+        } else {
+            // 切る位置 e: timed[e - 1] と timed[e] の間
+            (start + min_fill..=start + batch_size)
+                .max_by_key(|&e| (timed[e].0 - timed[e - 1].0, e))
+                .unwrap_or(start + batch_size)
+        };
+        batches.push(timed[start..end].iter().map(|(_, p)| (*p).clone()).collect());
+        start = end;
+    }
+    batches.extend(untimed.chunks(batch_size).map(|c| c.iter().map(|p| (*p).clone()).collect()));
+    batches
+}
+
+/// 撮影機材を記録し、--time-offset の補正を captured_at に反映する。
+/// 前回までに足した分（clock_offset）との差だけを足すので、何度実行しても二重にはならず、補正を外せば元に戻る。
+fn apply_clock_offsets(records: &mut GroupRecords, cameras: &HashMap<String, CameraInfo>, offsets: &ClockOffsets) {
+    let index = KeyIndex::new(cameras.keys());
+    for (fname, rec) in records.iter_mut() {
+        if let Some(camera) = index.get(cameras, fname) {
+            rec.camera = camera.clone();
+        }
+        let Some(ts) = rec.captured_at else { continue };
+        let offset = offsets.offset_for(&rec.camera);
+        if offset != rec.clock_offset {
+            rec.captured_at = Some(ts + offset - rec.clock_offset);
+            rec.clock_offset = offset;
+        }
+    }
+}
+
+fn fill_capture_times(records: &mut GroupRecords, capture_times: &HashMap<String, i64>) {
+    let index = KeyIndex::new(capture_times.keys());
+    for (fname, rec) in records.iter_mut() {
+        if !rec.locked {
+            normalize_machine_id(rec);
+        }
+        if rec.captured_at.is_none() {
+            if let Some(ts) = index.get(capture_times, fname) {
+                rec.captured_at = Some(*ts);
+            }
+        }
+    }
+}
+
+/// 撮影時刻の補完と machine_id の正規化（取付道路の伝播を含む）。ロック済みの machine_id は変えない。
+pub fn apply_capture_times(records: &mut GroupRecords, capture_times: &HashMap<String, i64>) {
+    fill_capture_times(records, capture_times);
+    propagate_attachment_by_time(records);
+}
+
+/// apply_capture_times に加えて撮影機材を記録し、機材ごとの時計の補正を反映する。cameras はファイル名 → 撮影機材。
+pub fn apply_capture_times_with_offsets(
+    records: &mut GroupRecords,
+    capture_times: &HashMap<String, i64>,
+    cameras: &HashMap<String, CameraInfo>,
+    offsets: &ClockOffsets,
+) {
+    fill_capture_times(records, capture_times);
+    apply_clock_offsets(records, cameras, offsets);
+    propagate_attachment_by_time(records);
+}
+
+/// ハッシュの無いレコード（今回分類した写真・旧版のレコード）に今の内容のハッシュを記録する。
+/// 再分類に失敗したレコードは前のハッシュのまま残し、次の実行でまた再分類する。
+pub fn apply_content_hashes(records: &mut GroupRecords, hashes: &HashMap<String, String>) {
+    let index = KeyIndex::new(hashes.keys());
+    for (fname, rec) in records.iter_mut() {
+        if let (true, Some(hash)) = (rec.content_hash.is_empty(), index.get(hashes, fname)) {
+            rec.content_hash = hash.clone();
+        }
+    }
+}
+
+/// 同じ測点番号（No.X）で時間的に連続する写真のうち 1 枚でも取付道路なら、
+/// その一続き全体を「取付道路 No.X」に揃える。
+fn propagate_attachment_by_time(records: &mut GroupRecords) {
+    let order = time_order(records);
+
+    // 測点 → 時刻順の一続き（chunk）の列
+    let mut by_no: BTreeMap<String, Vec<Vec<(&String, &GroupRecord)>>> = BTreeMap::new();
+    for &(fname, rec) in &order {
+        let Some(no) = extract_no(&rec.machine_id)
+            .or_else(|| extract_no(&rec.detected_text))
+            .or_else(|| extract_no(&rec.description))
+        else {
+            continue;
+        };
+        let chunks = by_no.entry(no).or_default();
+        match chunks.last_mut() {
+            Some(chunk) if gap_secs(chunk[chunk.len() - 1].1, rec) <= GROUP_GAP_SECS => {
+                chunk.push((fname, rec));
+            }
+            _ => chunks.push(vec![(fname, rec)]),
+        }
+    }
+
+    let mut updates: Vec<(String, String)> = Vec::new();
+    for (no, chunks) in &by_no {
+        for chunk in chunks {
+            if chunk.iter().any(|(_, rec)| has_attachment_hint(rec)) {
+                let machine_id = format!("取付道路 {}", no);
+                updates.extend(chunk.iter().map(|(fname, _)| ((*fname).clone(), machine_id.clone())));
+            }
+        }
+    }
+
+    for (fname, machine_id) in updates {
+        if let Some(rec) = records.get_mut(&fname).filter(|r| !r.locked) {
+            rec.machine_id = machine_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::synthetic_records;
+    use crate::domain::ReviewStatus;
+
+    /// 時刻順インデックスに書き換える前の assign_groups（機械ごとに並べ替えて区切り、先頭時刻で番号を詰める）
+    fn reference_assign_groups(records: &mut GroupRecords) {
+        let mut by_id: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (fname, rec) in records.iter() {
+            by_id.entry(rec.machine_id.clone()).or_default().push(fname.clone());
+        }
+
+        let mut segment_heads: Vec<(i64, String, u32)> = Vec::new();
+        let mut tmp_groups: HashMap<String, u32> = HashMap::new();
+        let mut next_tmp = 1u32;
+        for (machine_id, mut files) in by_id {
+            files.sort_by(|a, b| {
+                let ta = records[a].captured_at.unwrap_or(i64::MAX);
+                let tb = records[b].captured_at.unwrap_or(i64::MAX);
+                ta.cmp(&tb).then(a.cmp(b))
+            });
+            let mut current = next_tmp;
+            next_tmp += 1;
+            segment_heads.push((records[&files[0]].captured_at.unwrap_or(i64::MAX), machine_id.clone(), current));
+            tmp_groups.insert(files[0].clone(), current);
+            for pair in files.windows(2) {
+                let (prev, curr) = (&records[&pair[0]], &records[&pair[1]]);
+                let prev_ts = prev.captured_at.unwrap_or(i64::MAX);
+                let curr_ts = curr.captured_at.unwrap_or(i64::MAX);
+                let gap = if prev_ts == i64::MAX || curr_ts == i64::MAX { 0 } else { (curr_ts - prev_ts).abs() };
+                if gap > GROUP_GAP_SECS || has_attachment_hint(prev) != has_attachment_hint(curr) {
+                    current = next_tmp;
+                    next_tmp += 1;
+                    segment_heads.push((curr_ts, machine_id.clone(), current));
+                }
+                tmp_groups.insert(pair[1].clone(), current);
+            }
+        }
+
+        segment_heads.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        let compact: HashMap<u32, u32> =
+            segment_heads.iter().enumerate().map(|(idx, (_, _, tmp))| (*tmp, (idx + 1) as u32)).collect();
+        for (fname, rec) in records.iter_mut() {
+            rec.group = tmp_groups.get(fname).map(|tmp| compact[tmp]).unwrap_or(0);
+        }
+    }
+
+    fn record(machine_id: &str, detected_text: &str, captured_at: Option<i64>) -> GroupRecord {
+        GroupRecord {
+            role: "機械全景".into(),
+            machine_type: "タイヤローラー".into(),
+            machine_id: machine_id.into(),
+            group: 0,
+            has_board: false,
+            detected_text: detected_text.into(),
+            description: String::new(),
+            captured_at,
+            clock_offset: 0,
+            camera: CameraInfo::default(),
+            content_hash: String::new(),
+            caption: String::new(),
+            locked: false,
+            group_notes: String::new(),
+            group_status: ReviewStatus::Unreviewed,
+        }
+    }
+
+    /// 時刻の重複・欠落・i64::MAX・5 分前後の間隔・取付道路の混在を含む乱数レコード
+    fn random_records(seed: u64) -> GroupRecords {
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let mut next = move |n: u64| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % n
+        };
+        const IDS: [&str; 5] = ["M1", "M2", "M3", "取付道路 No.4", "No.5"];
+        let mut ts = 1_770_000_000i64;
+        let mut records = GroupRecords::new();
+        for i in 0..next(40) {
+            ts += [0, 10, 290, 300, 301, 900][next(6) as usize];
+            let captured_at = match next(10) {
+                0 => None,
+                1 => Some(i64::MAX),
+                // 時刻順とファイル名順が食い違うように少し戻す
+                2 => Some(ts - 400),
+                _ => Some(ts),
+            };
+            let text = if next(4) == 0 { "取付道路" } else { "" };
+            records.insert(format!("{:03}.jpg", next(1000) * 100 + i), record(IDS[next(5) as usize], text, captured_at));
+        }
+        records
+    }
+
+    fn groups(records: &GroupRecords) -> Vec<(String, u32)> {
+        records.iter().map(|(f, r)| (f.clone(), r.group)).collect()
+    }
+
+    #[test]
+    fn assign_groups_matches_the_previous_implementation() {
+        let mut cases: Vec<GroupRecords> = (0..2000).map(random_records).collect();
+        cases.push(synthetic_records(300, 7));
+        for (seed, case) in cases.into_iter().enumerate() {
+            let mut expected = case.clone();
+            reference_assign_groups(&mut expected);
+
+            let mut actual = case.clone();
+            assign_groups(&mut actual);
+            assert_eq!(groups(&actual), groups(&expected), "assign_groups, case {seed}");
+
+            let mut incremental = case;
+            assign_groups_incremental(&mut incremental, &GroupRecords::new());
+            assert_eq!(groups(&incremental), groups(&expected), "assign_groups_incremental without previous, case {seed}");
+        }
+    }
+
+    #[test]
+    fn incremental_keeps_numbers_of_unchanged_machines() {
+        for seed in 0..500 {
+            let mut previous = random_records(seed);
+            assign_groups(&mut previous);
+            let mut records = previous.clone();
+            records.values_mut().for_each(|r| r.group = 0);
+            assign_groups_incremental(&mut records, &previous);
+            assert_eq!(groups(&records), groups(&previous), "case {seed}");
+        }
+    }
+
+    #[test]
+    fn gaps_and_attachment_changes_split_a_machine() {
+        let mut records = GroupRecords::new();
+        records.insert("a.jpg".into(), record("M1", "", Some(1000)));
+        records.insert("b.jpg".into(), record("M1", "", Some(1000 + GROUP_GAP_SECS)));
+        records.insert("c.jpg".into(), record("M1", "", Some(1001 + 2 * GROUP_GAP_SECS)));
+        records.insert("d.jpg".into(), record("M1", "取付", Some(1002 + 2 * GROUP_GAP_SECS)));
+        records.insert("e.jpg".into(), record("M2", "", None));
+        assign_groups(&mut records);
+        assert_eq!(
+            groups(&records),
+            [("a.jpg", 1), ("b.jpg", 1), ("c.jpg", 2), ("d.jpg", 3), ("e.jpg", 4)].map(|(f, g)| (f.to_string(), g))
+        );
+    }
+
+    fn reviews(records: &GroupRecords) -> Vec<(String, u32, String, ReviewStatus)> {
+        records.iter().map(|(f, r)| (f.clone(), r.group, r.group_notes.clone(), r.group_status)).collect()
+    }
+
+    #[test]
+    fn photos_joining_a_reviewed_group_take_its_notes_and_status() {
+        let mut previous = GroupRecords::new();
+        previous.insert("a.jpg".into(), record("M1", "", Some(1000)));
+        previous.insert("b.jpg".into(), record("M1", "", Some(1060)));
+        previous.insert("x.jpg".into(), record("M2", "", Some(5000)));
+        assign_groups(&mut previous);
+        crate::domain::set_group_notes(&mut previous, 1, "転圧 2 回目").unwrap();
+        crate::domain::set_group_status(&mut previous, 1, ReviewStatus::Approved).unwrap();
+
+        let mut records = previous.clone();
+        records.insert("c.jpg".into(), record("M1", "", Some(1120)));
+        assign_groups_incremental(&mut records, &previous);
+        // 写真の集合が変わったので新しい番号になるが、メモと状態は引き継ぐ
+        let approved = |f: &str| (f.to_string(), 3, "転圧 2 回目".to_string(), ReviewStatus::Approved);
+        assert_eq!(
+            reviews(&records),
+            [approved("a.jpg"), approved("b.jpg"), approved("c.jpg"), ("x.jpg".into(), 2, String::new(), ReviewStatus::Unreviewed)]
+        );
+        let summary = crate::report::group_reviews(records.values());
+        let group = summary.iter().find(|g| g.group == 3).unwrap();
+        assert_eq!((group.photos, group.status), (3, ReviewStatus::Approved));
+    }
+
+    #[test]
+    fn notes_follow_their_photos_when_groups_are_renumbered() {
+        let mut records = GroupRecords::new();
+        records.insert("a.jpg".into(), record("M1", "", Some(1000)));
+        records.insert("x.jpg".into(), record("M2", "", Some(5000)));
+        assign_groups(&mut records);
+        crate::domain::set_group_notes(&mut records, 2, "M2 の点検").unwrap();
+
+        // 先頭時刻の早いグループが加わると番号がずれる
+        records.insert("early.jpg".into(), record("M3", "", Some(10)));
+        records.insert("y.jpg".into(), record("M2", "", Some(5060)));
+        assign_groups(&mut records);
+        assert_eq!(
+            reviews(&records),
+            [
+                ("a.jpg".into(), 2, String::new(), ReviewStatus::Unreviewed),
+                ("early.jpg".into(), 1, String::new(), ReviewStatus::Unreviewed),
+                ("x.jpg".into(), 3, "M2 の点検".into(), ReviewStatus::Unreviewed),
+                ("y.jpg".into(), 3, "M2 の点検".into(), ReviewStatus::Unreviewed),
+            ]
+        );
+    }
+
+    #[test]
+    fn merged_groups_keep_the_majority_review() {
+        let mut records = GroupRecords::new();
+        for (f, ts) in [("a.jpg", 1000), ("b.jpg", 1060), ("c.jpg", 1400)] {
+            records.insert(f.into(), record("M1", "", Some(ts)));
+        }
+        assign_groups(&mut records);
+        crate::domain::set_group_status(&mut records, 1, ReviewStatus::Approved).unwrap();
+        crate::domain::set_group_status(&mut records, 2, ReviewStatus::Rejected).unwrap();
+
+        // 間の写真が入って 1 つのグループにつながる
+        records.insert("bridge.jpg".into(), record("M1", "", Some(1200)));
+        assign_groups(&mut records);
+        assert!(records.values().all(|r| r.group == 1 && r.group_status == ReviewStatus::Approved));
+    }
+}
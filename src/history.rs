@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::error::{Error, Result};
+use crate::fs_ops::{append_jsonl, record_key, state_dir};
+use crate::tag::{TagRecord, TagRecords};
+
+/// レコードの変更履歴（追記のみ）
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// 変更の出どころ
+pub const SOURCE_AI: &str = "ai";
+pub const SOURCE_NORMALIZE: &str = "normalize";
+pub const SOURCE_REVIEW: &str = "review";
+pub const SOURCE_LOCK: &str = "lock";
+pub const SOURCE_CAPTION: &str = "caption";
+pub const SOURCE_GROUPS: &str = "groups";
+/// ファイルの無いレコードの削除（`gc`・`verify --fix`）
+pub const SOURCE_GC: &str = "gc";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix 秒
+    pub at: i64,
+    pub file: String,
+    pub source: String,
+    /// OS のユーザー名（取れなければ空）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub user: String,
+    /// フィールド名 → [変更前, 変更後]。新規レコードは変更前が、削除したレコードは変更後が null
+    pub changes: BTreeMap<String, [serde_json::Value; 2]>,
+}
+
+pub fn history_path(base: &Path) -> PathBuf {
+    state_dir(base).join(HISTORY_FILE)
+}
+
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+/// 履歴に残すフィールド（group と captured_at は導出値なので除く）
+fn tracked_fields(rec: &GroupRecord) -> BTreeMap<&'static str, serde_json::Value> {
+    BTreeMap::from([
+        ("role", rec.role.clone().into()),
+        ("machine_type", rec.machine_type.clone().into()),
+        ("machine_id", rec.machine_id.clone().into()),
+        ("has_board", rec.has_board.into()),
+        ("detected_text", rec.detected_text.clone().into()),
+        ("description", rec.description.clone().into()),
+        ("caption", rec.caption.clone().into()),
+        ("locked", rec.locked.into()),
+        ("group_notes", rec.group_notes.clone().into()),
+        ("group_status", rec.group_status.as_str().into()),
+    ])
+}
+
+/// photo-tags.json のレコードで履歴に残すフィールド（値の無いものは除く）
+fn tracked_tag_fields(rec: &TagRecord) -> BTreeMap<&'static str, serde_json::Value> {
+    let mut fields =
+        BTreeMap::from([("tag", rec.tag.clone().into()), ("detected_text", rec.detected_text.clone().into())]);
+    if let Some(confidence) = rec.confidence {
+        // f32 をそのまま入れると 0.899999… になるので、表示どおりの値で残す
+        fields.insert("confidence", confidence.to_string().parse::<f64>().unwrap_or_default().into());
+    }
+    if let Some(tag) = &rec.suggested_tag {
+        fields.insert("suggested_tag", tag.clone().into());
+    }
+    fields
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// before → after の差分を履歴エントリにする（削除されたレコードは removal_entries で扱う）
+pub fn diff_records(before: &GroupRecords, after: &GroupRecords, source: &str) -> Vec<HistoryEntry> {
+    let at = now_secs();
+    let user = current_user();
+    let mut out = Vec::new();
+    for (fname, rec) in after {
+        let new = tracked_fields(rec);
+        let old = before.get(fname).map(tracked_fields);
+        let changes: BTreeMap<String, [serde_json::Value; 2]> = new
+            .into_iter()
+            .filter_map(|(field, value)| {
+                let previous = old.as_ref().map_or(serde_json::Value::Null, |o| o[field].clone());
+                (previous != value).then(|| (field.to_string(), [previous, value]))
+            })
+            .collect();
+        if !changes.is_empty() {
+            out.push(HistoryEntry {
+                at,
+                file: fname.clone(),
+                source: source.to_string(),
+                user: user.clone(),
+                changes,
+            });
+        }
+    }
+    out
+}
+
+/// 削除したレコードを、全フィールドの変更後が null の履歴エントリにする
+pub fn removal_entries(groups: &GroupRecords, tags: &TagRecords, source: &str) -> Vec<HistoryEntry> {
+    let at = now_secs();
+    let user = current_user();
+    let removed = groups
+        .iter()
+        .map(|(fname, rec)| (fname, tracked_fields(rec)))
+        .chain(tags.iter().map(|(fname, rec)| (fname, tracked_tag_fields(rec))));
+    removed
+        .map(|(fname, fields)| HistoryEntry {
+            at,
+            file: fname.clone(),
+            source: source.to_string(),
+            user: user.clone(),
+            changes: fields
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), [value, serde_json::Value::Null]))
+                .collect(),
+        })
+        .collect()
+}
+
+fn append_entries(base: &Path, entries: &[HistoryEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let dir = state_dir(base);
+    std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+    append_jsonl(&history_path(base), entries)
+}
+
+/// 差分があれば .photo-tagger/history.jsonl に追記する
+pub fn append_history(base: &Path, before: &GroupRecords, after: &GroupRecords, source: &str) -> Result<()> {
+    append_entries(base, &diff_records(before, after, source))
+}
+
+/// 削除したレコードを .photo-tagger/history.jsonl に追記する
+pub fn append_removals(base: &Path, groups: &GroupRecords, tags: &TagRecords, source: &str) -> Result<()> {
+    append_entries(base, &removal_entries(groups, tags, source))
+}
+
+/// 指定ファイルの履歴（古い順）。履歴ファイルが無ければ空。読めない行は飛ばす。
+pub fn load_history(base: &Path, file: &str) -> Result<Vec<HistoryEntry>> {
+    let path = history_path(base);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+    let key = record_key(file);
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+        .filter(|entry| record_key(&entry.file) == key)
+        .collect())
+}
@@ -0,0 +1,144 @@
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::HooksConfig;
+use crate::error::{Error, Result};
+
+/// フックに渡すイベント。外部コマンドには 1 行の JSON として標準入力に渡す。
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookEvent<'a> {
+    /// 今回の実行で分類したレコード 1 件（保存後、グループ番号の確定した状態）
+    Record {
+        folder: &'a str,
+        /// "group" / "tag"
+        mode: &'a str,
+        file: &'a str,
+        record: serde_json::Value,
+    },
+    /// 実行の終わり（途中で打ち切った場合も呼ぶ）
+    RunEnd {
+        folder: &'a str,
+        mode: &'a str,
+        images: usize,
+        classified: usize,
+        errors: usize,
+        /// 打ち切った理由（打ち切っていなければ null）
+        aborted: Option<&'a str>,
+    },
+}
+
+/// 後処理フック。ライブラリとして使う場合はこれを実装して PhotoTaggerBuilder::hook で足す。
+pub trait Hook: Send + Sync {
+    fn on_event(&self, event: &HookEvent<'_>) -> Result<()>;
+}
+
+/// photo-tagger.toml の [hooks] で指定した外部コマンド。対象フォルダをカレントにしてシェル経由で起動する。
+pub struct CommandHook {
+    command: String,
+    dir: PathBuf,
+    /// true ならレコードごと、false なら実行の終わりだけ
+    per_record: bool,
+}
+
+impl CommandHook {
+    pub fn new(command: impl Into<String>, dir: impl Into<PathBuf>, per_record: bool) -> Self {
+        CommandHook { command: command.into(), dir: dir.into(), per_record }
+    }
+
+    fn shell(&self) -> Command {
+        if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").arg(&self.command);
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&self.command);
+            cmd
+        }
+    }
+}
+
+impl Hook for CommandHook {
+    fn on_event(&self, event: &HookEvent<'_>) -> Result<()> {
+        if self.per_record != matches!(event, HookEvent::Record { .. }) {
+            return Ok(());
+        }
+        let hook_err = |message: String| Error::Hook { command: self.command.clone(), message };
+        let json = serde_json::to_string(event).map_err(|e| hook_err(e.to_string()))?;
+        let mut child = self
+            .shell()
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| hook_err(e.to_string()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // 標準入力を読まないコマンドでもエラーにしない
+            let _ = writeln!(stdin, "{json}");
+        }
+        let status = child.wait().map_err(|e| hook_err(e.to_string()))?;
+        if !status.success() {
+            return Err(hook_err(format!("exited with {status}")));
+        }
+        Ok(())
+    }
+}
+
+/// 登録済みのフックをまとめて呼ぶ
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl Hooks {
+    /// [hooks] の on_record / on_run_end から外部コマンドのフックを作る
+    pub fn from_config(config: &HooksConfig, base: &Path) -> Self {
+        let mut hooks = Hooks::default();
+        for command in &config.on_record {
+            hooks.push(Box::new(CommandHook::new(command, base, true)));
+        }
+        for command in &config.on_run_end {
+            hooks.push(Box::new(CommandHook::new(command, base, false)));
+        }
+        hooks
+    }
+
+    pub fn push(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// すべてのフックを呼び、失敗したものを返す（1 つ失敗しても残りは呼ぶ）
+    pub fn emit(&self, event: &HookEvent<'_>) -> Vec<Error> {
+        self.hooks.iter().filter_map(|h| h.on_event(event).err()).collect()
+    }
+
+    /// レコードごとのイベント。records は file → レコードの組。
+    pub fn emit_records<'r, T: Serialize + 'r>(
+        &self,
+        folder: &str,
+        mode: &str,
+        records: impl IntoIterator<Item = (&'r String, &'r T)>,
+    ) -> Vec<Error> {
+        let mut errors = Vec::new();
+        if self.is_empty() {
+            return errors;
+        }
+        for (file, rec) in records {
+            let record = match serde_json::to_value(rec) {
+                Ok(v) => v,
+                Err(e) => {
+                    errors.push(Error::Parse { message: format!("Failed to serialize record {file}"), source: Some(e) });
+                    continue;
+                }
+            };
+            errors.extend(self.emit(&HookEvent::Record { folder, mode, file, record }));
+        }
+        errors
+    }
+}
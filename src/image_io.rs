@@ -0,0 +1,21 @@
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::fs_ops::long_path;
+
+/// 画像を読み、EXIF の Orientation を適用して正立させる（縦撮りの写真を横倒しのまま扱わない）。
+/// --cluster の埋め込みと --text-filter の文字判定はこれを通す。
+pub(crate) fn open_upright(path: &Path) -> Result<DynamicImage> {
+    let err = |message: String| Error::Image { path: path.to_path_buf(), message };
+    let mut decoder = ImageReader::open(long_path(path))
+        .map_err(|e| Error::io(path, e))?
+        .with_guessed_format()
+        .map_err(|e| Error::io(path, e))?
+        .into_decoder()
+        .map_err(|e| err(e.to_string()))?;
+    let orientation = decoder.orientation().map_err(|e| err(e.to_string()))?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| err(e.to_string()))?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
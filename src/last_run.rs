@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::fs_ops::state_dir;
+
+const LAST_RUN_FILE: &str = "last-run.json";
+
+/// 前回成功した実行の設定（.photo-tagger/last-run.json）。
+/// 次回同じフォルダで実行するとき、コマンドラインで指定しなかった項目の既定値として使う。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LastRun {
+    pub tool_version: String,
+    /// Unix 秒
+    pub saved_at: i64,
+    /// オプション名（`concurrent` など、`--` と `-` を除いた clap の id）→ 値
+    pub settings: BTreeMap<String, serde_json::Value>,
+}
+
+impl LastRun {
+    pub fn new(settings: BTreeMap<String, serde_json::Value>) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        LastRun { tool_version: env!("CARGO_PKG_VERSION").to_string(), saved_at, settings }
+    }
+}
+
+pub fn last_run_path(base: &Path) -> PathBuf {
+    state_dir(base).join(LAST_RUN_FILE)
+}
+
+/// 前回の設定を読む。無ければ None。
+pub fn load_last_run(base: &Path) -> Result<Option<LastRun>> {
+    let path = last_run_path(base);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|source| Error::CorruptRecords { path, source })
+}
+
+pub fn save_last_run(base: &Path, last: &LastRun) -> Result<PathBuf> {
+    let dir = state_dir(base);
+    std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+    let path = dir.join(LAST_RUN_FILE);
+    let json = serde_json::to_string_pretty(last).map_err(|e| Error::Parse {
+        message: "Failed to serialize last-run settings".into(),
+        source: Some(e),
+    })?;
+    std::fs::write(&path, json).map_err(|e| Error::io(&path, e))?;
+    Ok(path)
+}
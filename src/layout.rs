@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::domain::GroupRecord;
+use crate::fs_ops::sanitize_dir_name;
+use crate::pairing::phase_in;
+use crate::safety::safety_scene_in;
+
+/// 既定の振り分け先（<フォルダ>/<タグ>/）
+pub const DEFAULT_OUTPUT_LAYOUT: &str = "{tag}";
+
+/// 差し込み項目。`{工種}` は `{tag}` の、`{activity}` は `{machine_type}` の別名。
+const FIELDS: [&str; 9] =
+    ["tag", "工種", "写真区分", "role", "activity", "machine_type", "machine_id", "date", "month"];
+
+/// 写真区分（デジタル写真管理情報基準）と判定に使う文言。上から順に判定し、どれでもなければ施工状況写真。
+const PHOTO_CLASSES: [(&str, &[&str]); 3] = [
+    ("品質管理写真", &["品質", "温度", "密度", "締固め度", "試験"]),
+    ("出来形管理写真", &["出来形", "寸法", "厚さ", "幅", "延長", "高さ"]),
+    ("使用材料写真", &["材料", "搬入", "納品書", "ミルシート"]),
+];
+
+/// 黒板・役割などの文字から写真区分を判定する
+pub fn photo_class(text: &str) -> &'static str {
+    if phase_in(text).is_some() {
+        return "着手前及び完成写真";
+    }
+    if safety_scene_in(text).is_some() {
+        return "安全管理写真";
+    }
+    let text: String = text.nfkc().filter(|c| !c.is_whitespace()).collect();
+    PHOTO_CLASSES
+        .iter()
+        .find(|(_, words)| words.iter().any(|w| text.contains(w)))
+        .map_or("施工状況写真", |(class, _)| *class)
+}
+
+/// 振り分ける写真 1 枚分の差し込み値
+#[derive(Debug, Clone, Default)]
+pub struct LayoutFields<'a> {
+    pub tag: &'a str,
+    /// タグ付けで読んだ黒板の文字
+    pub text: &'a str,
+    /// 同じ写真のグループのレコード（役割・機械・作業名）
+    pub group: Option<&'a GroupRecord>,
+    /// 撮影日
+    pub date: Option<(i32, u32, u32)>,
+}
+
+/// 振り分け先のフォルダ構成（`{写真区分}/{工種}/{date}/{activity}` など）。`/` ごとに 1 階層。
+/// 差し込んだ値はフォルダ名として安全な名前にし、空になった階層は詰める。
+#[derive(Debug, Clone)]
+pub struct OutputLayout {
+    segments: Vec<String>,
+}
+
+impl Default for OutputLayout {
+    fn default() -> Self {
+        OutputLayout { segments: vec![DEFAULT_OUTPUT_LAYOUT.to_string()] }
+    }
+}
+
+impl OutputLayout {
+    /// テンプレートを読む。知らない差し込み項目・`..` などの階層はエラー（メッセージを返す）。
+    pub fn parse(template: &str) -> std::result::Result<Self, String> {
+        let segments: Vec<String> = template
+            .split(['/', '\\'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        if segments.is_empty() {
+            return Err("layout is empty".into());
+        }
+        for seg in &segments {
+            if seg == "." || seg == ".." {
+                return Err(format!("invalid path segment `{seg}`"));
+            }
+            let mut rest = seg.as_str();
+            while let Some(start) = rest.find('{') {
+                let Some(len) = rest[start..].find('}') else {
+                    return Err(format!("unclosed `{{` in `{seg}`"));
+                };
+                let field = &rest[start + 1..start + len];
+                if !FIELDS.contains(&field) {
+                    return Err(format!("unknown field `{{{field}}}` (use {})", field_list()));
+                }
+                rest = &rest[start + len + 1..];
+            }
+        }
+        Ok(OutputLayout { segments })
+    }
+
+    /// タグが階層のどこかに入るか。入らなければ振り分け先に目印を置かない（タグで照合できないため）。
+    pub fn uses_tag(&self) -> bool {
+        self.segments.iter().any(|s| s.contains("{tag}") || s.contains("{工種}"))
+    }
+
+    /// 振り分け先（base からの相対パス）。すべての階層が空になったらタグのフォルダ。
+    pub fn render(&self, fields: &LayoutFields) -> PathBuf {
+        let group = fields.group;
+        let role = group.map_or("", |g| g.role.as_str());
+        let activity = group.map_or("", |g| g.machine_type.as_str());
+        let machine_id = group.map_or("", |g| g.machine_id.as_str());
+        let class_text = match group {
+            Some(g) => format!("{} {} {} {}", fields.text, g.role, g.detected_text, g.description),
+            None => fields.text.to_string(),
+        };
+        let date = fields.date.map(|(y, m, d)| format!("{y:04}-{m:02}-{d:02}")).unwrap_or_default();
+        let month = fields.date.map(|(y, m, _)| format!("{y:04}-{m:02}")).unwrap_or_default();
+        let path: PathBuf = self
+            .segments
+            .iter()
+            .map(|seg| {
+                seg.replace("{tag}", fields.tag)
+                    .replace("{工種}", fields.tag)
+                    .replace("{写真区分}", photo_class(&class_text))
+                    .replace("{role}", role)
+                    .replace("{activity}", activity)
+                    .replace("{machine_type}", activity)
+                    .replace("{machine_id}", machine_id)
+                    .replace("{date}", &date)
+                    .replace("{month}", &month)
+            })
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| sanitize_dir_name(&name))
+            .collect();
+        if path.as_os_str().is_empty() {
+            PathBuf::from(sanitize_dir_name(fields.tag))
+        } else {
+            path
+        }
+    }
+}
+
+fn field_list() -> String {
+    FIELDS.iter().map(|f| format!("{{{f}}}")).collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::domain::{is_emission_role, is_inspection_role, ROLE_INSPECTION, ROLE_OVERVIEW};
+use crate::error::{Error, Result};
+
+/// テンプレートの無い機械に求める写真（全景・検査証票・排ガス/低騒音証票）
+pub const DEFAULT_MACHINE_SET: [&str; 3] = [ROLE_OVERVIEW, ROLE_INSPECTION, "排ガス・低騒音証票"];
+
+/// 機械種別ごとに揃えるべき写真の役割（machine-sets.toml の [[set]]）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineSetTemplate {
+    /// machine_type に含まれていれば当てはまる。"*" はすべての機械
+    pub machine_type: String,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplatesFile {
+    #[serde(default)]
+    set: Vec<MachineSetTemplate>,
+}
+
+/// 機械 1 台分のテンプレート。上から順に照合し、当てはまらなければ DEFAULT_MACHINE_SET。
+///
+/// ```toml
+/// [[set]]
+/// machine_type = "バックホウ"
+/// roles = ["機械全景", "特定自主検査証票", "排ガス・低騒音証票", "保険証"]
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MachineSets {
+    templates: Vec<MachineSetTemplate>,
+}
+
+impl MachineSets {
+    pub fn new(templates: Vec<MachineSetTemplate>) -> Self {
+        MachineSets { templates }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let file: TemplatesFile =
+            toml::from_str(&text).map_err(|e| Error::Config { path: path.to_path_buf(), message: e.to_string() })?;
+        if let Some(t) = file.set.iter().find(|t| t.machine_type.trim().is_empty() || t.roles.is_empty()) {
+            return Err(Error::Config {
+                path: path.to_path_buf(),
+                message: format!("set {:?}: machine_type and roles are required", t.machine_type),
+            });
+        }
+        Ok(MachineSets::new(file.set))
+    }
+
+    /// machine_type の機械に揃えるべき役割
+    pub fn roles_for(&self, machine_type: &str) -> Vec<&str> {
+        self.templates
+            .iter()
+            .find(|t| t.machine_type == "*" || machine_type.contains(t.machine_type.as_str()))
+            .map(|t| t.roles.iter().map(String::as_str).collect())
+            .unwrap_or_else(|| DEFAULT_MACHINE_SET.to_vec())
+    }
+
+    /// roles（その機械の写真の役割）に無いテンプレートの役割
+    pub fn missing<'a>(&'a self, machine_type: &str, roles: &[&str]) -> Vec<&'a str> {
+        self.roles_for(machine_type)
+            .into_iter()
+            .filter(|required| !roles.iter().any(|role| role_matches(required, role)))
+            .collect()
+    }
+}
+
+/// 写真の役割がテンプレートの役割に当たるか。検査証票・排ガス/低騒音証票は表記揺れを許容し、ほかは部分一致。
+pub fn role_matches(required: &str, role: &str) -> bool {
+    if is_inspection_role(required) {
+        return is_inspection_role(role);
+    }
+    if is_emission_role(required) {
+        return is_emission_role(role);
+    }
+    role.contains(required)
+}
@@ -1,26 +1,621 @@
-use anyhow::Result;
-use clap::Parser;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::UNIX_EPOCH;
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use std::thread;
 
 use photo_tagger::{GroupRecord, GroupRecords, classify_group_batch};
-use photo_tagger::fs_ops;
+use photo_tagger::{set_group_notes, set_group_status, set_locked, ReviewStatus};
+use photo_tagger::{apply_capture_times, assign_groups_incremental, meta, time_batches};
+use photo_tagger::concurrency::{CircuitBreaker, DEFAULT_BREAKER_ERROR_RATE, DEFAULT_BREAKER_WINDOW};
+use photo_tagger::validate::{self, Issue, IssueKind};
+use photo_tagger::config::Config;
+use photo_tagger::project::{self, FolderReport, ProjectReport};
+use photo_tagger::{GeminiBackend, GroupProgress, GroupRun, PhotoTagger};
+use photo_tagger::{
+    ab, backup, bench, caption, domain, estimate, eval, force_reclassify_enabled, fs_ops, git, history, last_run, layout, manifest, measure, package, pairing, pending_images, report, retry_state,
+    review, safety, schema, skew, summary, tag, text_detect,
+    timestamp, trash, verify, witness, xlsx,
+};
+use photo_tagger::tag::{Categories, TagRecord};
+use photo_tagger::fs_ops::RecordFormat;
+use photo_tagger::machine_set::MachineSets;
+use photo_tagger::profile::RunProfile;
+use photo_tagger::summary::RunSummary;
+use photo_tagger::hooks::{HookEvent, Hooks};
+use photo_tagger::sample::{self, SampleStrategy};
 
 const BATCH_SIZE: usize = 10;
-const MAX_CONCURRENT: usize = 3;
-const GROUP_GAP_SECS: i64 = 5 * 60;
+const DEFAULT_AB_SAMPLE: usize = 30;
+const DEFAULT_CONCURRENT: usize = 3;
 
 #[derive(Parser)]
-#[command(name = "photo-tagger", version, about = "Classify and group construction photos")]
+#[command(
+    name = "photo-tagger",
+    version,
+    about = "Classify and group construction photos",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    path: Option<PathBuf>,
     #[arg(long)]
     dry_run: bool,
     #[arg(long)]
     profile: bool,
+    /// Number of batches sent in parallel (upper bound with --auto-concurrency)
+    #[arg(long, default_value_t = DEFAULT_CONCURRENT)]
+    concurrent: usize,
+    /// Start at 1 and adapt parallelism to backend latency and errors (AIMD)
+    #[arg(long, overrides_with = "no_auto_concurrency")]
+    auto_concurrency: bool,
+    /// Use a fixed --concurrent even if the previous run used --auto-concurrency
+    #[arg(long)]
+    no_auto_concurrency: bool,
+    /// Threads reading file metadata (sequential on Windows), EXIF and content hashes while scanning the folder
+    #[arg(long, default_value_t = meta::DEFAULT_SCAN_WORKERS)]
+    scan_workers: usize,
+    /// Renumber every group instead of only re-segmenting machines that changed
+    #[arg(long)]
+    renumber: bool,
+    /// Layout of photo-groups.json (default: keep the existing file's layout); keys are always sorted
+    #[arg(long, value_enum)]
+    record_format: Option<RecordFormatArg>,
+    /// After a successful run, commit photo-groups.json and .photo-tagger/ to the folder's Git repository
+    #[arg(long)]
+    git_commit: bool,
+    /// Exit nonzero when problems remain: 10 classification errors, 11 unassigned photos,
+    /// 12 incomplete machine sets, 13 date mismatches, 14 off-hours photos (highest priority first)
+    #[arg(long, overrides_with = "no_strict")]
+    strict: bool,
+    /// Exit zero on remaining problems even if the previous run used --strict
+    #[arg(long)]
+    no_strict: bool,
+    /// Stop the run when at least this share (above 0, at most 1) of the last --error-window batches failed
+    #[arg(long, default_value_t = DEFAULT_BREAKER_ERROR_RATE, value_parser = parse_rate)]
+    max_error_rate: f64,
+    /// Number of recent batches the error rate is measured over (0 = never stop early)
+    #[arg(long, default_value_t = DEFAULT_BREAKER_WINDOW)]
+    error_window: usize,
+    /// Cluster visually similar photos with the local [cluster] model and send one photo per cluster to the AI
+    #[arg(long, overrides_with = "no_cluster")]
+    cluster: bool,
+    /// Send every photo even if the previous run used --cluster
+    #[arg(long)]
+    no_cluster: bool,
+    /// Send photos without a blackboard or text (local edge check) with a shorter prompt that skips text reading
+    #[arg(long, overrides_with = "no_text_filter")]
+    text_filter: bool,
+    /// Use the full prompt for every photo even if the previous run used --text-filter
+    #[arg(long)]
+    no_text_filter: bool,
+    /// Text score below which --text-filter treats a photo as having no text
+    #[arg(long, default_value_t = text_detect::DEFAULT_MIN_TEXT_SCORE, value_parser = parse_confidence)]
+    min_text_score: f32,
+    /// Add the machines classified in earlier batches to each prompt so a machine photographed
+    /// across a batch boundary keeps one machine_id
+    #[arg(long, overrides_with = "no_batch_context")]
+    batch_context: bool,
+    /// Do not add earlier batches' machines even if the previous run used --batch-context
+    #[arg(long)]
+    no_batch_context: bool,
+    /// Correct a camera's clock: CAMERA=+00:03:20 added to its capture times (CAMERA is the EXIF model,
+    /// serial number or model#serial; repeatable). `skew` suggests values
+    #[arg(long, value_name = "CAMERA=OFFSET", value_parser = parse_time_offset)]
+    time_offset: Vec<String>,
+    /// Use no clock offsets even if the previous run had --time-offset
+    #[arg(long, conflicts_with = "time_offset")]
+    no_time_offset: bool,
+    /// Classify only photos taken with this camera (EXIF model, serial number or model#serial;
+    /// repeatable). Photos from other cameras stay pending for a later run
+    #[arg(long, value_name = "CAMERA")]
+    camera: Vec<String>,
+    /// Classify only this many of the pending photos into .photo-tagger/photo-groups.sample.json
+    /// (photo-groups.json, history and hooks are left alone) to try prompt or threshold changes cheaply
+    #[arg(long, value_name = "N")]
+    sample: Option<usize>,
+    /// How --sample picks photos: spread over the time of day, or at random
+    #[arg(long, value_enum, default_value_t = SampleBy::Time, requires = "sample")]
+    sample_by: SampleBy,
+    /// Match photos to records by file name only: skip content hashing (faster on slow storage), so a photo
+    /// replaced under the same name keeps its old classification
+    #[arg(long)]
+    trust_names: bool,
+    /// Seconds between rewrites of photo-groups.json during a run (0 = after every wave of batches);
+    /// results are also appended to the journal after each batch
+    #[arg(long, value_name = "SECS", default_value_t = fs_ops::DEFAULT_FLUSH_INTERVAL_SECS)]
+    flush_interval: u64,
+    /// Machine names to steer the classifier toward, one per line (blank lines and lines starting with # are ignored)
+    #[arg(long, value_name = "FILE")]
+    vocabulary: Option<PathBuf>,
+    /// Do not take defaults from the previous successful run (.photo-tagger/last-run.json)
+    #[arg(long)]
+    ignore_last_run: bool,
+    /// Write counts, new groups, errors and top warnings to this file (.html for HTML, otherwise plain text)
+    #[arg(long)]
+    summary_out: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print schemas for the record files written by photo-tagger
+    Schema {
+        #[arg(long, value_enum, default_value = "json-schema")]
+        format: SchemaFormat,
+    },
+    /// Run every dated subfolder of a project and write a combined report
+    Batch {
+        /// Project folder containing dated subfolders (20260211, 2026-02-11, ...)
+        #[arg(long)]
+        root: PathBuf,
+        #[arg(long, value_enum, default_value = "group")]
+        mode: BatchMode,
+        /// Number of folders processed at the same time
+        #[arg(long, default_value_t = 1)]
+        jobs: usize,
+        /// Batches sent in parallel within each folder
+        #[arg(long, default_value_t = DEFAULT_CONCURRENT)]
+        concurrent: usize,
+    },
+    /// Classify the same photos with two prompts and report per-field agreement (calls the AI twice per batch)
+    Ab {
+        path: PathBuf,
+        /// Prompt template A; `{files}` is replaced with the batch's file names (default: the built-in prompt)
+        #[arg(long)]
+        prompt_a: Option<PathBuf>,
+        /// Prompt template B
+        #[arg(long)]
+        prompt_b: PathBuf,
+        /// Number of photos to compare, spread over the time of day (0 = all)
+        #[arg(long, default_value_t = DEFAULT_AB_SAMPLE)]
+        sample: usize,
+        /// Number of batches sent in parallel (each batch is sent once per prompt)
+        #[arg(long, default_value_t = DEFAULT_CONCURRENT)]
+        concurrent: usize,
+        /// Write the Markdown report to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Score classification results against a hand-labeled golden set (golden.jsonl), per field
+    Eval {
+        path: PathBuf,
+        /// JSON Lines with {"file": ..., "role": ..., "machine_type": ..., "machine_id": ..., "has_board": ...}
+        #[arg(long)]
+        golden: PathBuf,
+        /// Classify the golden photos again instead of scoring the saved photo-groups.json
+        #[arg(long)]
+        live: bool,
+        /// Prompt template for --live (`{files}` is replaced with the file names; default: the built-in prompt)
+        #[arg(long, requires = "live")]
+        prompt: Option<PathBuf>,
+        /// Number of batches sent in parallel with --live
+        #[arg(long, default_value_t = DEFAULT_CONCURRENT)]
+        concurrent: usize,
+        /// Write the Markdown report to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Estimate requests, tokens and cost for the photos a run would send, without calling the AI
+    Estimate {
+        path: PathBuf,
+        #[arg(long, value_enum, default_value_t = EstimateMode::Group)]
+        mode: EstimateMode,
+        /// Category list for --mode tag (same file as `tag --categories`)
+        #[arg(long)]
+        categories: Option<PathBuf>,
+    },
+    /// Estimate each camera's clock offset from blackboards photographed by several cameras
+    Skew { path: PathBuf },
+    /// Reports across every dated subfolder of a project (no AI calls)
+    Project {
+        #[command(subcommand)]
+        action: ProjectAction,
+    },
+    /// Tag photos by blackboard text against a category list (photo-tags.json)
+    Tag {
+        path: PathBuf,
+        /// Category list, one per line (`#` starts a comment)
+        #[arg(long, required_unless_present = "learn_categories")]
+        categories: Option<PathBuf>,
+        /// Use the existing subfolder names of <path> as categories
+        #[arg(long)]
+        learn_categories: bool,
+        /// Alias file, one `category = alias, alias` per line
+        #[arg(long)]
+        aliases: Option<PathBuf>,
+        /// Move each photo into a <path>/<tag>/ folder after tagging
+        #[arg(long = "move")]
+        move_files: bool,
+        /// Show the result and planned moves without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Batches sent in parallel
+        #[arg(long, default_value_t = DEFAULT_CONCURRENT)]
+        concurrent: usize,
+        /// Put photos tagged below this confidence (0.0-1.0) into _要確認 instead of a category
+        #[arg(long, value_parser = parse_confidence)]
+        min_confidence: Option<f32>,
+        /// Layout of photo-tags.json (default: keep the existing file's layout)
+        #[arg(long, value_enum)]
+        record_format: Option<RecordFormatArg>,
+        /// After a successful run, commit photo-tags.json and .photo-tagger/ to the folder's Git repository
+        #[arg(long)]
+        git_commit: bool,
+    },
+    /// Check photo-groups.json against the folder without calling the AI
+    Validate {
+        path: PathBuf,
+        /// Exit nonzero when problems are found (same codes as grouping --strict)
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Fill the caption field of every record from a template (photo-tagger.toml [caption])
+    Caption {
+        path: PathBuf,
+        /// Template overriding the config, e.g. "{machine_type} {station} {role}"
+        #[arg(long)]
+        template: Option<String>,
+        /// Use the description the classifier already wrote instead of the template
+        #[arg(long)]
+        ai_captions: bool,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Protect records (file names or group numbers) from reclassification and normalization
+    Lock {
+        path: PathBuf,
+        #[arg(required = true)]
+        targets: Vec<String>,
+    },
+    /// Remove the protection added by `lock`
+    Unlock {
+        path: PathBuf,
+        #[arg(required = true)]
+        targets: Vec<String>,
+    },
+    /// Review status and notes per group, stored in photo-groups.json
+    Groups {
+        #[command(subcommand)]
+        action: GroupsAction,
+    },
+    /// Save records, journals, config and state files to .photo-tagger/backups/
+    Backup {
+        path: PathBuf,
+        /// List existing backups instead of creating one
+        #[arg(long)]
+        list: bool,
+    },
+    /// Restore a backup (file name in .photo-tagger/backups/ or a path); the current state is backed up first
+    Restore {
+        path: PathBuf,
+        archive: PathBuf,
+    },
+    /// Write checksums of every photo and document in the folder (MANIFEST.sha256), or check them
+    Manifest {
+        path: PathBuf,
+        #[arg(long, value_enum, default_value = "sha256")]
+        algorithm: ManifestAlgorithm,
+        /// Manifest file (default: <folder>/MANIFEST.sha256 or MANIFEST.md5)
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Check the folder against an existing manifest instead of writing one
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Bundle sorted photos, documents and a checksum manifest into a delivery ZIP ([package] in photo-tagger.toml)
+    Package {
+        path: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+        #[arg(long, value_enum, default_value = "sha256")]
+        algorithm: ManifestAlgorithm,
+        /// Do not add a checksum manifest to the archive
+        #[arg(long)]
+        no_manifest: bool,
+        /// Print the archive layout without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Permanently delete files the tool moved to .photo-tagger/trash/
+    Purge {
+        path: PathBuf,
+        /// Only delete trash older than this many days
+        #[arg(long)]
+        older_than_days: Option<u64>,
+        /// List the trash instead of deleting it
+        #[arg(long)]
+        list: bool,
+    },
+    /// Show the recorded changes of one photo (.photo-tagger/history.jsonl)
+    History {
+        path: PathBuf,
+        file: String,
+    },
+    /// Extract measured values (出来形) from blackboard text as CSV
+    Measure {
+        path: PathBuf,
+        /// Design values CSV (測点,項目,設計値,下限差,上限差) to flag out-of-tolerance values
+        #[arg(long)]
+        design: Option<PathBuf>,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Cross-check records against the files actually in the folder (read-only unless --fix)
+    Verify {
+        path: PathBuf,
+        /// Remove records whose file no longer exists anywhere in the folder (backs up first)
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Remove records whose photo no longer exists anywhere in the folder (backs up first)
+    Gc {
+        path: PathBuf,
+        /// List the records that would be removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Also keep the removed records in .photo-tagger/archive/gc-<timestamp>.json
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Export problem records for manual review, or apply the reviewed CSV
+    Review {
+        #[command(subcommand)]
+        action: ReviewAction,
+    },
+    /// Generate documents from photo-groups.json
+    Report {
+        #[command(subcommand)]
+        kind: ReportKind,
+    },
+    /// Time the non-AI pipeline stages on synthetic record sets
+    Bench {
+        /// Record counts to benchmark
+        #[arg(long, value_delimiter = ',', default_value = "1000,10000,100000")]
+        sizes: Vec<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportKind {
+    /// Daily work report (日報) skeleton in Markdown or xlsx
+    Daily {
+        path: PathBuf,
+        /// Day to report (YYYY-MM-DD, local time per photo-tagger.toml)
+        #[arg(long, value_parser = parse_date_arg)]
+        date: (i32, u32, u32),
+        /// Write to this file instead of stdout (.xlsx for an Excel workbook, otherwise Markdown)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// 使用機械一覧表 (CSV or xlsx) from group records
+    Machines {
+        path: PathBuf,
+        /// Write to this file instead of stdout (.xlsx for an Excel workbook, otherwise CSV)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Days each machine was photographed, with first/last time per day (CSV, optional HTML Gantt chart)
+    Timeline {
+        path: PathBuf,
+        /// Write the CSV to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Also write an HTML page with an SVG Gantt chart
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+    /// Trips per vehicle per day (運搬台数) from dump truck photos
+    Tally {
+        path: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Per-day asphalt temperature summary (CSV) from thermometer/blackboard text
+    Temperatures {
+        path: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// 立会一覧 (CSV): 立会/段階確認 photos with the date and attendees read from the board
+    Witness {
+        path: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Pair 施工前/施工後 photos by station or board text, each pair on adjacent rows (CSV); lists unpaired ones
+    Pairs {
+        path: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProjectAction {
+    /// Machine usage periods, weekly activity and per-folder issue counts (Markdown)
+    Stats {
+        /// Project folder containing dated subfolders (20260211, 2026-02-11, ...)
+        #[arg(long)]
+        root: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Weekly 安全管理写真 albums (one HTML page per ISO week) from safety photos (保安施設, KY活動, 巡視)
+    Safety {
+        /// Project folder containing dated subfolders (20260211, 2026-02-11, ...)
+        #[arg(long)]
+        root: PathBuf,
+        /// Folder to write the pages to
+        #[arg(long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum GroupsAction {
+    /// Show every group with its status, notes and cameras
+    List {
+        path: PathBuf,
+        /// Only groups with this status
+        #[arg(long, value_enum)]
+        status: Option<GroupStatus>,
+        /// Only groups with a photo taken with this camera (EXIF model, serial number or model#serial)
+        #[arg(long, value_name = "CAMERA")]
+        camera: Option<String>,
+    },
+    /// Set the notes of a group (an empty string clears them)
+    Note {
+        path: PathBuf,
+        group: u32,
+        notes: String,
+    },
+    /// Mark a group as unreviewed, approved or rejected
+    Status {
+        path: PathBuf,
+        group: u32,
+        #[arg(value_enum)]
+        status: GroupStatus,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RecordFormatArg {
+    /// Indented JSON
+    Pretty,
+    /// One record per line
+    Compact,
+}
+
+impl From<RecordFormatArg> for RecordFormat {
+    fn from(f: RecordFormatArg) -> Self {
+        match f {
+            RecordFormatArg::Pretty => RecordFormat::Pretty,
+            RecordFormatArg::Compact => RecordFormat::Compact,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GroupStatus {
+    Unreviewed,
+    Approved,
+    Rejected,
+}
+
+impl From<GroupStatus> for ReviewStatus {
+    fn from(s: GroupStatus) -> Self {
+        match s {
+            GroupStatus::Unreviewed => ReviewStatus::Unreviewed,
+            GroupStatus::Approved => ReviewStatus::Approved,
+            GroupStatus::Rejected => ReviewStatus::Rejected,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ReviewAction {
+    /// Write one CSV row per photo and issue, with blank new_* columns to fill in
+    Export {
+        path: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Apply the new_* columns of a reviewed CSV and renumber the affected groups
+    Import {
+        path: PathBuf,
+        queue: PathBuf,
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn parse_confidence(s: &str) -> std::result::Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(v) if v > 0.0 && v <= 1.0 => Ok(v),
+        _ => Err(format!("expected a number above 0.0 and at most 1.0, got {s:?}")),
+    }
+}
+
+fn parse_rate(s: &str) -> std::result::Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v > 0.0 && v <= 1.0 => Ok(v),
+        _ => Err(format!("expected a number above 0.0 and at most 1.0, got {s:?}")),
+    }
+}
+
+fn parse_time_offset(s: &str) -> std::result::Result<String, String> {
+    time_offset_pair(s).map(|_| s.to_string())
+}
+
+/// "CAMERA=+00:03:20" → (機材, 秒)
+fn time_offset_pair(s: &str) -> std::result::Result<(String, i64), String> {
+    let err = || format!("expected CAMERA=[+-]hh:mm:ss, got {s:?}");
+    let (camera, offset) = s.rsplit_once('=').ok_or_else(err)?;
+    let secs = timestamp::parse_clock_offset(offset).ok_or_else(err)?;
+    if camera.trim().is_empty() {
+        return Err(err());
+    }
+    Ok((camera.trim().to_string(), secs))
+}
+
+fn parse_date_arg(s: &str) -> std::result::Result<(i32, u32, u32), String> {
+    timestamp::parse_date(s).ok_or_else(|| format!("expected YYYY-MM-DD, got {s:?}"))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SampleBy {
+    /// Evenly spaced over the capture time of day
+    Time,
+    /// Random (seeded from the clock)
+    Random,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EstimateMode {
+    /// Grouping run (`photo-tagger <PATH>`)
+    Group,
+    /// `photo-tagger tag`
+    Tag,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaFormat {
+    JsonSchema,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ManifestAlgorithm {
+    Sha256,
+    /// For delivery specs that still require MD5
+    Md5,
+}
+
+impl From<ManifestAlgorithm> for manifest::HashAlgorithm {
+    fn from(a: ManifestAlgorithm) -> Self {
+        match a {
+            ManifestAlgorithm::Sha256 => manifest::HashAlgorithm::Sha256,
+            ManifestAlgorithm::Md5 => manifest::HashAlgorithm::Md5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BatchMode {
+    /// Machine/station grouping (photo-groups.json)
+    Group,
 }
 
 fn fmt_duration(d: Duration) -> String {
@@ -32,357 +627,1690 @@ fn fmt_duration(d: Duration) -> String {
     }
 }
 
-fn assign_groups(records: &mut GroupRecords) {
-    let mut by_id: HashMap<String, Vec<String>> = HashMap::new();
-    for (fname, rec) in records.iter() {
-        by_id.entry(rec.machine_id.clone()).or_default().push(fname.clone());
+fn print_group_summary(records: &GroupRecords) -> std::io::Result<()> {
+    if records.is_empty() {
+        return Ok(());
     }
 
-    let mut segment_heads: Vec<(i64, String, u32)> = Vec::new();
-    let mut fname_to_tmp_group: HashMap<String, u32> = HashMap::new();
-    let mut next_tmp_group = 1u32;
+    let mut groups: BTreeMap<u32, Vec<(&String, &GroupRecord)>> = BTreeMap::new();
+    for (fname, rec) in records {
+        groups.entry(rec.group).or_default().push((fname, rec));
+    }
 
-    for (machine_id, mut files) in by_id {
-        files.sort_by(|a, b| {
-            let ra = &records[a];
-            let rb = &records[b];
-            ra.captured_at
-                .unwrap_or(i64::MAX)
-                .cmp(&rb.captured_at.unwrap_or(i64::MAX))
-                .then(a.cmp(b))
-        });
-        if files.is_empty() {
-            continue;
+    let group_nums: Vec<u32> = groups.keys().copied().collect();
+    // 機材が 1 台だけなら表示しない
+    let mut cameras: Vec<String> = records.values().map(|r| r.camera.key()).filter(|k| !k.is_empty()).collect();
+    cameras.sort_unstable();
+    cameras.dedup();
+    let show_cameras = cameras.len() > 1;
+
+    // 数万件でも 1 行ずつロックを取らないよう、まとめてバッファ出力する
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    writeln!(out, "\n--- Summary ({} machines, {} photos) ---", group_nums.len(), records.len())?;
+    for g in group_nums {
+        let members = &groups[&g];
+        let machine_type = &members[0].1.machine_type;
+        let machine_id = &members[0].1.machine_id;
+        let mut review = String::new();
+        if let Some(r) = report::group_reviews(members.iter().map(|m| m.1)).first() {
+            if !r.status.is_unreviewed() {
+                review.push_str(&format!(" [{}]", r.status.label()));
+            }
+            if show_cameras && !r.cameras.is_empty() {
+                review.insert_str(0, &format!(" @ {}", r.cameras.join(", ")));
+            }
+            if !r.notes.is_empty() {
+                review.push_str(&format!(" - {}", r.notes));
+            }
+        }
+        writeln!(out, "  Group {g}: {machine_type} ({machine_id}){review}")?;
+        for (fname, rec) in members {
+            writeln!(out, "    - {fname}: {}", rec.role)?;
         }
+    }
+    out.flush()
+}
 
-        let mut current_group = next_tmp_group;
-        next_tmp_group += 1;
-        let first_ts = records[&files[0]].captured_at.unwrap_or(i64::MAX);
-        segment_heads.push((first_ts, machine_id.clone(), current_group));
-        fname_to_tmp_group.insert(files[0].clone(), current_group);
+fn main() -> Result<()> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if cli.command.is_none() && !cli.ignore_last_run {
+        if let Some(path) = cli.path.clone() {
+            apply_last_run(&mut cli, &matches, &path)?;
+        }
+    }
 
-        for pair in files.windows(2) {
-            let prev = &records[&pair[0]];
-            let curr = &records[&pair[1]];
-            let prev_ts = prev.captured_at.unwrap_or(i64::MAX);
-            let curr_ts = curr.captured_at.unwrap_or(i64::MAX);
-            let gap = if prev_ts == i64::MAX || curr_ts == i64::MAX {
-                0
-            } else {
-                (curr_ts - prev_ts).abs()
+    match &cli.command {
+        Some(Command::Schema { format: SchemaFormat::JsonSchema }) => {
+            println!("{}", serde_json::to_string_pretty(&schema::json_schemas())?);
+            Ok(())
+        }
+        Some(Command::Batch { root, mode: BatchMode::Group, jobs, concurrent }) => {
+            run_batch(root, *jobs, *concurrent)
+        }
+        Some(Command::Report { kind: ReportKind::Daily { path, date, out } }) => {
+            run_daily_report(path, *date, out.as_deref())
+        }
+        Some(Command::Report { kind: ReportKind::Machines { path, out } }) => {
+            let config = Config::load(path)?;
+            let timestamps = config.timestamp_parser()?;
+            let machine_sets = config.machine_sets(path)?;
+            let records = fs_ops::load_group_records(path)?;
+            let rows = report::machine_list(&records, timestamps.utc_offset_secs(), &machine_sets);
+            match out.as_deref().filter(|p| is_xlsx(p)) {
+                Some(p) => write_sheets(p, &[report::machine_list_sheet(&rows)]),
+                None => write_output(out.as_deref(), &report::machine_list_csv(&rows)),
+            }
+        }
+        Some(Command::Tag {
+            path,
+            categories,
+            learn_categories,
+            aliases,
+            move_files,
+            dry_run,
+            concurrent,
+            min_confidence,
+            record_format,
+            git_commit,
+        }) => {
+            let list = tag_categories(path, categories.as_deref(), *learn_categories, aliases.as_deref())?;
+            let options = TagOptions {
+                move_files: *move_files,
+                dry_run: *dry_run,
+                concurrent: *concurrent,
+                min_confidence: *min_confidence,
+                record_format: record_format.map(RecordFormat::from),
+                git_commit: *git_commit,
             };
-            let prev_attach = has_attachment_hint(prev);
-            let curr_attach = has_attachment_hint(curr);
-
-            if gap > GROUP_GAP_SECS || prev_attach != curr_attach {
-                current_group = next_tmp_group;
-                next_tmp_group += 1;
-                segment_heads.push((curr_ts, machine_id.clone(), current_group));
+            run_tag(path, &list, &options)
+        }
+        Some(Command::Report { kind: ReportKind::Timeline { path, out, html } }) => {
+            let timestamps = Config::load(path)?.timestamp_parser()?;
+            let records = fs_ops::load_group_records(path)?;
+            let rows = report::machine_timeline(&records, timestamps.utc_offset_secs());
+            if let Some(html) = html {
+                write_output(Some(html), &report::machine_timeline_html(&path.display().to_string(), &rows))?;
+            }
+            write_output(out.as_deref(), &report::machine_timeline_csv(&rows))
+        }
+        Some(Command::Report { kind: ReportKind::Tally { path, out } }) => {
+            let timestamps = Config::load(path)?.timestamp_parser()?;
+            let records = fs_ops::load_group_records(path)?;
+            let rows = report::haul_tally(&records, timestamps.utc_offset_secs());
+            write_output(out.as_deref(), &report::haul_tally_csv(&rows))
+        }
+        Some(Command::Report { kind: ReportKind::Temperatures { path, out } }) => {
+            let timestamps = Config::load(path)?.timestamp_parser()?;
+            let records = fs_ops::load_group_records(path)?;
+            let measurements = measure::extract_measurements(&records);
+            let rows = measure::temperature_summary(&measurements, &records, timestamps.utc_offset_secs());
+            write_output(out.as_deref(), &measure::temperature_csv(&rows))
+        }
+        Some(Command::Report { kind: ReportKind::Pairs { path, out } }) => run_pairs_report(path, out.as_deref()),
+        Some(Command::Report { kind: ReportKind::Witness { path, out } }) => {
+            let timestamps = Config::load(path)?.timestamp_parser()?;
+            let groups = fs_ops::load_group_records(path)?;
+            let tags = fs_ops::load_tag_records(path)?;
+            // 同じ写真が両方にあればグループ分けのレコード（撮影時刻のあるほう）を使う
+            let photos = groups
+                .iter()
+                .map(|(f, r)| (f, r.detected_text.as_str(), r.captured_at))
+                .chain(tags.iter().filter(|(f, _)| !groups.contains_key(*f)).map(|(f, r)| (f, r.detected_text.as_str(), None)));
+            let rows = witness::witness_rows(photos, timestamps.utc_offset_secs());
+            write_output(out.as_deref(), &witness::witness_csv(&rows))
+        }
+        Some(Command::Validate { path, strict }) => {
+            let config = Config::load(path)?;
+            let offset = config.timestamp_parser()?.utc_offset_secs();
+            let hours = config.working_hours(path)?;
+            let machine_sets = config.machine_sets(path)?;
+            let records = fs_ops::load_group_records(path)?;
+            let images = fs_ops::collect_images_flat(path);
+            let issues =
+                validate::validate_group_records(path, &records, &images, offset, hours.as_ref(), &machine_sets);
+            report_issues(&issues, *strict);
+            Ok(())
+        }
+        Some(Command::Caption { path, template, ai_captions, dry_run }) => {
+            let config = Config::load(path)?;
+            let offset = config.timestamp_parser()?.utc_offset_secs();
+            let template = template.as_deref().unwrap_or(&config.caption.template);
+            let mut records = fs_ops::load_group_records(path)?;
+            let previous = records.clone();
+            let changed = caption::fill_captions(&mut records, template, *ai_captions, offset);
+            for (fname, rec) in &records {
+                println!("  {fname}: {}", rec.caption);
+            }
+            println!("\n{changed} caption(s) changed.");
+            if *dry_run {
+                println!("(dry-run: no files saved)");
+            } else if changed > 0 {
+                fs_ops::save_group_records(path, &records)?;
+                history::append_history(path, &previous, &records, history::SOURCE_CAPTION)?;
+            }
+            Ok(())
+        }
+        Some(Command::Lock { path, targets }) => run_lock(path, targets, true),
+        Some(Command::Unlock { path, targets }) => run_lock(path, targets, false),
+        Some(Command::Groups { action: GroupsAction::List { path, status, camera } }) => {
+            run_groups_list(path, status.map(ReviewStatus::from), camera.as_deref())
+        }
+        Some(Command::Groups { action: GroupsAction::Note { path, group, notes } }) => {
+            run_groups_update(path, *group, |records| set_group_notes(records, *group, notes))
+        }
+        Some(Command::Groups { action: GroupsAction::Status { path, group, status } }) => {
+            run_groups_update(path, *group, |records| set_group_status(records, *group, (*status).into()))
+        }
+        Some(Command::Backup { path, list }) => {
+            if *list {
+                for archive in backup::list_backups(path) {
+                    println!("{}", archive.display());
+                }
+            } else {
+                let offset = Config::load(path)?.timestamp_parser()?.utc_offset_secs();
+                println!("Wrote {}", backup::create_backup(path, offset)?.display());
+            }
+            Ok(())
+        }
+        Some(Command::Restore { path, archive }) => run_restore(path, archive),
+        Some(Command::Manifest { path, algorithm, out, verify }) => {
+            if *verify {
+                run_manifest_verify(path, out.as_deref())
+            } else {
+                run_manifest(path, (*algorithm).into(), out.as_deref())
+            }
+        }
+        Some(Command::Package { path, out, algorithm, no_manifest, dry_run }) => {
+            let manifest = (!*no_manifest).then(|| (*algorithm).into());
+            run_package(path, out, manifest, *dry_run)
+        }
+        Some(Command::Purge { path, older_than_days, list }) => run_purge(path, *older_than_days, *list),
+        Some(Command::History { path, file }) => run_history(path, file),
+        Some(Command::Measure { path, design, out }) => run_measure(path, design.as_deref(), out.as_deref()),
+        Some(Command::Verify { path, fix }) => run_verify(path, *fix),
+        Some(Command::Gc { path, dry_run, archive }) => run_gc(path, *dry_run, *archive),
+        Some(Command::Review { action: ReviewAction::Export { path, out } }) => {
+            let config = Config::load(path)?;
+            let offset = config.timestamp_parser()?.utc_offset_secs();
+            let hours = config.working_hours(path)?;
+            let machine_sets = config.machine_sets(path)?;
+            let records = fs_ops::load_group_records(path)?;
+            let images = fs_ops::collect_images_flat(path);
+            let rows = review::review_queue(path, &records, &images, offset, hours.as_ref(), &machine_sets);
+            if out.is_some() {
+                println!("{} row(s) to review.", rows.len());
             }
-            fname_to_tmp_group.insert(pair[1].clone(), current_group);
+            write_output(out.as_deref(), &review::review_csv(&rows))
+        }
+        Some(Command::Review { action: ReviewAction::Import { path, queue, dry_run } }) => {
+            run_review_import(path, queue, *dry_run)
+        }
+        Some(Command::Bench { sizes }) => run_bench(sizes),
+        Some(Command::Ab { path, prompt_a, prompt_b, sample, concurrent, out }) => {
+            let options = AbOptions { sample: *sample, concurrent: *concurrent, out: out.as_deref() };
+            run_ab(path, prompt_a.as_deref(), prompt_b, &options)
+        }
+        Some(Command::Eval { path, golden, live, prompt, concurrent, out }) => {
+            let live = live.then_some(LiveEval { prompt: prompt.as_deref(), concurrent: *concurrent });
+            run_eval(path, golden, live, out.as_deref())
+        }
+        Some(Command::Skew { path }) => run_skew(path),
+        Some(Command::Estimate { path, mode, categories }) => run_estimate(path, *mode, categories.as_deref()),
+        Some(Command::Project { action: ProjectAction::Stats { root, out } }) => run_project_stats(root, out.as_deref()),
+        Some(Command::Project { action: ProjectAction::Safety { root, out } }) => run_safety_albums(root, out),
+        None => {
+            let path = cli.path.as_deref().expect("clap requires <PATH> without a subcommand");
+            run_group(&cli, path)
         }
     }
+}
 
-    segment_heads.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
-    let mut compact_map: HashMap<u32, u32> = HashMap::new();
-    for (idx, (_, _, tmp)) in segment_heads.iter().enumerate() {
-        compact_map.insert(*tmp, (idx + 1) as u32);
+fn run_batch(root: &Path, jobs: usize, concurrent: usize) -> Result<()> {
+    let folders = project::discover_dated_folders(root)?;
+    if folders.is_empty() {
+        println!("No dated subfolders found in {}", root.display());
+        return Ok(());
     }
+    println!("{} folder(s), {} job(s)\n", folders.len(), jobs.max(1));
+
+    let run_one = |folder: &PathBuf| -> FolderReport {
+        let result = PhotoTagger::builder()
+            .folder(folder)
+            .batch_size(BATCH_SIZE)
+            .concurrency(concurrent.max(1))
+            .build()
+            .and_then(|tagger| tagger.group());
+        let report = match result {
+            Ok(records) => FolderReport::from_records(folder, &records),
+            Err(e) => FolderReport::failed(folder, e.to_string()),
+        };
+        match &report.error {
+            None => println!("  {}: {} photos, {} groups", report.folder, report.photos, report.groups),
+            Some(e) => eprintln!("  {}: error: {e}", report.folder),
+        }
+        report
+    };
+
+    let reports: Vec<FolderReport> = folders
+        .chunks(jobs.max(1))
+        .flat_map(|wave| {
+            thread::scope(|s| {
+                let handles: Vec<_> = wave.iter().map(|f| s.spawn(|| run_one(f))).collect();
+                handles
+                    .into_iter()
+                    .map(|h| h.join().expect("folder thread panicked"))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let report = ProjectReport::new(reports);
+    let saved = report.save(root)?;
+    println!(
+        "\n{} photos, {} groups in {} folder(s) ({} failed)",
+        report.total_photos,
+        report.total_groups,
+        report.folders.len(),
+        report.failed_folders
+    );
+    println!("Report: {}", saved.display());
+    Ok(())
+}
+
+/// 生成した文書を --out があればファイルに、無ければ標準出力に書く
+fn is_xlsx(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xlsx"))
+}
+
+fn write_sheets(out: &Path, sheets: &[xlsx::Sheet]) -> Result<()> {
+    xlsx::write_xlsx(out, sheets)?;
+    println!("Wrote {}", out.display());
+    Ok(())
+}
 
-    for (fname, rec) in records.iter_mut() {
-        if let Some(tmp) = fname_to_tmp_group.get(fname) {
-            rec.group = *compact_map.get(tmp).unwrap_or(tmp);
-        } else {
-            rec.group = 0;
+fn write_output(out: Option<&Path>, content: &str) -> Result<()> {
+    match out {
+        Some(p) => {
+            std::fs::write(p, content)?;
+            println!("Wrote {}", p.display());
         }
+        None => print!("{content}"),
     }
+    Ok(())
+}
+
+/// プロンプトのテンプレートを読む。`{files}` が無いとモデルにファイル名が渡らないのでエラーにする。
+fn load_prompt_template(file: &Path) -> Result<String> {
+    let template = std::fs::read_to_string(file)?;
+    if !template.contains(domain::PROMPT_FILES_PLACEHOLDER) {
+        anyhow::bail!("{} has no {} placeholder for the file names", file.display(), domain::PROMPT_FILES_PLACEHOLDER);
+    }
+    Ok(template)
 }
 
-fn has_attachment_hint(rec: &GroupRecord) -> bool {
-    rec.machine_id.contains("取付")
-        || rec.detected_text.contains("取付")
+struct AbOptions<'a> {
+    sample: usize,
+    concurrent: usize,
+    out: Option<&'a Path>,
 }
 
-fn extract_no(text: &str) -> Option<String> {
-    for marker in ["No.", "No ", "NO.", "NO "] {
-        if let Some(pos) = text.find(marker) {
-            let rest = &text[pos + marker.len()..];
-            let digits: String = rest
-                .chars()
-                .skip_while(|c| !c.is_ascii_digit())
-                .take_while(|c| c.is_ascii_digit())
+/// 同じ写真を A・B 2 つのプロンプトで分類し、項目ごとの一致率と違いを出す。レコードは保存しない。
+fn run_ab(path: &Path, prompt_a: Option<&Path>, prompt_b: &Path, options: &AbOptions) -> Result<()> {
+    let template_a = prompt_a.map(load_prompt_template).transpose()?;
+    let template_b = load_prompt_template(prompt_b)?;
+    let timestamps = Config::load(path)?.timestamp_parser()?;
+    let entries = fs_ops::collect_image_entries(path, meta::DEFAULT_SCAN_WORKERS);
+    let images: Vec<PathBuf> = entries.iter().map(|e| e.path.clone()).collect();
+    if images.is_empty() {
+        println!("No images found in {}", path.display());
+        return Ok(());
+    }
+    let images = if options.sample == 0 {
+        images
+    } else {
+        let capture_times = meta::scan_capture_times(path, &entries, &timestamps, false);
+        let offset = timestamps.utc_offset_secs();
+        sample::sample_images(&images, options.sample, &capture_times, offset, SampleStrategy::TimeOfDay)
+    };
+
+    let batches: Vec<&[PathBuf]> = images.chunks(BATCH_SIZE).collect();
+    eprintln!("Comparing {} image(s) in {} batch(es), each sent with prompt A and B", images.len(), batches.len());
+    let classify = |template: Option<&str>, batch: &[PathBuf]| match template {
+        Some(t) => domain::classify_group_batch_with_template(&GeminiBackend, batch, t),
+        None => classify_group_batch(batch, None),
+    };
+    let mut results: [BTreeMap<String, domain::GroupItem>; 2] = Default::default();
+    for (wave_no, wave) in batches.chunks(options.concurrent.max(1)).enumerate() {
+        let outcomes: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = wave
+                .iter()
+                .flat_map(|batch| {
+                    let a = s.spawn(|| classify(template_a.as_deref(), batch));
+                    let b = s.spawn(|| classify(Some(&template_b), batch));
+                    [(0, a), (1, b)]
+                })
                 .collect();
-            if !digits.is_empty() {
-                return Some(format!("No.{}", digits));
+            handles.into_iter().map(|(side, h)| (side, h.join().expect("batch thread panicked"))).collect()
+        });
+        for (i, (side, outcome)) in outcomes.into_iter().enumerate() {
+            match outcome {
+                Ok(items) => results[side].extend(items),
+                Err(e) => {
+                    let batch_num = wave_no * options.concurrent.max(1) + i / 2 + 1;
+                    eprintln!("  Batch {batch_num} ({}) error: {e}", ["A", "B"][side]);
+                }
             }
         }
     }
-    None
+
+    let files: Vec<String> = images.iter().filter_map(|p| fs_ops::image_key(p)).collect();
+    let [a, b] = &results;
+    let report = ab::compare(&files, a, b);
+    let label = |p: Option<&Path>| p.map_or("built-in prompt".to_string(), |p| p.display().to_string());
+    write_output(options.out, &report.to_markdown(&label(prompt_a), &label(Some(prompt_b))))
+}
+
+struct LiveEval<'a> {
+    prompt: Option<&'a Path>,
+    concurrent: usize,
 }
 
-fn normalize_machine_id(rec: &mut GroupRecord) {
-    let merged = format!("{} {}", rec.detected_text, rec.description);
-    if merged.contains("取付") {
-        if let Some(no) = extract_no(&merged).or_else(|| extract_no(&rec.machine_id)) {
-            rec.machine_id = format!("取付道路 {}", no);
+/// 正解データと分類結果を項目ごとに比べる。live なら正解データの写真を分類し直す（レコードは保存しない）。
+fn run_eval(path: &Path, golden: &Path, live: Option<LiveEval>, out: Option<&Path>) -> Result<()> {
+    let entries = eval::load_golden(golden)?;
+    let (records, source) = match live {
+        None => (fs_ops::load_group_records(path)?, format!("{} (saved)", path.join("photo-groups.json").display())),
+        Some(live) => {
+            let template = live.prompt.map(load_prompt_template).transpose()?;
+            let images: Vec<PathBuf> =
+                entries.iter().map(|e| path.join(&e.file)).filter(|p| p.is_file()).collect();
+            let batches: Vec<&[PathBuf]> = images.chunks(BATCH_SIZE).collect();
+            eprintln!("Classifying {} golden image(s) in {} batch(es)", images.len(), batches.len());
+            let mut records = GroupRecords::new();
+            for wave in batches.chunks(live.concurrent.max(1)) {
+                let outcomes: Vec<_> = thread::scope(|s| {
+                    let handles: Vec<_> = wave
+                        .iter()
+                        .map(|batch| {
+                            s.spawn(|| match template.as_deref() {
+                                Some(t) => domain::classify_group_batch_with_template(&GeminiBackend, batch, t),
+                                None => classify_group_batch(batch, None),
+                            })
+                        })
+                        .collect();
+                    handles.into_iter().map(|h| h.join().expect("batch thread panicked")).collect()
+                });
+                for outcome in outcomes {
+                    match outcome {
+                        Ok(items) => records.extend(items.into_iter().map(|(f, item)| (f, GroupRecord::from(item)))),
+                        Err(e) => eprintln!("  Batch error: {e}"),
+                    }
+                }
+            }
+            let prompt = live.prompt.map_or("built-in prompt".to_string(), |p| p.display().to_string());
+            (records, format!("live ({prompt})"))
         }
-    }
+    };
+    write_output(out, &eval::evaluate(&entries, &records).to_markdown(&source))
 }
 
-fn collect_capture_times(images: &[PathBuf]) -> HashMap<String, i64> {
-    let mut out = HashMap::new();
-    for p in images {
-        let fname = p
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-        if fname.is_empty() {
-            continue;
+/// 未処理の写真を本番と同じバッチ・プロンプトで送った場合のリクエスト数・トークン数・費用を表示する
+fn run_estimate(path: &Path, mode: EstimateMode, categories: Option<&Path>) -> Result<()> {
+    let config = Config::load(path)?;
+    let images = fs_ops::collect_images_flat(path);
+    let force = force_reclassify_enabled();
+    let (pending, est) = match mode {
+        EstimateMode::Group => {
+            let records = fs_ops::load_group_records(path)?;
+            let pending = pending_images(&images, &records, force);
+            let entries = fs_ops::collect_image_entries(path, meta::DEFAULT_SCAN_WORKERS);
+            let capture_times = meta::scan_capture_times(path, &entries, &config.timestamp_parser()?, false);
+            let batches = time_batches(&pending, &capture_times, BATCH_SIZE);
+            let prompt = |names: &[&str]| domain::group_prompt(names, None);
+            (pending.len(), estimate::estimate_batches(&batches, prompt, estimate::GROUP_OUTPUT_TOKENS_PER_IMAGE))
         }
-        let ts = std::fs::metadata(p)
-            .ok()
-            .and_then(|m| m.modified().ok())
-            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-            .map(|d| d.as_secs() as i64);
-        if let Some(v) = ts {
-            out.insert(fname, v);
+        EstimateMode::Tag => {
+            let records = fs_ops::load_tag_records(path)?;
+            let list = categories.map(Categories::load).transpose()?.unwrap_or_default();
+            let index = fs_ops::KeyIndex::new(records.keys());
+            let pending: Vec<PathBuf> = images
+                .iter()
+                .filter(|img| {
+                    let Some(key) = fs_ops::image_key(img) else { return false };
+                    force || index.find(&records, &key).is_none()
+                })
+                .cloned()
+                .collect();
+            let batches: Vec<Vec<PathBuf>> = pending.chunks(BATCH_SIZE).map(|c| c.to_vec()).collect();
+            let prompt = |names: &[&str]| tag::tag_prompt(names, &list);
+            (pending.len(), estimate::estimate_batches(&batches, prompt, estimate::TAG_OUTPUT_TOKENS_PER_IMAGE))
         }
+    };
+    if pending == 0 {
+        println!("Nothing pending in {} ({} image(s) already classified).", path.display(), images.len());
+        return Ok(());
     }
-    out
+    println!("Pending: {pending} image(s) in {} request(s) (up to {BATCH_SIZE} per batch)", est.requests);
+    println!(
+        "Input tokens:  ~{} (images {}, prompts {})",
+        est.input_tokens(),
+        est.image_tokens,
+        est.prompt_tokens
+    );
+    println!("Output tokens: ~{}", est.output_tokens);
+    if est.unknown_size > 0 {
+        let (w, h) = estimate::FALLBACK_IMAGE_SIZE;
+        println!("  ({} image(s) with unreadable size counted as {w}x{h})", est.unknown_size);
+    }
+    println!("Estimated cost (USD):");
+    for model in &config.estimate.models {
+        println!("  {:<24} ${:.4}", model.name, est.cost(model));
+    }
+    Ok(())
 }
 
-fn apply_capture_times(records: &mut GroupRecords, capture_times: &HashMap<String, i64>) {
-    for (fname, rec) in records.iter_mut() {
-        normalize_machine_id(rec);
-        if rec.captured_at.is_none() {
-            if let Some(ts) = capture_times.get(fname) {
-                rec.captured_at = Some(*ts);
+/// 日付フォルダのレコードをまとめて集計する。レコードが読めないフォルダは警告して飛ばす。
+fn run_project_stats(root: &Path, out: Option<&Path>) -> Result<()> {
+    let folders = project::discover_dated_folders(root)?;
+    if folders.is_empty() {
+        println!("No dated subfolders found in {}", root.display());
+        return Ok(());
+    }
+    let mut inputs = Vec::new();
+    for folder in &folders {
+        let name = folder.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let Some(date) = project::parse_folder_date(&name) else { continue };
+        let loaded = Config::load(folder).and_then(|c| {
+            let checks = (c.working_hours(folder)?, c.machine_sets(folder)?);
+            Ok((c.timestamp_parser()?.utc_offset_secs(), checks, fs_ops::load_group_records(folder)?))
+        });
+        let (utc_offset_secs, (hours, machine_sets), records) = match loaded {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Warning: skipping {name}: {e}");
+                continue;
             }
+        };
+        let images = fs_ops::collect_images_flat(folder);
+        let issues =
+            validate::validate_group_records(folder, &records, &images, utc_offset_secs, hours.as_ref(), &machine_sets);
+        inputs.push(project::FolderRecords { folder: name, date, records, issues, utc_offset_secs });
+    }
+    inputs.sort_by_key(|f| f.date);
+    write_output(out, &project::project_stats(&inputs).to_markdown())
+}
+
+fn run_safety_albums(root: &Path, out: &Path) -> Result<()> {
+    let mut inputs = Vec::new();
+    for folder in project::discover_dated_folders(root)? {
+        let name = folder.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let Some(date) = project::parse_folder_date(&name) else { continue };
+        let loaded = Config::load(&folder)
+            .and_then(|c| Ok((c.timestamp_parser()?.utc_offset_secs(), fs_ops::load_group_records(&folder)?)));
+        let (utc_offset_secs, records) = match loaded {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Warning: skipping {name}: {e}");
+                continue;
+            }
+        };
+        inputs.push(project::FolderRecords { folder: name, date, records, issues: Vec::new(), utc_offset_secs });
+    }
+    let weeks = safety::weekly_albums(&inputs);
+    if weeks.is_empty() {
+        println!("No safety photos found in {}", root.display());
+        return Ok(());
+    }
+    std::fs::create_dir_all(out)?;
+    let root_href = relative_href(out, root)?;
+    let project_name = root.canonicalize()?.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    for week in &weeks {
+        let file = out.join(week.file_name());
+        std::fs::write(&file, safety::album_html(week, &project_name, &root_href))?;
+        println!("Wrote {} ({} photo(s))", file.display(), week.photos.len());
+    }
+    Ok(())
+}
+
+/// dir に置いたページから target を参照するパス。dir が target の中なら "../.."、そうでなければ file: URL。
+fn relative_href(dir: &Path, target: &Path) -> Result<String> {
+    let (dir, target) = (dir.canonicalize()?, target.canonicalize()?);
+    if let Ok(inner) = dir.strip_prefix(&target) {
+        let depth = inner.components().count();
+        return Ok(if depth == 0 { ".".to_string() } else { vec![".."; depth].join("/") });
+    }
+    // Windows の canonicalize は \\?\ 付きのパスを返す
+    let path = target.to_string_lossy().replace('\\', "/");
+    Ok(format!("file:///{}", path.trim_start_matches("//?/").trim_start_matches('/')))
+}
+
+fn run_pairs_report(path: &Path, out: Option<&Path>) -> Result<()> {
+    let timestamps = Config::load(path)?.timestamp_parser()?;
+    let records = fs_ops::load_group_records(path)?;
+    let report = pairing::pair_before_after(&records);
+    write_output(out, &pairing::pair_csv(&report, timestamps.utc_offset_secs()))?;
+    eprintln!("{} pair(s), {} unpaired photo(s).", report.pairs, report.unpaired);
+    for r in report.rows.iter().filter(|r| r.pair.is_none()) {
+        let place = if r.location.is_empty() { "(no station or board text)" } else { r.location.as_str() };
+        eprintln!("  unpaired {}: {} {place}", r.phase.label(), r.file);
+    }
+    Ok(())
+}
+
+fn run_skew(path: &Path) -> Result<()> {
+    let timestamps = Config::load(path)?.timestamp_parser()?;
+    let records = fs_ops::load_group_records(path)?;
+    let entries = fs_ops::collect_image_entries(path, meta::DEFAULT_SCAN_WORKERS);
+    let scanned = meta::scan_entries(&entries, &meta::load_meta_cache(path), meta::DEFAULT_SCAN_WORKERS);
+    let report = skew::estimate_skews(&records, &meta::cameras(&scanned), &meta::capture_times(&scanned, &timestamps));
+    if report.reference.is_empty() {
+        println!("No photos with EXIF camera information.");
+        return Ok(());
+    }
+    println!("Reference camera: {} ({} photo(s))", report.reference, report.reference_photos);
+    let mut suggested = Vec::new();
+    for c in &report.cameras {
+        match c.offset_secs {
+            Some(secs) => {
+                let offset = timestamp::format_clock_offset(secs);
+                println!("  {}: {offset} from {} shared board(s), {} photo(s)", c.camera, c.shared_boards, c.photos);
+                if secs != 0 {
+                    suggested.push(format!("--time-offset \"{}={offset}\"", c.camera));
+                }
+            }
+            None => println!("  {}: no board shared with the reference, {} photo(s)", c.camera, c.photos),
         }
     }
-    propagate_attachment_by_time(records);
+    if !suggested.is_empty() {
+        println!("\nSuggested: photo-tagger {} {}", path.display(), suggested.join(" "));
+    }
+    Ok(())
 }
 
-fn propagate_attachment_by_time(records: &mut GroupRecords) {
-    let mut by_no: HashMap<String, Vec<String>> = HashMap::new();
-    for (fname, rec) in records.iter() {
-        if let Some(no) = extract_no(&rec.machine_id)
-            .or_else(|| extract_no(&rec.detected_text))
-            .or_else(|| extract_no(&rec.description))
-        {
-            by_no.entry(no).or_default().push(fname.clone());
+fn run_daily_report(path: &Path, date: (i32, u32, u32), out: Option<&Path>) -> Result<()> {
+    let timestamps = Config::load(path)?.timestamp_parser()?;
+    let records = fs_ops::load_group_records(path)?;
+    let report = report::daily_report(&records, date, timestamps.utc_offset_secs());
+    match out.filter(|p| is_xlsx(p)) {
+        Some(p) => write_sheets(p, &report.to_sheets()),
+        None => write_output(out, &report.to_markdown()),
+    }
+}
+
+/// 問題を表示し、strict なら優先度の最も高い問題の終了コードで終了する
+fn report_issues(issues: &[Issue], strict: bool) {
+    if issues.is_empty() {
+        println!("\nNo issues found.");
+        return;
+    }
+    println!("\n--- Issues ({}) ---", issues.len());
+    for issue in issues {
+        println!("  [{}] {}: {}", issue.kind.label(), issue.subject, issue.detail);
+    }
+    if strict {
+        if let Some(code) = validate::strict_exit_code(issues) {
+            std::process::exit(code);
         }
     }
+}
 
-    for (no, mut files) in by_no {
-        files.sort_by(|a, b| {
-            let ra = &records[a];
-            let rb = &records[b];
-            ra.captured_at
-                .unwrap_or(i64::MAX)
-                .cmp(&rb.captured_at.unwrap_or(i64::MAX))
-                .then(a.cmp(b))
+/// --categories / --learn-categories / --aliases からカテゴリ一覧を組み立てる
+fn tag_categories(path: &Path, file: Option<&Path>, learn: bool, aliases: Option<&Path>) -> Result<Categories> {
+    let mut list = Categories::default();
+    if let Some(file) = file {
+        list.merge(Categories::load(file)?);
+    }
+    if learn {
+        let learned = Categories::from_subdirs(path)?;
+        println!("Learned {} categories from subfolders: {}", learned.names.len(), learned.names.join(", "));
+        list.merge(learned);
+    }
+    if let Some(file) = aliases {
+        list.load_aliases(file)?;
+    }
+    if list.is_empty() {
+        anyhow::bail!("no categories: give --categories, or run --learn-categories on a folder with subfolders");
+    }
+    Ok(list)
+}
+
+struct TagOptions {
+    move_files: bool,
+    dry_run: bool,
+    concurrent: usize,
+    min_confidence: Option<f32>,
+    record_format: Option<RecordFormat>,
+    git_commit: bool,
+}
+
+/// --record-format があればその形式、無ければ既存ファイルと同じ形式で保存する
+fn save_tag_records(path: &Path, records: &tag::TagRecords, format: Option<RecordFormat>) -> photo_tagger::Result<()> {
+    match format {
+        Some(format) => fs_ops::save_tag_records_as(path, records, format),
+        None => fs_ops::save_tag_records(path, records),
+    }
+}
+
+fn run_tag(path: &Path, categories: &Categories, options: &TagOptions) -> Result<()> {
+    let &TagOptions { move_files, dry_run, concurrent, min_confidence, record_format, git_commit } = options;
+    let mut records = fs_ops::load_tag_records(path)?;
+    let images = fs_ops::collect_images_flat(path);
+    let index = fs_ops::KeyIndex::new(records.keys());
+    let pending: Vec<PathBuf> = images
+        .iter()
+        .filter(|img| {
+            let Some(key) = fs_ops::image_key(img) else { return false };
+            force_reclassify_enabled() || index.find(&records, &key).is_none()
+        })
+        .cloned()
+        .collect();
+    let unreadable = warn_non_utf8(&images);
+    if images.len() > pending.len() + unreadable {
+        println!("Skipping {} already tagged.", images.len() - pending.len() - unreadable);
+    }
+
+    let batches: Vec<&[PathBuf]> = pending.chunks(BATCH_SIZE).collect();
+    let num_batches = batches.len();
+    let mut breaker = CircuitBreaker::new(DEFAULT_BREAKER_WINDOW, DEFAULT_BREAKER_ERROR_RATE);
+    let config = Config::load(path)?;
+    // 設定の誤りは AI を呼ぶ前に知らせる
+    let layout = config.output_layout(path)?;
+    let hooks = if dry_run { Hooks::default() } else { Hooks::from_config(&config.hooks, path) };
+    let folder = path.display().to_string();
+    let mut tagged: Vec<String> = Vec::new();
+    let mut errors = 0;
+    let mut aborted = None;
+    for (wave_no, wave) in batches.chunks(concurrent.max(1)).enumerate() {
+        let results: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = wave
+                .iter()
+                .map(|batch| s.spawn(move || tag::classify_tag_batch_with(&GeminiBackend, batch, categories)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("batch thread panicked"))
+                .collect()
         });
-        if files.is_empty() {
-            continue;
+        for (i, result) in results.into_iter().enumerate() {
+            let batch_num = wave_no * concurrent.max(1) + i + 1;
+            match result {
+                Ok(items) => {
+                    for (fname, item) in items {
+                        let mut rec = TagRecord::from(item);
+                        if let Some(min) = min_confidence {
+                            rec.apply_min_confidence(min);
+                        }
+                        rec.apply_witness_routing();
+                        println!("  [B{batch_num}/{num_batches}] {fname} -> {}", rec.tag);
+                        tagged.push(fname.clone());
+                        records.insert(fname, rec);
+                    }
+                    breaker.record(None);
+                }
+                Err(e) => {
+                    eprintln!("  Batch {batch_num} error: {e}");
+                    errors += wave[i].len();
+                    breaker.record(Some(e.to_string()));
+                }
+            }
+        }
+        if !dry_run {
+            save_tag_records(path, &records, record_format)?;
+        }
+        if breaker.is_open() {
+            aborted = Some(breaker.cause());
+            break;
+        }
+    }
+    let emit_hooks = |records: &tag::TagRecords, aborted: Option<&str>| {
+        let done = tagged.iter().filter_map(|k| records.get_key_value(k));
+        let mut hook_errors = hooks.emit_records(&folder, "tag", done);
+        hook_errors.extend(hooks.emit(&HookEvent::RunEnd {
+            folder: &folder,
+            mode: "tag",
+            images: images.len(),
+            classified: tagged.len(),
+            errors,
+            aborted,
+        }));
+        warn_hook_errors(hook_errors);
+    };
+    if let Some(cause) = aborted {
+        emit_hooks(&records, Some(&cause));
+        anyhow::bail!("run stopped by the error-rate circuit breaker: {cause}");
+    }
+
+    // しきい値を変えて再実行したときは既存レコードも振り分け直す。立会の振り分けは以前のレコードにも当てる
+    let before = records.clone();
+    for rec in records.values_mut() {
+        if let Some(min) = min_confidence {
+            rec.apply_min_confidence(min);
         }
+        rec.apply_witness_routing();
+    }
+    if !dry_run && records.iter().zip(&before).any(|((_, a), (_, b))| a.tag != b.tag) {
+        save_tag_records(path, &records, record_format)?;
+    }
 
-        let mut chunk: Vec<String> = vec![files[0].clone()];
-        for pair in files.windows(2) {
-            let prev = &records[&pair[0]];
-            let curr = &records[&pair[1]];
-            let prev_ts = prev.captured_at.unwrap_or(i64::MAX);
-            let curr_ts = curr.captured_at.unwrap_or(i64::MAX);
-            let gap = if prev_ts == i64::MAX || curr_ts == i64::MAX {
-                0
-            } else {
-                (curr_ts - prev_ts).abs()
-            };
-            if gap > GROUP_GAP_SECS {
-                apply_attach_to_chunk(records, &chunk, &no);
-                chunk.clear();
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for rec in records.values() {
+        *counts.entry(rec.tag.as_str()).or_default() += 1;
+    }
+    println!("\n--- Tags ({} photos) ---", records.len());
+    for (tag, n) in &counts {
+        println!("  {tag}: {n}");
+    }
+    let stats = tag::confidence_stats(&records, min_confidence);
+    if stats.values().any(|s| s.scored > 0) {
+        println!("\n--- Confidence by category ---");
+        for (category, s) in stats.iter().filter(|(_, s)| s.scored > 0) {
+            print!(
+                "  {category}: n={} min={:.2} mean={:.2} max={:.2}",
+                s.scored, s.min, s.mean, s.max
+            );
+            if min_confidence.is_some() {
+                print!(" below={}", s.below_threshold);
+            }
+            println!();
+        }
+    }
+
+    if move_files {
+        let timestamps = config.timestamp_parser()?;
+        // 役割・作業名・撮影日は photo-groups.json から（無ければ撮影日だけファイル名から）
+        let groups = fs_ops::load_group_records(path)?;
+        let mut moved = 0;
+        for (fname, rec) in &records {
+            if !path.join(fname).is_file() {
+                continue;
+            }
+            let group = groups.get(fname);
+            let date = group
+                .and_then(|g| g.captured_at)
+                .map(|ts| timestamp::LocalDateTime::from_epoch(ts, timestamps.utc_offset_secs()).date())
+                .or_else(|| timestamps.parse_local(fname).map(|at| at.date()));
+            let fields = layout::LayoutFields { tag: &rec.tag, text: &rec.detected_text, group, date };
+            let dir = layout.render(&fields);
+            if dry_run {
+                println!("  would move {fname} -> {}/", dir.display());
+                continue;
+            }
+            let mark = layout.uses_tag().then_some(rec.tag.as_str());
+            match tag::move_to_sorted_dir(path, fname, &dir, mark) {
+                Ok(_) => moved += 1,
+                Err(e) => eprintln!("  {e}"),
             }
-            chunk.push(pair[1].clone());
         }
-        if !chunk.is_empty() {
-            apply_attach_to_chunk(records, &chunk, &no);
+        if !dry_run {
+            println!("Moved {moved} photo(s).");
         }
     }
+    emit_hooks(&records, None);
+    if dry_run {
+        println!("\n(dry-run: no files saved or moved)");
+    } else if git_commit {
+        let mut params = vec![
+            ("concurrent".to_string(), concurrent.to_string()),
+            ("move".to_string(), move_files.to_string()),
+        ];
+        if let Some(min) = min_confidence {
+            params.push(("min_confidence".to_string(), min.to_string()));
+        }
+        commit_metadata(path, "tag", images.len(), tagged.len(), errors, params)?;
+    }
+    Ok(())
+}
+
+/// --git-commit: 実行結果をコミットメッセージにしてメタデータをコミットする
+fn commit_metadata(
+    path: &Path,
+    mode: &str,
+    images: usize,
+    classified: usize,
+    errors: usize,
+    params: Vec<(String, String)>,
+) -> Result<()> {
+    let run = git::RunCommit { mode: mode.to_string(), images, classified, errors, params };
+    match git::commit_metadata(path, &run)? {
+        Some(hash) => println!("Committed metadata ({hash})."),
+        None => println!("No metadata changes to commit."),
+    }
+    Ok(())
 }
 
-fn apply_attach_to_chunk(records: &mut GroupRecords, chunk: &[String], no: &str) {
-    let has_attach = chunk
+/// --git-commit のメッセージに書くグループ分けの設定
+fn group_commit_params(cli: &Cli) -> Vec<(String, String)> {
+    let mut params: Vec<(String, String)> =
+        remembered_settings(cli).into_iter().map(|(name, value)| (name, value.to_string())).collect();
+    params.push(("renumber".to_string(), cli.renumber.to_string()));
+    if !cli.camera.is_empty() {
+        params.push(("camera".to_string(), cli.camera.join(", ")));
+    }
+    params
+}
+
+fn run_lock(path: &Path, targets: &[String], locked: bool) -> Result<()> {
+    let mut records = fs_ops::load_group_records(path)?;
+    let previous = records.clone();
+    let changed = set_locked(&mut records, targets, locked)?;
+    if changed > 0 {
+        fs_ops::save_group_records(path, &records)?;
+        history::append_history(path, &previous, &records, history::SOURCE_LOCK)?;
+    }
+    let verb = if locked { "Locked" } else { "Unlocked" };
+    println!("{verb} {changed} record(s).");
+    Ok(())
+}
+
+fn run_groups_list(path: &Path, status: Option<ReviewStatus>, camera: Option<&str>) -> Result<()> {
+    let records = fs_ops::load_group_records(path)?;
+    let reviews = report::group_reviews(records.values());
+    let camera_groups: Option<HashSet<u32>> = camera.map(|name| {
+        records.values().filter(|r| r.camera.matches(name)).map(|r| r.group).collect()
+    });
+    let mut shown = 0;
+    for r in reviews
         .iter()
-        .any(|fname| records.get(fname).map(has_attachment_hint).unwrap_or(false));
-    if !has_attach {
-        return;
+        .filter(|r| status.is_none_or(|s| r.status == s))
+        .filter(|r| camera_groups.as_ref().is_none_or(|groups| groups.contains(&r.group)))
+    {
+        let notes = if r.notes.is_empty() { String::new() } else { format!(" - {}", r.notes) };
+        let cameras = if r.cameras.is_empty() { String::new() } else { format!(" @ {}", r.cameras.join(", ")) };
+        println!(
+            "  Group {}: {} ({}), {} photo(s){cameras} [{}]{notes}",
+            r.group,
+            r.machine_type,
+            r.machine_id,
+            r.photos,
+            r.status.label()
+        );
+        shown += 1;
     }
-    for fname in chunk {
-        if let Some(rec) = records.get_mut(fname) {
-            rec.machine_id = format!("取付道路 {}", no);
-        }
+    println!("{shown} of {} group(s).", reviews.len());
+    Ok(())
+}
+
+fn run_groups_update(
+    path: &Path,
+    group: u32,
+    update: impl FnOnce(&mut GroupRecords) -> photo_tagger::Result<usize>,
+) -> Result<()> {
+    let mut records = fs_ops::load_group_records(path)?;
+    let previous = records.clone();
+    let changed = update(&mut records)?;
+    if changed > 0 {
+        fs_ops::save_group_records(path, &records)?;
+        history::append_history(path, &previous, &records, history::SOURCE_GROUPS)?;
     }
+    println!("Updated group {group} ({changed} record(s) changed).");
+    Ok(())
 }
 
-fn print_group_summary(records: &GroupRecords) {
-    if records.is_empty() {
-        return;
+fn run_verify(path: &Path, fix: bool) -> Result<()> {
+    let groups = fs_ops::load_group_records(path)?;
+    let tags = fs_ops::load_tag_records(path)?;
+    let findings = verify::verify_folder(path, &groups, &tags);
+    if findings.is_empty() {
+        println!("Records and folder agree.");
+        return Ok(());
+    }
+    println!("--- Findings ({}) ---", findings.len());
+    for f in &findings {
+        let source = if f.source.is_empty() { String::new() } else { format!(" ({})", f.source) };
+        println!("  [{}] {}{source}: {}", f.kind.label(), f.file, f.detail);
+    }
+    if !fix {
+        return Ok(());
     }
 
-    let mut groups: HashMap<u32, Vec<(&String, &GroupRecord)>> = HashMap::new();
-    for (fname, rec) in records {
-        groups.entry(rec.group).or_default().push((fname, rec));
+    let removed = remove_dangling(path, groups, tags, &findings, false)?;
+    println!("Removed {} dangling record(s).", removed.len());
+    Ok(())
+}
+
+fn run_gc(path: &Path, dry_run: bool, archive: bool) -> Result<()> {
+    let groups = fs_ops::load_group_records(path)?;
+    let tags = fs_ops::load_tag_records(path)?;
+    let findings: Vec<verify::Finding> = verify::verify_folder(path, &groups, &tags)
+        .into_iter()
+        .filter(|f| f.kind == verify::FindingKind::DanglingRecord)
+        .collect();
+    if findings.is_empty() {
+        println!("No records for missing files.");
+        return Ok(());
     }
+    println!("--- Records for missing files ({}) ---", findings.len());
+    for f in &findings {
+        println!("  {} ({})", f.file, f.source);
+    }
+    if dry_run {
+        println!("\n(dry-run: no records removed)");
+        return Ok(());
+    }
+    let removed = remove_dangling(path, groups, tags, &findings, archive)?;
+    println!("Removed {} record(s).", removed.len());
+    Ok(())
+}
 
-    let mut group_nums: Vec<u32> = groups.keys().copied().collect();
-    group_nums.sort();
+/// verify --fix と gc で共通: ファイルの無いレコードを取り除き、バックアップ・グループ番号の振り直し・
+/// 削除の履歴を済ませて保存する。archive なら取り除いたレコードを .photo-tagger/archive/ にも残す。
+fn remove_dangling(
+    path: &Path,
+    mut groups: GroupRecords,
+    mut tags: tag::TagRecords,
+    findings: &[verify::Finding],
+    archive: bool,
+) -> Result<verify::RemovedRecords> {
+    let previous = groups.clone();
+    let removed = verify::drop_dangling(&mut groups, &mut tags, findings);
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+    let offset = Config::load(path)?.timestamp_parser()?.utc_offset_secs();
+    let saved = backup::create_backup(path, offset)?;
+    println!("\nState saved to {}", saved.display());
+    if archive {
+        println!("Removed records archived to {}", verify::archive_removed(path, &removed, offset)?.display());
+    }
+    if !removed.groups.is_empty() {
+        assign_groups_incremental(&mut groups, &previous);
+        fs_ops::save_group_records(path, &groups)?;
+    }
+    if !removed.tags.is_empty() {
+        fs_ops::save_tag_records(path, &tags)?;
+    }
+    history::append_removals(path, &removed.groups, &removed.tags, history::SOURCE_GC)?;
+    Ok(removed)
+}
 
-    println!("\n--- Summary ({} machines, {} photos) ---", group_nums.len(), records.len());
-    for g in group_nums {
-        let members = &groups[&g];
-        let machine_type = &members[0].1.machine_type;
-        let machine_id = &members[0].1.machine_id;
-        println!("  Group {g}: {machine_type} ({machine_id})");
-        for (fname, rec) in members {
-            println!("    - {fname}: {}", rec.role);
-        }
+fn run_restore(path: &Path, archive: &Path) -> Result<()> {
+    let archive = if archive.is_file() {
+        archive.to_path_buf()
+    } else {
+        let in_dir = backup::backup_dir(path).join(archive);
+        if in_dir.is_file() { in_dir } else { in_dir.with_extension("tar") }
+    };
+    if !archive.is_file() {
+        anyhow::bail!("backup not found: {}", archive.display());
+    }
+    // 設定が壊れていても戻せるよう、退避の時刻は既定のタイムゾーンで付ける
+    let offset = Config::load(path)
+        .and_then(|c| c.timestamp_parser())
+        .map(|p| p.utc_offset_secs())
+        .unwrap_or_else(|_| timestamp::TimestampParser::default().utc_offset_secs());
+    let saved = backup::create_backup(path, offset)?;
+    println!("Current state saved to {}", saved.display());
+    for rel in backup::restore_backup(path, &archive, offset)? {
+        println!("  restored {}", rel.display());
     }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let total_start = Instant::now();
-    let cli = Cli::parse();
+fn run_manifest(path: &Path, algorithm: manifest::HashAlgorithm, out: Option<&Path>) -> Result<()> {
+    let entries = manifest::build_manifest(path, algorithm)?;
+    let out = out.map_or_else(|| path.join(manifest::manifest_file_name(algorithm)), Path::to_path_buf);
+    std::fs::write(&out, manifest::manifest_text(&entries))?;
+    println!("Wrote {} ({} file(s))", out.display(), entries.len());
+    Ok(())
+}
 
-    let mut records = fs_ops::load_group_records(&cli.path);
+fn run_manifest_verify(path: &Path, manifest_path: Option<&Path>) -> Result<()> {
+    let manifest_path = match manifest_path {
+        Some(p) => p.to_path_buf(),
+        None => [manifest::HashAlgorithm::Sha256, manifest::HashAlgorithm::Md5]
+            .iter()
+            .map(|a| path.join(manifest::manifest_file_name(*a)))
+            .find(|p| p.is_file())
+            .ok_or_else(|| anyhow::anyhow!("no MANIFEST.sha256 or MANIFEST.md5 in {}", path.display()))?,
+    };
+    let (algorithm, entries) = manifest::parse_manifest(&std::fs::read_to_string(&manifest_path)?)?;
+    let problems = manifest::verify_manifest(path, algorithm, &entries)?;
+    if problems.is_empty() {
+        println!("All {} file(s) match {}.", entries.len(), manifest_path.display());
+        return Ok(());
+    }
+    println!("--- Problems ({}) ---", problems.len());
+    for p in &problems {
+        println!("  [{}] {}", p.kind.label(), p.path);
+    }
+    anyhow::bail!("{} does not match the folder", manifest_path.display())
+}
 
-    let t = Instant::now();
-    let images = fs_ops::collect_images_flat(&cli.path);
-    let capture_times = collect_capture_times(&images);
-    let collect_dur = t.elapsed();
+fn run_package(path: &Path, out: &Path, manifest: Option<manifest::HashAlgorithm>, dry_run: bool) -> Result<()> {
+    let config = Config::load(path)?;
+    let offset = config.timestamp_parser()?.utc_offset_secs();
+    let layout = config.package;
+    // 出力先がフォルダ内でも ZIP 自体は含めない
+    let out_dir = out.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let exclude: Vec<PathBuf> = match (out_dir.canonicalize(), path.canonicalize(), out.file_name()) {
+        (Ok(dir), Ok(base), Some(name)) if dir == base => vec![path.join(name)],
+        _ => Vec::new(),
+    };
+    let entries = package::plan_package(path, &layout, &exclude)?;
+    if entries.is_empty() {
+        anyhow::bail!("nothing to package in {}", path.display());
+    }
+    if dry_run {
+        for entry in &entries {
+            println!("  {}", entry.name);
+        }
+        println!("\n(dry-run: {} file(s), nothing written)", entries.len());
+        return Ok(());
+    }
+    package::write_package(out, &layout, &entries, manifest, offset)?;
+    println!("Wrote {} ({} file(s))", out.display(), entries.len());
+    Ok(())
+}
 
-    if images.is_empty() {
-        println!("No images found in {}", cli.path.display());
+fn run_purge(path: &Path, older_than_days: Option<u64>, list: bool) -> Result<()> {
+    if list {
+        let offset = Config::load(path)?.timestamp_parser()?.utc_offset_secs();
+        for (dir, manifest) in trash::list_trash(path) {
+            let at = timestamp::LocalDateTime::from_epoch(manifest.trashed_at, offset);
+            println!(
+                "{} ({} {}, {}, {} file(s))",
+                dir.display(),
+                at.date_string(),
+                at.time_string(),
+                manifest.reason,
+                manifest.files.len()
+            );
+            for file in &manifest.files {
+                println!("  {}", file.display());
+            }
+        }
         return Ok(());
     }
+    let purged = trash::purge_trash(path, older_than_days.map(|d| d as i64 * 86_400))?;
+    println!("Purged {} trash folder(s).", purged.len());
+    Ok(())
+}
 
-    let pending: Vec<_> = images
+fn run_history(path: &Path, file: &str) -> Result<()> {
+    let offset = Config::load(path)?.timestamp_parser()?.utc_offset_secs();
+    let entries = history::load_history(path, file)?;
+    if entries.is_empty() {
+        println!("No history for {file}");
+        return Ok(());
+    }
+    for entry in entries {
+        let at = timestamp::LocalDateTime::from_epoch(entry.at, offset);
+        let user = if entry.user.is_empty() { String::new() } else { format!(" by {}", entry.user) };
+        println!("{} {} {}{user}", at.date_string(), at.time_string(), entry.source);
+        for (field, [old, new]) in &entry.changes {
+            println!("    {field}: {old} -> {new}");
+        }
+    }
+    Ok(())
+}
+
+fn run_measure(path: &Path, design: Option<&Path>, out: Option<&Path>) -> Result<()> {
+    let records = fs_ops::load_group_records(path)?;
+    let design = match design {
+        Some(file) => measure::parse_design_csv(&std::fs::read_to_string(file)?)?,
+        None => Vec::new(),
+    };
+    let rows = measure::check_measurements(measure::extract_measurements(&records), &design);
+    let failed: Vec<_> = rows
         .iter()
-        .filter(|img| {
-            let name = img
-                .file_name()
-                .map(|n| n.to_string_lossy())
-                .unwrap_or_default();
-            !records.contains_key(name.as_ref())
-        })
-        .cloned()
+        .filter(|r| r.verdict == Some(measure::Verdict::OutOfTolerance))
         .collect();
+    write_output(out, &measure::measurements_csv(&rows))?;
+    if !failed.is_empty() {
+        eprintln!("\n--- Out of tolerance ({}) ---", failed.len());
+        for r in failed {
+            let m = &r.measurement;
+            eprintln!("  {} {} {}: {}{}", m.file, m.station, m.item, m.value, m.unit);
+        }
+    }
+    Ok(())
+}
+
+fn run_review_import(path: &Path, queue: &Path, dry_run: bool) -> Result<()> {
+    let text = std::fs::read_to_string(queue)?;
+    let decisions = review::parse_review_csv(&text)?;
+    let timestamps = Config::load(path)?.timestamp_parser()?;
+    let mut records = fs_ops::load_group_records(path)?;
+    let previous = records.clone();
 
-    let skip = images.len() - pending.len();
-    if skip > 0 {
-        println!("Skipping {skip} already grouped.");
+    let outcome = review::apply_review(&mut records, &decisions);
+    for file in &outcome.skipped {
+        eprintln!("  skipped {file}: no record; fill new_role, new_machine_type and new_machine_id");
     }
-    if pending.is_empty() {
-        println!("All {} images grouped.", images.len());
-        apply_capture_times(&mut records, &capture_times);
-        assign_groups(&mut records);
-        if !cli.dry_run {
-            fs_ops::save_group_records(&cli.path, &records)?;
+    println!("Applied {} correction(s).", outcome.applied);
+    if outcome.applied == 0 {
+        return Ok(());
+    }
+
+    let reviewed = records.clone();
+    let entries = fs_ops::collect_image_entries(path, meta::DEFAULT_SCAN_WORKERS);
+    let capture_times = meta::scan_capture_times(path, &entries, &timestamps, !dry_run);
+    apply_capture_times(&mut records, &capture_times);
+    assign_groups_incremental(&mut records, &previous);
+    if dry_run {
+        println!("(dry-run: no files saved)");
+    } else {
+        fs_ops::save_group_records(path, &records)?;
+        history::append_history(path, &previous, &reviewed, history::SOURCE_REVIEW)?;
+        history::append_history(path, &reviewed, &records, history::SOURCE_NORMALIZE)?;
+    }
+    print_group_summary(&records)?;
+    Ok(())
+}
+
+/// ファイル名が UTF-8 でない画像を一覧表示し、その件数を返す（分類の対象外になる）
+fn warn_non_utf8(images: &[PathBuf]) -> usize {
+    let unreadable: Vec<PathBuf> = fs_ops::non_utf8_images(images).into_iter().map(Path::to_path_buf).collect();
+    warn_unreadable(&unreadable);
+    unreadable.len()
+}
+
+fn warn_unreadable(unreadable: &[PathBuf]) {
+    if !unreadable.is_empty() {
+        eprintln!(
+            "warning: skipping {} file(s) whose names are not valid UTF-8; rename them to classify:",
+            unreadable.len()
+        );
+        for p in unreadable {
+            eprintln!("  {}", p.display());
         }
-        print_group_summary(&records);
+    }
+}
+
+fn run_bench(sizes: &[usize]) -> Result<()> {
+    let rows = bench::run_bench(sizes, &std::env::temp_dir())?;
+    println!("  {:<18} {:>8} {:>10}", "stage", "records", "time");
+    for row in rows {
+        println!("  {:<18} {:>8} {:>10}", row.stage, row.size, fmt_duration(row.elapsed));
+    }
+    Ok(())
+}
+
+fn run_group(cli: &Cli, path: &Path) -> Result<()> {
+    let total_start = Instant::now();
+    let max_concurrent = cli.concurrent.max(1);
+    let utc_offset_secs = Config::load(path)?.timestamp_parser()?.utc_offset_secs();
+    let offsets = timestamp::ClockOffsets::new(cli.time_offset.iter().filter_map(|s| time_offset_pair(s).ok()).collect());
+    let style = ProgressStyle {
+        sampling: cli.sample.is_some(),
+        parallel: if cli.auto_concurrency { format!("auto ≤{max_concurrent}") } else { max_concurrent.to_string() },
+        batch_ms: cli.profile,
+    };
+    let mut builder = PhotoTagger::builder()
+        .folder(path)
+        .batch_size(BATCH_SIZE)
+        .concurrency(max_concurrent)
+        .auto_concurrency(cli.auto_concurrency)
+        .scan_workers(cli.scan_workers)
+        .batch_context(cli.batch_context)
+        .flush_interval(Duration::from_secs(cli.flush_interval))
+        .trust_names(cli.trust_names)
+        .dry_run(cli.dry_run)
+        .renumber(cli.renumber)
+        .clock_offsets(offsets)
+        .cluster(cli.cluster)
+        .circuit_breaker(cli.error_window, cli.max_error_rate)
+        .progress(move |event| print_group_progress(event, &style));
+    if let Some(file) = &cli.vocabulary {
+        builder = builder.vocabulary(load_vocabulary(file)?);
+    }
+    if let Some(format) = cli.record_format {
+        builder = builder.record_format(format.into());
+    }
+    for camera in &cli.camera {
+        builder = builder.camera(camera);
+    }
+    if cli.text_filter {
+        builder = builder.text_filter(cli.min_text_score);
+    }
+    if let Some(n) = cli.sample {
+        let strategy = match cli.sample_by {
+            SampleBy::Time => SampleStrategy::TimeOfDay,
+            SampleBy::Random => SampleStrategy::Random { seed: clock_seed() },
+        };
+        builder = builder.sample(n, strategy);
+    }
+    let mut run = builder.build()?.run()?;
+
+    if run.images.is_empty() {
+        println!("No images found in {}", path.display());
         return Ok(());
     }
+    if cli.sample.is_some() {
+        return finish_sample(cli, &run);
+    }
 
-    let batches: Vec<Vec<PathBuf>> = pending.chunks(BATCH_SIZE).map(|c| c.to_vec()).collect();
-    let num_batches = batches.len();
-    println!(
-        "{} image(s) in {} batch(es) ({}枚/batch, {}並列)\n",
-        pending.len(),
-        num_batches,
-        BATCH_SIZE,
-        MAX_CONCURRENT
-    );
+    print_group_summary(&run.records)?;
+    if cli.dry_run {
+        println!("\n(dry-run: no files saved)");
+    }
 
-    let classify_start = Instant::now();
+    let total_dur = total_start.elapsed();
+    // 分類するものが無かった実行では所要時間を出さない
+    if run.pending > 0 {
+        print_profile(cli, path, &mut run.profile, total_dur)?;
+    }
 
-    for (chunk_idx, chunk) in batches.chunks(MAX_CONCURRENT).enumerate() {
-        let handles: Vec<_> = chunk
-            .iter()
-            .enumerate()
-            .map(|(i, batch)| {
-                let batch_num = chunk_idx * MAX_CONCURRENT + i + 1;
-                let batch = batch.clone();
-                thread::spawn(move || {
-                    eprintln!(
-                        "--- Batch {batch_num}/{num_batches} ({} images) ---",
-                        batch.len()
-                    );
-                    let start = Instant::now();
-                    let results = match classify_group_batch(&batch, None) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            eprintln!("  Batch {batch_num} error: {e}");
-                            Vec::new()
-                        }
-                    };
-                    let elapsed = start.elapsed();
-                    (batch_num, results, elapsed)
-                })
+    let failed: Vec<Issue> = run
+        .failed
+        .iter()
+        .flat_map(|f| {
+            f.files.iter().map(|file| Issue {
+                kind: IssueKind::ClassificationError,
+                subject: file.clone(),
+                detail: format!("batch {} failed", f.batch),
             })
-            .collect();
+        })
+        .collect();
+    let summary = RunSummary {
+        images: run.images.len(),
+        classified: run.classified.len(),
+        skipped: run.skipped,
+        unreadable: run.unreadable.len(),
+        errors: failed.clone(),
+        elapsed_secs: total_dur.as_secs_f64(),
+        ..Default::default()
+    };
+    if let Some(report) = &run.error_report {
+        println!("Error report written to {}", report.display());
+    }
+    write_run_summary(cli, path, summary, &run.previous, &run.records, &run.images, utc_offset_secs)?;
+    warn_hook_errors(std::mem::take(&mut run.hook_errors));
+    if let Some(cause) = &run.aborted {
+        anyhow::bail!("run stopped by the error-rate circuit breaker: {cause}");
+    }
+    save_last_run(cli, path)?;
+    if cli.git_commit && !cli.dry_run {
+        let classified = run.classified.len();
+        commit_metadata(path, "group", run.images.len(), classified, failed.len(), group_commit_params(cli))?;
+    }
+    warn_off_hours(path, &run.records, utc_offset_secs);
+    check_strict(cli, path, &run.records, &run.images, utc_offset_secs, failed);
+    Ok(())
+}
+
+/// --profile の内訳（無ければ所要時間だけ）を表示し、詳細を保存する
+fn print_profile(cli: &Cli, path: &Path, profile: &mut RunProfile, total_dur: Duration) -> Result<()> {
+    let stage = |name| Duration::from_secs_f64(profile.stages_ms.get(name).copied().unwrap_or(0.0) / 1000.0);
+    if !cli.profile {
+        println!("\nCompleted in {}.", fmt_duration(total_dur));
+        return Ok(());
+    }
+    println!("\n--- Profile ---");
+    println!("  {:<12} {:>8}", "collect:", fmt_duration(stage("scan") + stage("meta")));
+    println!("  {:<12} {:>8}", "  scan:", fmt_duration(stage("scan")));
+    println!("  {:<12} {:>8}", "  meta:", fmt_duration(stage("meta")));
+    println!("  {:<12} {:>8}", "classify:", fmt_duration(stage("classify")));
+    println!("  {:<12} {:>8}", "total:", fmt_duration(total_dur));
+    if !cli.dry_run {
+        profile.stage("total", total_dur);
+        profile.summarize();
+        let saved = profile.save(path)?;
+        println!("  (details: {})", saved.display());
+    }
+    Ok(())
+}
 
-        for handle in handles {
-            let (batch_num, results, elapsed) = handle.join().expect("batch thread panicked");
+/// 進捗表示の設定
+struct ProgressStyle {
+    sampling: bool,
+    /// "3" や "auto ≤3"
+    parallel: String,
+    /// バッチごとの所要時間を表示する（--profile）
+    batch_ms: bool,
+}
 
-            for (fname, item) in &results {
-                println!(
-                    "  [B{batch_num}] {} -> {} / {} ({})",
-                    fname, item.role, item.machine_type, item.machine_id
-                );
-                records.insert(
-                    fname.clone(),
-                    GroupRecord {
-                        role: item.role.clone(),
-                        machine_type: item.machine_type.clone(),
-                        machine_id: item.machine_id.clone(),
-                        group: 0,
-                        has_board: item.has_board,
-                        detected_text: item.detected_text.clone(),
-                        description: item.description.clone(),
-                        captured_at: None,
-                    },
-                );
+fn print_group_progress(event: &GroupProgress<'_>, style: &ProgressStyle) {
+    match event {
+        GroupProgress::Pending {
+            images,
+            pending,
+            skipped,
+            changed,
+            unreadable,
+            hash_errors,
+            other_cameras,
+            sampled_from,
+        } => {
+            if *changed > 0 {
+                println!("{changed} photo(s) changed since they were classified; classifying them again.");
             }
-
-            if cli.profile {
-                eprintln!("  [B{batch_num}] {}", fmt_duration(elapsed));
+            warn_unreadable(unreadable);
+            warn_hash_errors(hash_errors);
+            if style.sampling && *pending == 0 {
+                println!("Nothing pending to sample (set PHOTO_TAGGER_FORCE_RECLASSIFY=1 to sample classified photos).");
+                return;
+            }
+            if let Some(total) = sampled_from {
+                println!("Sampling {pending} of {total} pending image(s); photo-groups.json is not changed.");
+            }
+            if *skipped > 0 {
+                println!("Skipping {skipped} already grouped.");
             }
+            let unsampled = sampled_from.map_or(0, |total| total - pending);
+            if unsampled > 0 {
+                println!("Leaving {unsampled} pending photo(s) outside the sample.");
+            }
+            if *other_cameras > 0 {
+                println!("Leaving {other_cameras} pending photo(s) from other cameras for a later run.");
+            }
+            if *pending == 0 && *other_cameras == 0 {
+                println!("All {} images grouped.", images - unreadable.len());
+            }
+        }
+        GroupProgress::Unfinished(unfinished) => report_unfinished(unfinished),
+        GroupProgress::Clustered { photos, clusters, duplicates, embed_failures, first_embed_error } => {
+            if let Some(e) = first_embed_error {
+                eprintln!("Warning: {embed_failures} photo(s) could not be embedded and are sent on their own (first: {e})");
+            }
+            println!("{photos} image(s) in {clusters} cluster(s); sending one photo per cluster.");
+            if !duplicates.is_empty() {
+                println!("Possible duplicates:");
+                for d in duplicates.iter() {
+                    println!("  {} ≈ {} ({:.3})", file_label(&d.first), file_label(&d.second), d.similarity);
+                }
+            }
+        }
+        GroupProgress::TextFiltered { no_text } => {
+            println!("{no_text} image(s) without a blackboard or text; sending them with the short prompt.");
+        }
+        GroupProgress::Planned { photos, batches } => {
+            if *batches > 0 {
+                println!("{photos} image(s) in {batches} batch(es) ({BATCH_SIZE}枚/batch, {}並列)\n", style.parallel);
+            }
+        }
+        GroupProgress::BatchStarted { batch, batches, photos } => {
+            eprintln!("--- Batch {batch}/{batches} ({photos} images) ---");
+        }
+        GroupProgress::BatchFinished { batch, records, followers, failure, stats } => {
+            if let Some(f) = failure {
+                eprintln!("  Batch {batch} error: {}", f.message);
+            }
+            for (fname, rec) in records.iter().filter(|(k, _)| !followers.contains(k)) {
+                println!("  [B{batch}] {fname} -> {} / {} ({})", rec.role, rec.machine_type, rec.machine_id);
+            }
+            for key in followers.iter() {
+                println!("  [B{batch}] {key} -> (same cluster)");
+            }
+            if style.batch_ms {
+                eprintln!("  [B{batch}] {:.0}ms", stats.analyze_ms);
+            }
+        }
+        GroupProgress::ConcurrencyChanged { from, to } => eprintln!("  concurrency {from} -> {to}"),
+        GroupProgress::Stopped { cause, unsent } => {
+            eprintln!("\nStopping early: {cause}");
+            eprintln!("  {unsent} batch(es) not sent; rerun after fixing the cause to continue.");
         }
     }
-    let classify_dur = classify_start.elapsed();
+}
 
-    apply_capture_times(&mut records, &capture_times);
-    assign_groups(&mut records);
+/// --sample の結果を表示する（本体のレコード・履歴・フック・last-run には触れていない）
+fn finish_sample(cli: &Cli, run: &GroupRun) -> Result<()> {
+    if run.pending == 0 {
+        return Ok(());
+    }
+    print_group_summary(&run.records)?;
+    match &run.sample_file {
+        Some(saved) => println!("\nSample results written to {}", saved.display()),
+        None if cli.dry_run => println!("\n(dry-run: no files saved)"),
+        None => {}
+    }
+    if let Some(cause) = &run.aborted {
+        anyhow::bail!("run stopped by the error-rate circuit breaker: {cause}");
+    }
+    Ok(())
+}
+
+/// --vocabulary のファイル（1 行に 1 語、空行と # で始まる行は飛ばす）
+fn load_vocabulary(file: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// 乱数の種（--sample-by random）
+fn clock_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
 
+/// 前回の実行から引き継ぐ設定（しきい値・並列数など、結果や負荷に関わるもの）。
+/// --dry-run・--profile・--renumber・--summary-out のようなその回限りの指定は含めない。
+fn remembered_settings(cli: &Cli) -> BTreeMap<String, serde_json::Value> {
+    let settings = serde_json::json!({
+        "concurrent": cli.concurrent,
+        "auto_concurrency": cli.auto_concurrency,
+        "scan_workers": cli.scan_workers,
+        "strict": cli.strict,
+        "max_error_rate": cli.max_error_rate,
+        "error_window": cli.error_window,
+        "cluster": cli.cluster,
+        "text_filter": cli.text_filter,
+        "batch_context": cli.batch_context,
+        "time_offset": cli.time_offset,
+        // f32 をそのまま入れると 0.019999… になるので、表示どおりの値で残す
+        "min_text_score": cli.min_text_score.to_string().parse::<f64>().unwrap_or_default(),
+    });
+    serde_json::from_value(settings).unwrap_or_default()
+}
+
+/// last-run.json の値を、コマンドラインで指定されなかった項目にだけ当てはめる
+fn apply_last_run(cli: &mut Cli, matches: &ArgMatches, path: &Path) -> Result<()> {
+    let Some(last) = last_run::load_last_run(path)? else { return Ok(()) };
+    let before = remembered_settings(cli);
+    let mut applied = Vec::new();
+    for (id, value) in &last.settings {
+        // 新しい版で増えた・消えた項目は無視する（value_source は未定義の id で panic するので先に除く）
+        let Some(current) = before.get(id) else { continue };
+        if current == value || matches.value_source(id) == Some(ValueSource::CommandLine) || negated(cli, id) {
+            continue;
+        }
+        let ok = match id.as_str() {
+            "concurrent" => set_from(&mut cli.concurrent, value),
+            "auto_concurrency" => set_from(&mut cli.auto_concurrency, value),
+            "scan_workers" => set_from(&mut cli.scan_workers, value),
+            "strict" => set_from(&mut cli.strict, value),
+            "max_error_rate" => set_from(&mut cli.max_error_rate, value),
+            "error_window" => set_from(&mut cli.error_window, value),
+            "cluster" => set_from(&mut cli.cluster, value),
+            "text_filter" => set_from(&mut cli.text_filter, value),
+            "batch_context" => set_from(&mut cli.batch_context, value),
+            "time_offset" => set_from(&mut cli.time_offset, value),
+            "min_text_score" => set_from(&mut cli.min_text_score, value),
+            _ => false,
+        };
+        if ok {
+            applied.push(format!("--{} {value}", id.replace('_', "-")));
+        }
+    }
+    if !applied.is_empty() {
+        println!("Using settings from the previous run: {}", applied.join(", "));
+        println!("  (pass the option, or --no-<option> to turn a flag off or clear --time-offset, to override;");
+        println!("   --ignore-last-run for the built-in defaults)");
+    }
+    Ok(())
+}
+
+/// --no-strict などで、前回の値を使わないよう指定された項目か
+fn negated(cli: &Cli, id: &str) -> bool {
+    match id {
+        "auto_concurrency" => cli.no_auto_concurrency,
+        "strict" => cli.no_strict,
+        "cluster" => cli.no_cluster,
+        "text_filter" => cli.no_text_filter,
+        "batch_context" => cli.no_batch_context,
+        "time_offset" => cli.no_time_offset,
+        _ => false,
+    }
+}
+
+fn set_from<T: serde::de::DeserializeOwned>(field: &mut T, value: &serde_json::Value) -> bool {
+    match serde_json::from_value(value.clone()) {
+        Ok(v) => {
+            *field = v;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// 成功した実行の設定を last-run.json に残す
+fn save_last_run(cli: &Cli, path: &Path) -> Result<()> {
     if !cli.dry_run {
-        fs_ops::save_group_records(&cli.path, &records)?;
+        last_run::save_last_run(path, &last_run::LastRun::new(remembered_settings(cli)))?;
     }
+    Ok(())
+}
 
-    print_group_summary(&records);
+/// フックの失敗は警告だけ（分類結果は保存済み）。同じ失敗が続く場合に備えて先頭の数件だけ表示する。
+fn warn_hook_errors(errors: Vec<photo_tagger::Error>) {
+    const SHOWN: usize = 3;
+    for e in errors.iter().take(SHOWN) {
+        eprintln!("Warning: {e}");
+    }
+    if errors.len() > SHOWN {
+        eprintln!("Warning: ... and {} more hook failure(s)", errors.len() - SHOWN);
+    }
+}
 
-    if cli.dry_run {
-        println!("\n(dry-run: no files saved)");
+/// 内容のハッシュを計算できなかった写真を知らせる（それらは名前だけで照合する）
+fn warn_hash_errors(errors: &[photo_tagger::Error]) {
+    const SHOWN: usize = 3;
+    for e in errors.iter().take(SHOWN) {
+        eprintln!("Warning: could not hash {e}; matching it by name only");
     }
+    if errors.len() > SHOWN {
+        eprintln!("Warning: ... and {} more photo(s) that could not be hashed", errors.len() - SHOWN);
+    }
+}
 
-    let total_dur = total_start.elapsed();
-    if cli.profile {
-        println!("\n--- Profile ---");
-        println!("  {:<12} {:>8}", "collect:", fmt_duration(collect_dur));
-        println!("  {:<12} {:>8}", "classify:", fmt_duration(classify_dur));
-        println!("  {:<12} {:>8}", "total:", fmt_duration(total_dur));
-    } else {
-        println!("\nCompleted in {}.", fmt_duration(total_dur));
+/// 前回の実行で分類されずに残った写真の内訳を表示する
+fn report_unfinished(unfinished: &retry_state::Unfinished) {
+    if unfinished.is_empty() {
+        return;
+    }
+    println!("Previous run did not finish every batch:");
+    for (n, what) in [
+        (unfinished.never_sent.len(), "never sent"),
+        (unfinished.unanswered.len(), "sent but not answered (sending again)"),
+        (unfinished.unparsed.len(), "answered but not parseable (retrying one photo per batch)"),
+        (unfinished.failed.len(), "failed (sending again)"),
+    ] {
+        if n > 0 {
+            println!("  {n} photo(s) {what}");
+        }
+    }
+}
+
+fn file_label(img: &Path) -> String {
+    img.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// 分類エラーに validate の結果を足す（分類に失敗した写真の「未分類」は重複なので除く）
+fn run_issues(
+    path: &Path,
+    records: &GroupRecords,
+    images: &[PathBuf],
+    utc_offset_secs: i64,
+    mut issues: Vec<Issue>,
+) -> Vec<Issue> {
+    let failed: std::collections::HashSet<String> = issues.iter().map(|i| i.subject.clone()).collect();
+    let hours = load_working_hours(path);
+    let machine_sets = load_machine_sets(path);
+    issues.extend(
+        validate::validate_group_records(path, records, images, utc_offset_secs, hours.as_ref(), &machine_sets)
+            .into_iter()
+            .filter(|i| !(i.kind == IssueKind::Unassigned && failed.contains(&i.subject))),
+    );
+    issues.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.subject.cmp(&b.subject)));
+    issues
+}
+
+/// [working_hours] を読む。読めなければ警告して検査しない（実行自体は止めない）
+fn load_working_hours(path: &Path) -> Option<validate::WorkingHours> {
+    match Config::load(path).and_then(|c| c.working_hours(path)) {
+        Ok(hours) => hours,
+        Err(e) => {
+            eprintln!("Warning: working hours not checked: {e}");
+            None
+        }
+    }
+}
+
+/// [machine_sets] のテンプレートを読む。読めなければ警告して既定の 3 枚で検査する
+fn load_machine_sets(path: &Path) -> MachineSets {
+    match Config::load(path).and_then(|c| c.machine_sets(path)) {
+        Ok(sets) => sets,
+        Err(e) => {
+            eprintln!("Warning: machine set templates not loaded, using the default set: {e}");
+            MachineSets::default()
+        }
+    }
+}
+
+/// 作業時間外・休日に撮影された写真を目立つように表示する（--strict でなくても出す）。
+/// 撮影時刻がずれていると時刻の間隔によるグループ分けが崩れるため。
+fn warn_off_hours(path: &Path, records: &GroupRecords, utc_offset_secs: i64) {
+    const SHOWN: usize = 10;
+    let Some(hours) = load_working_hours(path) else { return };
+    let issues = validate::off_hours_issues(records, &hours, utc_offset_secs);
+    if issues.is_empty() {
+        return;
+    }
+    eprintln!("\n!!! {} photo(s) captured outside working hours or on a holiday !!!", issues.len());
+    eprintln!("    Wrong camera clocks break time-based grouping; fix the times and run again.");
+    for issue in issues.iter().take(SHOWN) {
+        eprintln!("  {}: {}", issue.subject, issue.detail);
     }
+    if issues.len() > SHOWN {
+        eprintln!("  ... and {} more (see `validate`)", issues.len() - SHOWN);
+    }
+}
 
+/// --strict 指定時に検査結果を表示し、問題があれば終了コードを返して終了する
+fn check_strict(
+    cli: &Cli,
+    path: &Path,
+    records: &GroupRecords,
+    images: &[PathBuf],
+    utc_offset_secs: i64,
+    issues: Vec<Issue>,
+) {
+    if !cli.strict {
+        return;
+    }
+    report_issues(&run_issues(path, records, images, utc_offset_secs, issues), true);
+}
+
+/// --summary-out 指定時に実行結果の要約を書く。summary には件数と分類エラーを入れて渡す。
+fn write_run_summary(
+    cli: &Cli,
+    path: &Path,
+    mut summary: RunSummary,
+    previous: &GroupRecords,
+    records: &GroupRecords,
+    images: &[PathBuf],
+    utc_offset_secs: i64,
+) -> Result<()> {
+    let Some(out) = &cli.summary_out else { return Ok(()) };
+    summary.folder = path.display().to_string();
+    summary.finished_at = timestamp::local_now(utc_offset_secs);
+    let groups: std::collections::BTreeSet<u32> = records.values().map(|r| r.group).filter(|g| *g != 0).collect();
+    summary.groups = groups.len();
+    summary.new_groups = summary::new_groups(previous, records);
+    summary.warnings = run_issues(path, records, images, utc_offset_secs, summary.errors.clone())
+        .into_iter()
+        .filter(|i| i.kind != IssueKind::ClassificationError)
+        .collect();
+    summary.dry_run = cli.dry_run;
+    summary::write_summary(out, &summary)?;
+    println!("Summary written to {}", out.display());
     Ok(())
 }
@@ -0,0 +1,186 @@
+use sha2::Digest;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fs_ops::long_path;
+
+/// 電子納品の整合性確認用のチェックサム。`sha256sum -c` / `md5sum -c` でも検証できる形式で書く。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    /// 古い要領で MD5 を求められる場合用
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub fn extension(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Md5 => "md5",
+        }
+    }
+
+    /// 16 進のダイジェスト長から判別する
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            64 => Some(HashAlgorithm::Sha256),
+            32 => Some(HashAlgorithm::Md5),
+            _ => None,
+        }
+    }
+}
+
+/// 既定の目録ファイル名（MANIFEST.sha256 / MANIFEST.md5）
+pub fn manifest_file_name(algorithm: HashAlgorithm) -> String {
+    format!("MANIFEST.{}", algorithm.extension())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// base からの相対パス（区切りは `/`）
+    pub path: String,
+    /// 小文字 16 進
+    pub digest: String,
+}
+
+/// 目録の対象: base 以下のすべてのファイル。`.` で始まるファイル・フォルダ（.photo-tagger/ など）と
+/// 目録ファイル自体（MANIFEST.*）は除く。相対パスの名前順。
+pub fn manifest_files(base: &Path) -> Result<Vec<String>> {
+    fn walk(base: &Path, rel: &Path, out: &mut Vec<String>) -> Result<()> {
+        let dir = base.join(rel);
+        let entries = std::fs::read_dir(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::io(&dir, e))?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || (rel.as_os_str().is_empty() && name.starts_with("MANIFEST.")) {
+                continue;
+            }
+            let child = rel.join(name.as_ref());
+            let file_type = entry.file_type().map_err(|e| Error::io(&dir, e))?;
+            if file_type.is_dir() {
+                walk(base, &child, out)?;
+            } else if file_type.is_file() {
+                let parts: Vec<String> = child.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+                out.push(parts.join("/"));
+            }
+        }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    walk(base, Path::new(""), &mut out)?;
+    out.sort();
+    Ok(out)
+}
+
+fn digest_with<D: Digest>(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(long_path(path)).map_err(|e| Error::io(path, e))?;
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| Error::io(path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+pub fn file_digest(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    match algorithm {
+        HashAlgorithm::Sha256 => digest_with::<sha2::Sha256>(path),
+        HashAlgorithm::Md5 => digest_with::<md5::Md5>(path),
+    }
+}
+
+/// base 以下の写真・帳票のチェックサムを計算する
+pub fn build_manifest(base: &Path, algorithm: HashAlgorithm) -> Result<Vec<ManifestEntry>> {
+    manifest_files(base)?
+        .into_iter()
+        .map(|path| {
+            let digest = file_digest(&base.join(&path), algorithm)?;
+            Ok(ManifestEntry { path, digest })
+        })
+        .collect()
+}
+
+/// `<digest>  <path>` を 1 行ずつ（LF、sha256sum と同じ形式）
+pub fn manifest_text(entries: &[ManifestEntry]) -> String {
+    entries.iter().map(|e| format!("{}  {}\n", e.digest, e.path)).collect()
+}
+
+/// 目録を読み、使われているアルゴリズムと項目を返す。空行は無視する。
+pub fn parse_manifest(text: &str) -> Result<(HashAlgorithm, Vec<ManifestEntry>)> {
+    let mut algorithm = None;
+    let mut entries = Vec::new();
+    for (no, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || Error::Validation(format!("manifest line {}: expected `<digest>  <path>`", no + 1));
+        let (digest, path) = line.split_once(' ').ok_or_else(invalid)?;
+        // sha256sum のバイナリモード表記（`<digest> *<path>`）も読む
+        let path = path.strip_prefix(' ').or_else(|| path.strip_prefix('*')).ok_or_else(invalid)?;
+        let this = HashAlgorithm::from_hex_len(digest.len())
+            .filter(|_| digest.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(invalid)?;
+        if *algorithm.get_or_insert(this) != this {
+            return Err(Error::Validation(format!("manifest line {}: mixed digest algorithms", no + 1)));
+        }
+        entries.push(ManifestEntry { path: path.to_string(), digest: digest.to_ascii_lowercase() });
+    }
+    let algorithm = algorithm.ok_or_else(|| Error::Validation("manifest is empty".into()))?;
+    Ok((algorithm, entries))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ManifestProblemKind {
+    /// 内容が変わっている
+    Mismatch,
+    /// 目録にあるがファイルが無い
+    Missing,
+    /// 目録に無いファイル
+    Unlisted,
+}
+
+impl ManifestProblemKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ManifestProblemKind::Mismatch => "mismatch",
+            ManifestProblemKind::Missing => "missing",
+            ManifestProblemKind::Unlisted => "unlisted",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestProblem {
+    pub kind: ManifestProblemKind,
+    pub path: String,
+}
+
+/// 目録と base 以下のファイルを突き合わせる。問題が無ければ空。
+pub fn verify_manifest(base: &Path, algorithm: HashAlgorithm, entries: &[ManifestEntry]) -> Result<Vec<ManifestProblem>> {
+    let present = manifest_files(base)?;
+    let listed: HashSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    let mut problems = Vec::new();
+    for entry in entries {
+        let path: PathBuf = base.join(&entry.path);
+        if !long_path(&path).is_file() {
+            problems.push(ManifestProblem { kind: ManifestProblemKind::Missing, path: entry.path.clone() });
+        } else if file_digest(&path, algorithm)? != entry.digest {
+            problems.push(ManifestProblem { kind: ManifestProblemKind::Mismatch, path: entry.path.clone() });
+        }
+    }
+    for path in present {
+        if !listed.contains(path.as_str()) {
+            problems.push(ManifestProblem { kind: ManifestProblemKind::Unlisted, path });
+        }
+    }
+    problems.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.path.cmp(&b.path)));
+    Ok(problems)
+}
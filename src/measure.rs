@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use crate::domain::GroupRecords;
+use crate::error::{Error, Result};
+use crate::caption::station;
+use crate::report::{parse_csv, to_csv};
+use crate::timestamp::LocalDateTime;
+
+/// 黒板・出来形管理用紙から読み取った測定値 1 件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub file: String,
+    /// 測点（No.X / 取付道路 No.X）。読めなければ空
+    pub station: String,
+    pub item: String,
+    pub value: f64,
+    pub unit: String,
+    /// 用紙に「設計 X 実測 Y」と並んでいた場合の設計値
+    pub design: Option<f64>,
+}
+
+/// 設計値 CSV の 1 行。測点が空なら全測点に適用する。
+#[derive(Debug, Clone)]
+pub struct DesignValue {
+    pub station: String,
+    pub item: String,
+    pub design: f64,
+    /// 設計値からの許容差（下限は負の値で書く）
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    OutOfTolerance,
+}
+
+/// 測定値と、設計値 CSV と突き合わせた結果
+#[derive(Debug, Clone)]
+pub struct CheckedMeasurement {
+    pub measurement: Measurement,
+    pub design: Option<f64>,
+    pub verdict: Option<Verdict>,
+}
+
+const UNITS: [&str; 7] = ["mm", "cm", "m", "%", "‰", "℃", "°C"];
+
+/// 先頭の数値（符号・小数点付き）を読み、(値, 残り) を返す
+fn leading_number(s: &str) -> Option<(f64, &str)> {
+    let s = s.trim_start();
+    let end = s
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(s.len(), |(i, _)| i);
+    let value = s[..end].parse().ok()?;
+    Some((value, &s[end..]))
+}
+
+fn leading_unit(s: &str) -> &'static str {
+    let s = s.trim_start();
+    UNITS.iter().find(|u| s.starts_with(*u)).copied().unwrap_or("")
+}
+
+/// 「項目 数値単位」「項目=数値」「項目 設計X 実測Y」「項目 V1=X V2=Y ...」を読む
+fn parse_segment(segment: &str) -> Vec<(String, f64, String, Option<f64>)> {
+    let segment = segment.trim();
+    let mut out = Vec::new();
+
+    if let Some(pos) = segment.find("実測") {
+        let head = segment[..pos].trim();
+        let (item, design) = match head.find("設計") {
+            Some(d) => (head[..d].trim(), leading_number(&head[d + "設計".len()..]).map(|(v, _)| v)),
+            None => (head, None),
+        };
+        if let Some((value, rest)) = leading_number(&segment[pos + "実測".len()..]) {
+            if !item.is_empty() {
+                out.push((item.to_string(), value, leading_unit(rest).to_string(), design));
+            }
+        }
+        return out;
+    }
+
+    // 計画高(設計) V1=5.0 V2=5.1 ... のような測点内の複数点
+    let mut words = segment.split_whitespace().peekable();
+    let mut prefix: Vec<&str> = Vec::new();
+    while let Some(word) = words.next_if(|w| !w.contains('=')) {
+        prefix.push(word);
+    }
+    if words.peek().is_some() {
+        let prefix = prefix.join(" ");
+        for word in words {
+            let Some((key, rest)) = word.split_once('=') else { continue };
+            if let Some((value, rest)) = leading_number(rest) {
+                let item = if prefix.is_empty() { key.to_string() } else { format!("{prefix} {key}") };
+                out.push((item, value, leading_unit(rest).to_string(), None));
+            }
+        }
+        return out;
+    }
+
+    // 厚さ 5.0cm / 幅:3.50m
+    let split = segment
+        .char_indices()
+        .find(|&(_, c)| c.is_ascii_digit() || c == '-' || c == '+')
+        .map(|(i, _)| i);
+    if let Some(i) = split.filter(|&i| i > 0) {
+        let item = segment[..i].trim().trim_end_matches([':', '：', '=']).trim();
+        if let Some((value, rest)) = leading_number(&segment[i..]) {
+            // 「No.3」「測点 No.5」は測点番号なので測定値にしない
+            let is_station = ["No", "No.", "NO", "NO."].iter().any(|m| item.ends_with(m));
+            if !item.is_empty() && !is_station {
+                out.push((item.to_string(), value, leading_unit(rest).to_string(), None));
+            }
+        }
+    }
+    out
+}
+
+/// detected_text から測定値を取り出す（AI 呼び出し無し）
+pub fn extract_measurements(records: &GroupRecords) -> Vec<Measurement> {
+    let mut out = Vec::new();
+    for (fname, rec) in records {
+        if rec.detected_text.is_empty() {
+            continue;
+        }
+        let station = station(rec);
+        for segment in rec.detected_text.split([',', '、', '，', '\n']) {
+            for (item, value, unit, design) in parse_segment(segment) {
+                out.push(Measurement {
+                    file: fname.clone(),
+                    station: station.clone(),
+                    item,
+                    value,
+                    unit,
+                    design,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// 設計値 CSV（測点,項目,設計値,下限差,上限差）を読む
+pub fn parse_design_csv(text: &str) -> Result<Vec<DesignValue>> {
+    let mut rows = parse_csv(text).into_iter();
+    let header = rows.next().unwrap_or_default();
+    let col = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| Error::Validation(format!("design values: missing column `{name}`")))
+    };
+    let (station, item, design, lower, upper) = (col("測点")?, col("項目")?, col("設計値")?, col("下限差")?, col("上限差")?);
+
+    rows.enumerate()
+        .map(|(i, row)| {
+            let field = |c: usize| row.get(c).map(|v| v.trim()).unwrap_or("");
+            let number = |c: usize| {
+                field(c).parse::<f64>().map_err(|_| {
+                    Error::Validation(format!("design values line {}: `{}` is not a number", i + 2, field(c)))
+                })
+            };
+            Ok(DesignValue {
+                station: field(station).to_string(),
+                item: field(item).to_string(),
+                design: number(design)?,
+                lower: number(lower)?,
+                upper: number(upper)?,
+            })
+        })
+        .collect()
+}
+
+/// 測定値を設計値と突き合わせる。測点一致の行を優先し、無ければ測点空欄の行を使う。
+pub fn check_measurements(measurements: Vec<Measurement>, design: &[DesignValue]) -> Vec<CheckedMeasurement> {
+    let mut table: BTreeMap<(&str, &str), &DesignValue> = BTreeMap::new();
+    for d in design {
+        table.insert((d.station.as_str(), d.item.as_str()), d);
+    }
+    measurements
+        .into_iter()
+        .map(|m| {
+            let spec = table
+                .get(&(m.station.as_str(), m.item.as_str()))
+                .or_else(|| table.get(&("", m.item.as_str())));
+            let verdict = spec.map(|d| {
+                let diff = m.value - d.design;
+                if diff >= d.lower && diff <= d.upper {
+                    Verdict::Ok
+                } else {
+                    Verdict::OutOfTolerance
+                }
+            });
+            CheckedMeasurement {
+                design: spec.map(|d| d.design).or(m.design),
+                verdict,
+                measurement: m,
+            }
+        })
+        .collect()
+}
+
+/// 舗装の温度管理で記録する項目（この順で並べる）
+pub const TEMPERATURE_ITEMS: [&str; 4] = ["到着温度", "敷均し温度", "初期締固め温度", "二次締固め温度"];
+
+/// 1 日・1 項目分の温度の集計
+#[derive(Debug, Clone)]
+pub struct TemperatureRow {
+    pub date: (i32, u32, u32),
+    pub item: String,
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+fn is_temperature(m: &Measurement) -> bool {
+    m.unit == "℃" || m.unit == "°C" || m.item.contains("温度")
+}
+
+/// 温度の測定値を撮影日・項目ごとに集計する。撮影時刻の無い写真は除く。
+pub fn temperature_summary(
+    measurements: &[Measurement],
+    records: &GroupRecords,
+    utc_offset_secs: i64,
+) -> Vec<TemperatureRow> {
+    let rank = |item: &str| TEMPERATURE_ITEMS.iter().position(|t| item.contains(t)).unwrap_or(usize::MAX);
+    // (日付, 項目の並び順, 項目) → 値
+    let mut by_day: BTreeMap<(_, usize, &str), Vec<f64>> = BTreeMap::new();
+    for m in measurements.iter().filter(|m| is_temperature(m)) {
+        let Some(ts) = records.get(&m.file).and_then(|r| r.captured_at) else { continue };
+        let date = LocalDateTime::from_epoch(ts, utc_offset_secs).date();
+        by_day.entry((date, rank(&m.item), m.item.as_str())).or_default().push(m.value);
+    }
+    by_day
+        .into_iter()
+        .map(|((date, _, item), values)| TemperatureRow {
+            date,
+            item: item.to_string(),
+            count: values.len(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        })
+        .collect()
+}
+
+pub fn temperature_csv(rows: &[TemperatureRow]) -> String {
+    to_csv(
+        &["日付", "項目", "回数", "最低(℃)", "最高(℃)", "平均(℃)"],
+        rows.iter().map(|r| {
+            let (y, m, d) = r.date;
+            vec![
+                format!("{y:04}-{m:02}-{d:02}"),
+                r.item.clone(),
+                r.count.to_string(),
+                format!("{:.1}", r.min),
+                format!("{:.1}", r.max),
+                format!("{:.1}", r.mean),
+            ]
+        }),
+    )
+}
+
+pub fn measurements_csv(rows: &[CheckedMeasurement]) -> String {
+    to_csv(
+        &["ファイル", "測点", "項目", "測定値", "単位", "設計値", "差", "判定"],
+        rows.iter().map(|r| {
+            let m = &r.measurement;
+            vec![
+                m.file.clone(),
+                m.station.clone(),
+                m.item.clone(),
+                m.value.to_string(),
+                m.unit.clone(),
+                r.design.map(|d| d.to_string()).unwrap_or_default(),
+                r.design.map(|d| format!("{:+.3}", m.value - d)).unwrap_or_default(),
+                match r.verdict {
+                    Some(Verdict::Ok) => "OK".into(),
+                    Some(Verdict::OutOfTolerance) => "NG".into(),
+                    None => String::new(),
+                },
+            ]
+        }),
+    )
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use crate::error::{Error, Result};
+use crate::exif::{read_camera, CameraInfo};
+use crate::fs_ops::{long_path, record_key, state_dir, ImageEntry};
+use crate::manifest::{file_digest, HashAlgorithm};
+use crate::timestamp::TimestampParser;
+
+const META_CACHE_FILE: &str = "meta.json";
+/// ネットワークドライブでも stat を詰まらせない程度の同時読み取り数
+pub const DEFAULT_SCAN_WORKERS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageMeta {
+    pub size: u64,
+    pub mtime: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<i64>,
+    /// EXIF の撮影機材（読めなかった・EXIF が無い場合は空）。None は旧版のキャッシュで未読。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraInfo>,
+    /// 内容の SHA-256。--trust-names の実行や旧版のキャッシュでは None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// ディスク上のファイル名。キー（NFC 正規化した名前）と異なる場合だけ持つ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+}
+
+impl ImageMeta {
+    /// base 内の実際のパス
+    pub fn path_in(&self, base: &Path, key: &str) -> PathBuf {
+        base.join(self.file_name.as_deref().unwrap_or(key))
+    }
+}
+
+/// ファイル名 → メタデータ。(size, mtime) が一致する間は再解析しない。
+pub type MetaCache = BTreeMap<String, ImageMeta>;
+
+/// .photo-tagger/meta.json を読む。無い・壊れている場合は空キャッシュ。
+pub fn load_meta_cache(base: &Path) -> MetaCache {
+    std::fs::read_to_string(state_dir(base).join(META_CACHE_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_meta_cache(base: &Path, cache: &MetaCache) -> Result<()> {
+    let dir = state_dir(base);
+    std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+    let path = dir.join(META_CACHE_FILE);
+    let json = serde_json::to_string(cache).map_err(|e| Error::Parse {
+        message: "Failed to serialize meta cache".into(),
+        source: Some(e),
+    })?;
+    std::fs::write(&path, json).map_err(|e| Error::io(path, e))
+}
+
+/// 画像のメタデータを最大 workers 本のスレッドで並列に読む
+pub fn scan_images(images: &[PathBuf], cache: &MetaCache, workers: usize) -> MetaCache {
+    if images.is_empty() {
+        return MetaCache::new();
+    }
+    let chunk_size = images.len().div_ceil(workers.max(1));
+    thread::scope(|s| {
+        let handles: Vec<_> = images
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|p| scan_one(p, cache))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("meta scan thread panicked"))
+            .collect()
+    })
+}
+
+fn scan_one(p: &Path, cache: &MetaCache) -> Option<(String, ImageMeta)> {
+    let fname = p.file_name()?.to_str()?;
+    let md = std::fs::metadata(long_path(p)).ok()?;
+    let size = md.len();
+    let mtime = md.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    Some(lookup(p, fname, size, mtime, cache))
+}
+
+/// 走査時に読んだメタデータからキャッシュを作る（ファイルを再度 stat しない）。
+/// キャッシュに無い画像の EXIF は最大 workers 本のスレッドで並列に読む。
+pub fn scan_entries(entries: &[ImageEntry], cache: &MetaCache, workers: usize) -> MetaCache {
+    if entries.is_empty() {
+        return MetaCache::new();
+    }
+    let chunk_size = entries.len().div_ceil(workers.max(1));
+    thread::scope(|s| {
+        let handles: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|e| {
+                            let fname = e.path.file_name()?.to_str()?;
+                            Some(lookup(&e.path, fname, e.size, e.mtime?, cache))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("meta scan thread panicked"))
+            .collect()
+    })
+}
+
+fn lookup(path: &Path, disk_name: &str, size: u64, mtime: i64, cache: &MetaCache) -> (String, ImageMeta) {
+    let fname = record_key(disk_name);
+    let file_name = (fname != disk_name).then(|| disk_name.to_string());
+    let same = cache.get(&fname).filter(|hit| hit.size == size && hit.mtime == mtime);
+    if let Some(hit) = same.filter(|hit| hit.camera.is_some()) {
+        return (fname, ImageMeta { file_name, ..hit.clone() });
+    }
+    // 撮影時刻は現状ファイル更新時刻。EXIF 等を読む場合はここで差し替える。
+    let camera = read_camera(path);
+    let content_hash = same.and_then(|hit| hit.content_hash.clone());
+    (fname, ImageMeta { size, mtime, captured_at: Some(mtime), camera, content_hash, file_name })
+}
+
+/// ハッシュの無い画像（新しい・変更された写真）の内容のハッシュを最大 workers 本のスレッドで計算する。
+/// 読めない画像は None のままにして、そのエラーを返す。
+pub fn fill_content_hashes(base: &Path, meta: &mut MetaCache, workers: usize) -> Vec<Error> {
+    let missing: Vec<(String, PathBuf)> = meta
+        .iter()
+        .filter(|(_, m)| m.content_hash.is_none())
+        .map(|(f, m)| (f.clone(), m.path_in(base, f)))
+        .collect();
+    if missing.is_empty() {
+        return Vec::new();
+    }
+    let chunk_size = missing.len().div_ceil(workers.max(1));
+    let hashes: Vec<(String, Result<String>)> = thread::scope(|s| {
+        let handles: Vec<_> = missing
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(f, path)| (f.clone(), file_digest(path, HashAlgorithm::Sha256)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("hash thread panicked"))
+            .collect()
+    });
+    let mut errors = Vec::new();
+    for (fname, hash) in hashes {
+        match (hash, meta.get_mut(&fname)) {
+            (Ok(hash), Some(m)) => m.content_hash = Some(hash),
+            (Ok(_), None) => {}
+            (Err(e), _) => errors.push(e),
+        }
+    }
+    errors
+}
+
+/// ファイル名 → 内容のハッシュ（計算していない画像は除く）
+pub fn content_hashes(meta: &MetaCache) -> HashMap<String, String> {
+    meta.iter()
+        .filter_map(|(fname, m)| Some((fname.clone(), m.content_hash.clone()?)))
+        .collect()
+}
+
+/// 撮影時刻はファイル名の日時を優先し、読めなければメタデータ（更新時刻）を使う
+pub fn capture_times(meta: &MetaCache, parser: &TimestampParser) -> HashMap<String, i64> {
+    meta.iter()
+        .filter_map(|(fname, m)| {
+            parser
+                .parse_photo_timestamp(fname)
+                .or(m.captured_at)
+                .map(|ts| (fname.clone(), ts))
+        })
+        .collect()
+}
+
+/// ファイル名 → 撮影機材（EXIF の無い写真は除く）
+pub fn cameras(meta: &MetaCache) -> HashMap<String, CameraInfo> {
+    meta.iter()
+        .filter_map(|(fname, m)| Some((fname.clone(), m.camera.clone().filter(|c| !c.is_empty())?)))
+        .collect()
+}
+
+/// キャッシュ付きでメタデータを集める。persist=false ならキャッシュを書き戻さない。
+pub fn scan_cached(base: &Path, entries: &[ImageEntry], persist: bool, workers: usize) -> MetaCache {
+    scan_cached_with(base, entries, persist, workers, false).0
+}
+
+/// scan_cached に加えて内容のハッシュを集める。(size, mtime) が変わらない間はキャッシュのハッシュを使う。
+/// ハッシュを計算できなかった画像のエラーも返す。
+pub fn scan_cached_hashed(
+    base: &Path,
+    entries: &[ImageEntry],
+    persist: bool,
+    workers: usize,
+) -> (MetaCache, Vec<Error>) {
+    scan_cached_with(base, entries, persist, workers, true)
+}
+
+fn scan_cached_with(
+    base: &Path,
+    entries: &[ImageEntry],
+    persist: bool,
+    workers: usize,
+    hash: bool,
+) -> (MetaCache, Vec<Error>) {
+    let cache = load_meta_cache(base);
+    let mut scanned = scan_entries(entries, &cache, workers);
+    let errors = if hash { fill_content_hashes(base, &mut scanned, workers) } else { Vec::new() };
+    if persist && scanned != cache {
+        // キャッシュは高速化のためだけなので、書けなくても処理は続ける
+        let _ = save_meta_cache(base, &scanned);
+    }
+    (scanned, errors)
+}
+
+/// キャッシュ付きで撮影時刻を集める。persist=false ならキャッシュを書き戻さない。
+pub fn scan_capture_times(
+    base: &Path,
+    entries: &[ImageEntry],
+    parser: &TimestampParser,
+    persist: bool,
+) -> HashMap<String, i64> {
+    capture_times(&scan_cached(base, entries, persist, DEFAULT_SCAN_WORKERS), parser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_ops::collect_image_entries;
+
+    fn temp_base(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("photo-tagger-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    #[test]
+    fn hashes_photos_whose_names_are_not_nfc() {
+        let base = temp_base("meta-nfd");
+        // macOS などで NFD のまま保存された「ガ.jpg」
+        let nfd = "\u{30AB}\u{3099}.jpg";
+        std::fs::write(base.join(nfd), b"jpeg").unwrap();
+
+        let entries = collect_image_entries(&base, 1);
+        let (meta, errors) = scan_cached_hashed(&base, &entries, false, 1);
+        assert!(errors.is_empty(), "{errors:?}");
+        let m = &meta["\u{30AC}.jpg"];
+        assert_eq!(m.file_name.as_deref(), Some(nfd));
+        assert!(m.content_hash.is_some());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn unreadable_photos_are_reported() {
+        let base = temp_base("meta-missing");
+        let mut meta = MetaCache::new();
+        meta.insert(
+            "gone.jpg".into(),
+            ImageMeta { size: 1, mtime: 0, captured_at: None, camera: None, content_hash: None, file_name: None },
+        );
+        let errors = fill_content_hashes(&base, &mut meta, 1);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], Error::Io { .. }));
+        assert!(meta["gone.jpg"].content_hash.is_none());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+use crate::config::{PackageConfig, CONFIG_FILE};
+use crate::error::{Error, Result};
+use crate::fs_ops::{is_image, long_path, nested_subdirs, GROUP_FILE, GROUP_JOURNAL_FILE, TAG_FILE};
+use crate::manifest::{file_digest, manifest_file_name, manifest_text, HashAlgorithm, ManifestEntry};
+use crate::timestamp::LocalDateTime;
+
+/// 納品 ZIP に入れるファイル 1 件
+#[derive(Debug, Clone)]
+pub struct PackageEntry {
+    pub source: PathBuf,
+    /// ZIP 内のパス（区切りは `/`）
+    pub name: String,
+}
+
+/// 空の要素を除いて `/` でつなぐ。`..` などは配置テンプレートの誤りとして拒否する。
+fn zip_path(parts: &[&str]) -> Result<String> {
+    let mut out: Vec<&str> = Vec::new();
+    for part in parts {
+        for seg in part.split(['/', '\\']).filter(|s| !s.is_empty()) {
+            if seg == "." || seg == ".." {
+                return Err(Error::Validation(format!("package layout: invalid path segment `{seg}`")));
+            }
+            out.push(seg);
+        }
+    }
+    Ok(out.join("/"))
+}
+
+/// 写真の置き場所。{tag} は振り分け先のフォルダ（階層があれば `/` 区切り、直下の写真は空）。
+fn photo_path(layout: &PackageConfig, tag: &str, file: &str) -> Result<String> {
+    zip_path(&[&layout.root, &layout.photos.replace("{tag}", tag), file])
+}
+
+/// ツール自身のファイル（レコード・ジャーナル・設定・目録）は納品物に含めない
+fn is_tool_file(name: &str) -> bool {
+    [GROUP_FILE, GROUP_JOURNAL_FILE, TAG_FILE, CONFIG_FILE].contains(&name) || name.starts_with("MANIFEST.")
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<std::fs::DirEntry>> {
+    let entries = std::fs::read_dir(long_path(dir)).map_err(|e| Error::io(dir, e))?;
+    let mut out: Vec<std::fs::DirEntry> = entries
+        .flatten()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .collect();
+    out.sort_by_key(|e| e.file_name());
+    Ok(out)
+}
+
+/// base の中身を ZIP 内の配置に割り当てる。
+/// 直下の写真と振り分け先フォルダ（`_` で始まる未分類・要確認フォルダを除く）の写真は layout.photos へ、
+/// 直下のそれ以外のファイル（帳票・台帳など）は layout.documents へ。exclude（出力先の ZIP 自体など）は含めない。
+pub fn plan_package(base: &Path, layout: &PackageConfig, exclude: &[PathBuf]) -> Result<Vec<PackageEntry>> {
+    let mut planned: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut add = |name: String, source: PathBuf| {
+        if let Some(prev) = planned.insert(name.clone(), source) {
+            return Err(Error::Validation(format!(
+                "package layout puts two files at {name} (one is {})",
+                prev.display()
+            )));
+        }
+        Ok(())
+    };
+
+    for entry in sorted_entries(base)? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let source = base.join(&name);
+        if exclude.contains(&source) {
+            continue;
+        }
+        let file_type = entry.file_type().map_err(|e| Error::io(&source, e))?;
+        if file_type.is_dir() {
+            if name.starts_with('_') {
+                continue;
+            }
+            // [sort] layout で階層を分けた振り分け先は、その階層のまま {tag} に入れる
+            for dir in std::iter::once(source.clone()).chain(nested_subdirs(&source)) {
+                let rel: Vec<String> = dir
+                    .strip_prefix(base)
+                    .unwrap_or(&dir)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                if rel.iter().any(|seg| seg.starts_with('_')) {
+                    continue;
+                }
+                let tag = rel.join("/");
+                for sub in sorted_entries(&dir)? {
+                    let file = sub.file_name().to_string_lossy().into_owned();
+                    let sub_source = dir.join(&file);
+                    if sub.file_type().is_ok_and(|t| t.is_file()) && is_image(&sub_source) {
+                        add(photo_path(layout, &tag, &file)?, sub_source)?;
+                    }
+                }
+            }
+        } else if file_type.is_file() && is_image(&source) {
+            add(photo_path(layout, "", &name)?, source)?;
+        } else if file_type.is_file() && !is_tool_file(&name) {
+            add(zip_path(&[&layout.root, &layout.documents, &name])?, source)?;
+        }
+    }
+    Ok(planned.into_iter().map(|(name, source)| PackageEntry { source, name }).collect())
+}
+
+/// ファイルの更新時刻を ZIP の日時（現地時刻）にする。読めない・範囲外なら None。
+fn zip_time(path: &Path, utc_offset_secs: i64) -> Option<zip::DateTime> {
+    let mtime = std::fs::metadata(long_path(path)).ok()?.modified().ok()?;
+    let secs = mtime.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    let at = LocalDateTime::from_epoch(secs, utc_offset_secs);
+    let (y, mo, d) = at.date();
+    zip::DateTime::from_date_and_time(
+        u16::try_from(y).ok()?,
+        mo as u8,
+        d as u8,
+        at.hour as u8,
+        at.minute as u8,
+        at.second as u8,
+    )
+    .ok()
+}
+
+/// ZIP を書き出す。manifest を指定すると、ZIP 内のパスで作ったチェックサム目録をルートに加える。
+/// 写真は圧縮済みなので無圧縮で格納し、それ以外は deflate で圧縮する。
+pub fn write_package(
+    out: &Path,
+    layout: &PackageConfig,
+    entries: &[PackageEntry],
+    manifest: Option<HashAlgorithm>,
+    utc_offset_secs: i64,
+) -> Result<()> {
+    let zip_err = |e: zip::result::ZipError| Error::io(out, e.into());
+    let file = File::create(long_path(out)).map_err(|e| Error::io(out, e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let mut digests = Vec::new();
+    let root = zip_path(&[&layout.root])?;
+
+    for entry in entries {
+        let method = if is_image(&entry.source) { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+        let mut options = SimpleFileOptions::default().compression_method(method);
+        if let Some(at) = zip_time(&entry.source, utc_offset_secs) {
+            options = options.last_modified_time(at);
+        }
+        zip.start_file(entry.name.as_str(), options).map_err(zip_err)?;
+        let mut src = File::open(long_path(&entry.source)).map_err(|e| Error::io(&entry.source, e))?;
+        std::io::copy(&mut src, &mut zip).map_err(|e| Error::io(&entry.source, e))?;
+        if let Some(algorithm) = manifest {
+            // 目録のパスは展開先のルート（layout.root）からの相対パス
+            let path = match root.as_str() {
+                "" => entry.name.as_str(),
+                root => entry.name.strip_prefix(&format!("{root}/")).unwrap_or(&entry.name),
+            };
+            digests.push(ManifestEntry {
+                path: path.to_string(),
+                digest: file_digest(&entry.source, algorithm)?,
+            });
+        }
+    }
+
+    if let Some(algorithm) = manifest {
+        let name = zip_path(&[&layout.root, &manifest_file_name(algorithm)])?;
+        zip.start_file(name.as_str(), SimpleFileOptions::default()).map_err(zip_err)?;
+        zip.write_all(manifest_text(&digests).as_bytes()).map_err(|e| Error::io(out, e))?;
+    }
+    zip.finish().map_err(zip_err)?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::caption::station;
+use crate::domain::{GroupRecord, GroupRecords};
+use crate::report::to_csv;
+use crate::timestamp::LocalDateTime;
+
+const BEFORE_WORDS: [&str; 3] = ["施工前", "着手前", "補修前"];
+const AFTER_WORDS: [&str; 4] = ["施工後", "完了", "竣工", "補修後"];
+
+/// 施工前・施工後の区別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Before,
+    After,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::Before => "施工前",
+            Phase::After => "施工後",
+        }
+    }
+}
+
+/// 役割・黒板・説明の文言から施工前/施工後を判定する。どちらとも読めなければ None。
+pub fn phase(rec: &GroupRecord) -> Option<Phase> {
+    phase_in(&format!("{} {} {}", rec.role, rec.detected_text, rec.description))
+}
+
+/// 文字から施工前/施工後を判定する（phase の本体）
+pub fn phase_in(text: &str) -> Option<Phase> {
+    let before = BEFORE_WORDS.iter().any(|w| text.contains(w));
+    let after = AFTER_WORDS.iter().any(|w| text.contains(w));
+    match (before, after) {
+        (true, false) => Some(Phase::Before),
+        (false, true) => Some(Phase::After),
+        _ => None,
+    }
+}
+
+/// 対にする場所。測点（No.X / 取付道路 No.X）があればそれ、無ければ施工前/後の文言を除いた黒板の文字。
+fn location(rec: &GroupRecord) -> String {
+    let station = station(rec);
+    if !station.is_empty() {
+        return station;
+    }
+    let mut text: String = rec.detected_text.nfkc().filter(|c| !c.is_whitespace()).collect();
+    for w in BEFORE_WORDS.iter().chain(AFTER_WORDS.iter()) {
+        text = text.replace(w, "");
+    }
+    text
+}
+
+/// 台帳の 1 行（写真 1 枚）。対になった施工前・施工後は続けて並ぶ。
+#[derive(Debug, Clone)]
+pub struct PairRow {
+    /// 対の番号（1 から）。対の無い写真は None
+    pub pair: Option<usize>,
+    pub location: String,
+    pub phase: Phase,
+    pub file: String,
+    pub captured_at: Option<i64>,
+    pub board: String,
+}
+
+/// 施工前・施工後の写真を場所ごとに撮影順で対にした結果。場所の読めない写真は対にしない。
+/// rows は場所順・対ごとに施工前 → 施工後、対の無い写真はその場所の最後、場所の読めない写真は全体の最後。
+#[derive(Debug, Clone, Default)]
+pub struct PairReport {
+    pub rows: Vec<PairRow>,
+    pub pairs: usize,
+    pub unpaired: usize,
+}
+
+type Photo<'a> = (&'a String, &'a GroupRecord);
+
+pub fn pair_before_after(records: &GroupRecords) -> PairReport {
+    // 場所 → 区分 → 写真
+    let mut places: BTreeMap<String, BTreeMap<Phase, Vec<Photo>>> = BTreeMap::new();
+    for (fname, rec) in records {
+        let Some(phase) = phase(rec) else { continue };
+        places.entry(location(rec)).or_default().entry(phase).or_default().push((fname, rec));
+    }
+
+    let mut report = PairReport::default();
+    let row = |pair, location: &str, phase, (fname, rec): Photo| PairRow {
+        pair,
+        location: location.to_string(),
+        phase,
+        file: fname.clone(),
+        captured_at: rec.captured_at,
+        board: rec.detected_text.replace(['\n', '\r'], " "),
+    };
+    // 場所の読めない写真は最後にまとめる
+    let unknown = places.remove("").map(|phases| (String::new(), phases));
+    for (location, mut phases) in places.into_iter().chain(unknown) {
+        let mut take = |phase| {
+            let mut photos = phases.remove(&phase).unwrap_or_default();
+            photos.sort_by(|a, b| a.1.captured_at.cmp(&b.1.captured_at).then(a.0.cmp(b.0)));
+            photos
+        };
+        let (before, after) = (take(Phase::Before), take(Phase::After));
+        let paired = if location.is_empty() { 0 } else { before.len().min(after.len()) };
+        for i in 0..paired {
+            report.pairs += 1;
+            report.rows.push(row(Some(report.pairs), &location, Phase::Before, before[i]));
+            report.rows.push(row(Some(report.pairs), &location, Phase::After, after[i]));
+        }
+        for (phase, rest) in [(Phase::Before, &before[paired..]), (Phase::After, &after[paired..])] {
+            for photo in rest {
+                report.unpaired += 1;
+                report.rows.push(row(None, &location, phase, *photo));
+            }
+        }
+    }
+    report
+}
+
+pub fn pair_csv(report: &PairReport, utc_offset_secs: i64) -> String {
+    to_csv(
+        &["対", "場所", "区分", "ファイル", "撮影日時", "黒板"],
+        report.rows.iter().map(|r| {
+            let at = r.captured_at.map(|ts| LocalDateTime::from_epoch(ts, utc_offset_secs));
+            vec![
+                r.pair.map(|p| p.to_string()).unwrap_or_else(|| "対なし".to_string()),
+                r.location.clone(),
+                r.phase.label().to_string(),
+                r.file.clone(),
+                at.map(|a| format!("{} {}", a.date_string(), a.time_string())).unwrap_or_default(),
+                r.board.clone(),
+            ]
+        }),
+    )
+}
@@ -0,0 +1,104 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::fs_ops::state_dir;
+
+const RUN_PROFILE_FILE: &str = "run-profile.json";
+
+/// 1 回の実行の計測結果（.photo-tagger/run-profile.json）
+#[derive(Debug, Default, Serialize)]
+pub struct RunProfile {
+    pub images: usize,
+    pub pending: usize,
+    pub batch_size: usize,
+    pub concurrency: usize,
+    /// フォルダ走査でメタデータを読むスレッド数
+    pub scan_workers: usize,
+    /// --auto-concurrency 時に各ウェーブで使った並列数
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub concurrency_levels: Vec<usize>,
+    /// 処理段ごとの所要時間（scan: フォルダ走査, meta: 撮影時刻の解決, classify, io, total など）
+    pub stages_ms: BTreeMap<&'static str, f64>,
+    pub batches: Vec<BatchProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_latency_ms: Option<LatencySummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_image_ms: Option<LatencySummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProfile {
+    pub batch: usize,
+    pub images: usize,
+    /// 分類開始からこのバッチが送信されるまでの待ち時間
+    pub queue_wait_ms: f64,
+    pub analyze_ms: f64,
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl LatencySummary {
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let pct = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        Some(LatencySummary {
+            count: sorted.len(),
+            min: sorted[0],
+            p50: pct(0.5),
+            p90: pct(0.9),
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        })
+    }
+}
+
+pub fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl RunProfile {
+    pub fn stage(&mut self, name: &'static str, d: Duration) {
+        *self.stages_ms.entry(name).or_default() += ms(d);
+    }
+
+    /// バッチ単位・画像単位のレイテンシ分布を集計する（成功バッチのみ）
+    pub fn summarize(&mut self) {
+        let ok: Vec<&BatchProfile> = self.batches.iter().filter(|b| b.ok).collect();
+        let batch: Vec<f64> = ok.iter().map(|b| b.analyze_ms).collect();
+        let per_image: Vec<f64> = ok
+            .iter()
+            .filter(|b| b.images > 0)
+            .map(|b| b.analyze_ms / b.images as f64)
+            .collect();
+        self.batch_latency_ms = LatencySummary::from_samples(&batch);
+        self.per_image_ms = LatencySummary::from_samples(&per_image);
+    }
+
+    pub fn save(&self, base: &Path) -> Result<PathBuf> {
+        let dir = state_dir(base);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        let path = dir.join(RUN_PROFILE_FILE);
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Parse {
+            message: "Failed to serialize run profile".into(),
+            source: Some(e),
+        })?;
+        std::fs::write(&path, json).map_err(|e| Error::io(&path, e))?;
+        Ok(path)
+    }
+}
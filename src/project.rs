@@ -0,0 +1,310 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::domain::{is_machine_role, GroupRecords};
+use crate::error::{Error, Result};
+use crate::timestamp::{civil_from_days, days_from_civil, LocalDateTime};
+use crate::validate::{Issue, IssueKind};
+
+const PROJECT_REPORT_FILE: &str = "photo-tagger-report.json";
+
+/// フォルダ名先頭の日付（YYYYMMDD / YYYY-MM-DD / YYYY_MM_DD / YYYY.MM.DD）を読む
+pub fn parse_folder_date(name: &str) -> Option<(i32, u32, u32)> {
+    let digits: Vec<u32> = name.chars().take(10).map_while(|c| c.to_digit(10)).collect();
+    let (y, m, d) = if digits.len() >= 8 {
+        let n = |r: std::ops::Range<usize>| digits[r].iter().fold(0, |acc, x| acc * 10 + x);
+        (n(0..4), n(4..6), n(6..8))
+    } else {
+        let mut parts = name.splitn(4, ['-', '_', '.']);
+        let y = parts.next()?;
+        let m = parts.next()?;
+        let d: String = parts.next()?.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if y.len() != 4 || !(1..=2).contains(&m.len()) || !(1..=2).contains(&d.len()) {
+            return None;
+        }
+        (y.parse().ok()?, m.parse().ok()?, d.parse().ok()?)
+    };
+    ((1..=12).contains(&m) && (1..=31).contains(&d) && y >= 1900).then_some((y as i32, m, d))
+}
+
+/// root 直下の日付フォルダを名前順で返す
+pub fn discover_dated_folders(root: &Path) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(root).map_err(|e| Error::io(root, e))?;
+    let mut out: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .filter(|e| e.file_name().to_str().and_then(parse_folder_date).is_some())
+        .map(|e| e.path())
+        .collect();
+    out.sort();
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FolderReport {
+    pub folder: String,
+    pub photos: usize,
+    pub groups: usize,
+    pub machines: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FolderReport {
+    pub fn from_records(folder: &Path, records: &GroupRecords) -> Self {
+        let groups: BTreeSet<u32> = records.values().map(|r| r.group).collect();
+        let machines: BTreeSet<(&str, &str)> = records
+            .values()
+            .map(|r| (r.machine_type.as_str(), r.machine_id.as_str()))
+            .collect();
+        FolderReport {
+            folder: folder_name(folder),
+            photos: records.len(),
+            groups: groups.len(),
+            machines: machines.len(),
+            error: None,
+        }
+    }
+
+    pub fn failed(folder: &Path, error: String) -> Self {
+        FolderReport {
+            folder: folder_name(folder),
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+fn folder_name(folder: &Path) -> String {
+    folder
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| folder.display().to_string())
+}
+
+/// プロジェクト全体（日付フォルダ群）の集計
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProjectReport {
+    pub folders: Vec<FolderReport>,
+    pub total_photos: usize,
+    pub total_groups: usize,
+    pub failed_folders: usize,
+}
+
+impl ProjectReport {
+    pub fn new(mut folders: Vec<FolderReport>) -> Self {
+        folders.sort_by(|a, b| a.folder.cmp(&b.folder));
+        ProjectReport {
+            total_photos: folders.iter().map(|f| f.photos).sum(),
+            total_groups: folders.iter().map(|f| f.groups).sum(),
+            failed_folders: folders.iter().filter(|f| f.error.is_some()).count(),
+            folders,
+        }
+    }
+
+    pub fn save(&self, root: &Path) -> Result<PathBuf> {
+        let path = root.join(PROJECT_REPORT_FILE);
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Parse {
+            message: "Failed to serialize project report".into(),
+            source: Some(e),
+        })?;
+        std::fs::write(&path, json).map_err(|e| Error::io(&path, e))?;
+        Ok(path)
+    }
+}
+
+/// `project stats` の入力: 日付フォルダ 1 つ分のレコードと検査結果（AI 呼び出し無し）
+#[derive(Debug, Clone)]
+pub struct FolderRecords {
+    pub folder: String,
+    /// フォルダ名の日付（撮影時刻の無いレコードの日付に使う）
+    pub date: (i32, u32, u32),
+    pub records: GroupRecords,
+    pub issues: Vec<Issue>,
+    pub utc_offset_secs: i64,
+}
+
+/// 機械 1 台の使用期間（プロジェクト全体）
+#[derive(Debug, Clone)]
+pub struct MachineSpan {
+    pub machine_type: String,
+    pub machine_id: String,
+    pub first: (i32, u32, u32),
+    pub last: (i32, u32, u32),
+    /// 写っていた日数
+    pub days: usize,
+    pub photos: usize,
+}
+
+/// 週（月曜始まり）ごとの作業量
+#[derive(Debug, Clone)]
+pub struct WeekActivity {
+    pub week_start: (i32, u32, u32),
+    pub photos: usize,
+    pub days: usize,
+    pub machines: usize,
+    /// 機械以外の活動（安全パトロール・朝礼など）の種類数
+    pub activities: usize,
+}
+
+/// フォルダごとの検査結果の件数（問題の推移を見る用）
+#[derive(Debug, Clone)]
+pub struct FolderTrend {
+    pub folder: String,
+    pub photos: usize,
+    pub issues: BTreeMap<IssueKind, usize>,
+}
+
+/// プロジェクト全体の集計（`project stats`）
+#[derive(Debug, Clone, Default)]
+pub struct ProjectStats {
+    pub folders: Vec<FolderTrend>,
+    pub machines: Vec<MachineSpan>,
+    pub weeks: Vec<WeekActivity>,
+}
+
+/// 週の月曜日（1970-01-01 は木曜）
+pub(crate) fn week_start(date: (i32, u32, u32)) -> (i32, u32, u32) {
+    let days = days_from_civil(date.0 as i64, date.1, date.2);
+    civil_from_days(days - (days + 3).rem_euclid(7))
+}
+
+type Date = (i32, u32, u32);
+
+pub fn project_stats(folders: &[FolderRecords]) -> ProjectStats {
+    type Key<'a> = (&'a str, &'a str);
+    // (写っていた日, 枚数)
+    let mut spans: BTreeMap<Key, (BTreeSet<Date>, usize)> = BTreeMap::new();
+    let mut machines: BTreeSet<Key> = BTreeSet::new();
+    #[derive(Default)]
+    struct Week<'a> {
+        photos: usize,
+        days: BTreeSet<Date>,
+        /// 機械・活動（machine_type が空のものは除く）
+        subjects: BTreeSet<Key<'a>>,
+    }
+    let mut weeks: BTreeMap<Date, Week> = BTreeMap::new();
+    let mut trends = Vec::new();
+
+    for f in folders {
+        for rec in f.records.values() {
+            let date = rec
+                .captured_at
+                .map(|ts| LocalDateTime::from_epoch(ts, f.utc_offset_secs).date())
+                .unwrap_or(f.date);
+            let key = (rec.machine_type.as_str(), rec.machine_id.as_str());
+            let span = spans.entry(key).or_default();
+            span.0.insert(date);
+            span.1 += 1;
+            let week = weeks.entry(week_start(date)).or_default();
+            week.photos += 1;
+            week.days.insert(date);
+            if is_machine_role(&rec.role) {
+                machines.insert(key);
+            }
+            if !rec.machine_type.is_empty() {
+                week.subjects.insert(key);
+            }
+        }
+        let mut issues: BTreeMap<IssueKind, usize> = BTreeMap::new();
+        for issue in &f.issues {
+            *issues.entry(issue.kind).or_default() += 1;
+        }
+        trends.push(FolderTrend { folder: f.folder.clone(), photos: f.records.len(), issues });
+    }
+
+    let mut machine_rows: Vec<MachineSpan> = spans
+        .into_iter()
+        .filter(|(key, _)| machines.contains(key))
+        .filter_map(|((machine_type, machine_id), (days, photos))| {
+            Some(MachineSpan {
+                machine_type: machine_type.to_string(),
+                machine_id: machine_id.to_string(),
+                first: *days.first()?,
+                last: *days.last()?,
+                days: days.len(),
+                photos,
+            })
+        })
+        .collect();
+    machine_rows.sort_by(|a, b| a.first.cmp(&b.first).then(a.machine_type.cmp(&b.machine_type)));
+
+    let weeks = weeks
+        .into_iter()
+        .map(|(week_start, w)| {
+            let machine_count = w.subjects.iter().filter(|k| machines.contains(*k)).count();
+            let activities = w
+                .subjects
+                .iter()
+                .filter(|k| !machines.contains(*k))
+                .map(|(t, _)| *t)
+                .collect::<BTreeSet<_>>()
+                .len();
+            WeekActivity { week_start, photos: w.photos, days: w.days.len(), machines: machine_count, activities }
+        })
+        .collect();
+    ProjectStats { folders: trends, machines: machine_rows, weeks }
+}
+
+fn date_string((y, m, d): (i32, u32, u32)) -> String {
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+impl ProjectStats {
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let photos: usize = self.folders.iter().map(|f| f.photos).sum();
+        let _ = writeln!(out, "# プロジェクト集計\n");
+        let _ = writeln!(out, "- 日付フォルダ: {}", self.folders.len());
+        let _ = writeln!(out, "- 写真枚数: {photos}");
+        let _ = writeln!(out, "- 使用機械: {}", self.machines.len());
+
+        let _ = writeln!(out, "\n## 使用機械の期間\n");
+        let _ = writeln!(out, "| 機械名 | 型式 | 初回 | 最終 | 日数 | 枚数 |");
+        let _ = writeln!(out, "|---|---|---|---|---:|---:|");
+        for m in &self.machines {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                m.machine_type.replace('|', "\\|"),
+                m.machine_id.replace('|', "\\|"),
+                date_string(m.first),
+                date_string(m.last),
+                m.days,
+                m.photos
+            );
+        }
+
+        let _ = writeln!(out, "\n## 週ごとの作業量\n");
+        let _ = writeln!(out, "| 週（月曜） | 写真 | 撮影日数 | 機械 | その他の作業 |");
+        let _ = writeln!(out, "|---|---:|---:|---:|---:|");
+        for w in &self.weeks {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                date_string(w.week_start),
+                w.photos,
+                w.days,
+                w.machines,
+                w.activities
+            );
+        }
+
+        let kinds = [
+            IssueKind::Unassigned,
+            IssueKind::IncompleteMachineSet,
+            IssueKind::DateMismatch,
+            IssueKind::OffHours,
+        ];
+        let _ = writeln!(out, "\n## フォルダごとの問題\n");
+        let _ = writeln!(out, "| フォルダ | 写真 | 未分類 | 機械写真の不足 | 撮影日の不一致 | 時間外の撮影 |");
+        let _ = writeln!(out, "|---|---:|---:|---:|---:|---:|");
+        for f in &self.folders {
+            let counts: Vec<String> = kinds.iter().map(|k| f.issues.get(k).copied().unwrap_or(0).to_string()).collect();
+            let _ = writeln!(out, "| {} | {} | {} |", f.folder, f.photos, counts.join(" | "));
+        }
+        out
+    }
+}
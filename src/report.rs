@@ -0,0 +1,728 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::domain::{is_emission_role, is_inspection_role, is_machine_role, GroupRecord, GroupRecords, ReviewStatus};
+use crate::machine_set::MachineSets;
+use crate::summary::html_escape;
+use crate::timestamp::{civil_from_days, days_from_civil, LocalDateTime};
+use crate::xlsx::{Cell, Sheet};
+
+/// 同じ種類・識別の写真のまとまり（機械、または安全パトロール等の活動）
+#[derive(Debug, Clone)]
+pub struct DailySubject {
+    pub machine_type: String,
+    pub machine_id: String,
+    pub photos: usize,
+    pub first: LocalDateTime,
+    pub last: LocalDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoardEntry {
+    pub at: LocalDateTime,
+    pub file: String,
+    pub text: String,
+}
+
+/// 1 日分の集計（日報の下書き）
+#[derive(Debug, Clone)]
+pub struct DailyReport {
+    pub date: (i32, u32, u32),
+    pub photos: usize,
+    pub first: Option<LocalDateTime>,
+    pub last: Option<LocalDateTime>,
+    pub subjects: Vec<DailySubject>,
+    pub roles: BTreeMap<String, usize>,
+    pub boards: Vec<BoardEntry>,
+    /// その日の写真を含むグループの確認状態
+    pub reviews: Vec<GroupReview>,
+}
+
+/// グループ 1 つ分の確認状態とメモ（`groups list`・日報）
+#[derive(Debug, Clone)]
+pub struct GroupReview {
+    pub group: u32,
+    pub machine_type: String,
+    pub machine_id: String,
+    pub photos: usize,
+    pub status: ReviewStatus,
+    pub notes: String,
+    /// 写真を撮った機材（CameraInfo::key()、名前順）
+    pub cameras: Vec<String>,
+}
+
+/// グループ番号順に確認状態をまとめる（番号未付与の 0 は除く）。
+/// 状態が写真ごとに違う（手で書き換えられた）グループは未確認、メモは最初に見つかったものを使う。
+pub fn group_reviews<'a>(records: impl IntoIterator<Item = &'a GroupRecord>) -> Vec<GroupReview> {
+    let mut groups: BTreeMap<u32, (GroupReview, bool)> = BTreeMap::new();
+    for rec in records.into_iter().filter(|r| r.group != 0) {
+        let (review, mixed) = groups.entry(rec.group).or_insert_with(|| {
+            let review = GroupReview {
+                group: rec.group,
+                machine_type: rec.machine_type.clone(),
+                machine_id: rec.machine_id.clone(),
+                photos: 0,
+                status: rec.group_status,
+                notes: String::new(),
+                cameras: Vec::new(),
+            };
+            (review, false)
+        });
+        review.photos += 1;
+        let camera = rec.camera.key();
+        if !camera.is_empty() {
+            if let Err(at) = review.cameras.binary_search(&camera) {
+                review.cameras.insert(at, camera);
+            }
+        }
+        *mixed |= review.status != rec.group_status;
+        if review.notes.is_empty() {
+            review.notes = rec.group_notes.clone();
+        }
+    }
+    groups
+        .into_values()
+        .map(|(mut review, mixed)| {
+            if mixed {
+                review.status = ReviewStatus::Unreviewed;
+            }
+            review
+        })
+        .collect()
+}
+
+/// captured_at が指定日（utc_offset_secs の現地日付）に入るレコードを集計する
+pub fn daily_report(records: &GroupRecords, date: (i32, u32, u32), utc_offset_secs: i64) -> DailyReport {
+    let mut day: Vec<(LocalDateTime, &String, &GroupRecord)> = records
+        .iter()
+        .filter_map(|(fname, rec)| {
+            let at = LocalDateTime::from_epoch(rec.captured_at?, utc_offset_secs);
+            (at.date() == date).then_some((at, fname, rec))
+        })
+        .collect();
+    day.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(b.1)));
+
+    let mut subjects: BTreeMap<(&str, &str), DailySubject> = BTreeMap::new();
+    let mut roles: BTreeMap<String, usize> = BTreeMap::new();
+    let mut boards = Vec::new();
+    for &(at, fname, rec) in &day {
+        subjects
+            .entry((rec.machine_type.as_str(), rec.machine_id.as_str()))
+            .and_modify(|s| {
+                s.photos += 1;
+                s.last = at;
+            })
+            .or_insert_with(|| DailySubject {
+                machine_type: rec.machine_type.clone(),
+                machine_id: rec.machine_id.clone(),
+                photos: 1,
+                first: at,
+                last: at,
+            });
+        *roles.entry(rec.role.clone()).or_default() += 1;
+        if rec.has_board && !rec.detected_text.trim().is_empty() {
+            boards.push(BoardEntry {
+                at,
+                file: fname.clone(),
+                text: rec.detected_text.trim().to_string(),
+            });
+        }
+    }
+
+    let mut subjects: Vec<DailySubject> = subjects.into_values().collect();
+    subjects.sort_by(|a, b| a.first.cmp(&b.first).then(a.machine_type.cmp(&b.machine_type)));
+    let day_groups: BTreeSet<u32> = day.iter().map(|d| d.2.group).collect();
+    let reviews = group_reviews(records.values().filter(|r| day_groups.contains(&r.group)));
+
+    DailyReport {
+        date,
+        photos: day.len(),
+        first: day.first().map(|d| d.0),
+        last: day.last().map(|d| d.0),
+        subjects,
+        roles,
+        boards,
+        reviews,
+    }
+}
+
+/// 使用機械一覧表の 1 行
+#[derive(Debug, Clone)]
+pub struct MachineListRow {
+    pub machine_type: String,
+    pub machine_id: String,
+    pub has_inspection: bool,
+    pub has_emission: bool,
+    pub first_seen: Option<LocalDateTime>,
+    pub photos: usize,
+    /// テンプレート（[machine_sets]）の役割のうち写真の無いもの
+    pub missing: Vec<String>,
+}
+
+/// 機械の役割の写真を 1 枚以上含む (machine_type, machine_id) ごとに 1 行。初回撮影日順。
+pub fn machine_list(records: &GroupRecords, utc_offset_secs: i64, machine_sets: &MachineSets) -> Vec<MachineListRow> {
+    let mut rows: BTreeMap<(&str, &str), MachineListRow> = BTreeMap::new();
+    let mut roles: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for rec in records.values() {
+        let row = rows
+            .entry((rec.machine_type.as_str(), rec.machine_id.as_str()))
+            .or_insert_with(|| MachineListRow {
+                machine_type: rec.machine_type.clone(),
+                machine_id: rec.machine_id.clone(),
+                has_inspection: false,
+                has_emission: false,
+                first_seen: None,
+                photos: 0,
+                missing: Vec::new(),
+            });
+        row.photos += 1;
+        roles.entry((rec.machine_type.as_str(), rec.machine_id.as_str())).or_default().push(rec.role.as_str());
+        row.has_inspection |= is_inspection_role(&rec.role);
+        row.has_emission |= is_emission_role(&rec.role);
+        if let Some(ts) = rec.captured_at {
+            let at = LocalDateTime::from_epoch(ts, utc_offset_secs);
+            row.first_seen = Some(row.first_seen.map_or(at, |f| f.min(at)));
+        }
+    }
+
+    let machines: BTreeSet<(&str, &str)> = records
+        .values()
+        .filter(|r| is_machine_role(&r.role))
+        .map(|r| (r.machine_type.as_str(), r.machine_id.as_str()))
+        .collect();
+    let mut out: Vec<MachineListRow> = rows
+        .into_iter()
+        .filter(|(key, _)| machines.contains(key))
+        .map(|(key, mut row)| {
+            let missing = machine_sets.missing(key.0, roles.get(&key).map_or(&[][..], Vec::as_slice));
+            row.missing = missing.into_iter().map(str::to_string).collect();
+            row
+        })
+        .collect();
+    out.sort_by(|a, b| {
+        a.first_seen
+            .is_none()
+            .cmp(&b.first_seen.is_none())
+            .then(a.first_seen.cmp(&b.first_seen))
+            .then(a.machine_type.cmp(&b.machine_type))
+    });
+    out
+}
+
+/// 運搬台数表の 1 行（1 日・1 台分）
+#[derive(Debug, Clone)]
+pub struct HaulTallyRow {
+    pub date: (i32, u32, u32),
+    pub vehicle: String,
+    /// 別グループ（撮影間隔が GROUP_GAP_SECS を超えたもの）を 1 回の運搬と数える
+    pub trips: usize,
+    pub first: LocalDateTime,
+    pub last: LocalDateTime,
+    pub photos: usize,
+}
+
+/// ダンプ・トラック・運搬の写真か
+pub fn is_haul_record(rec: &GroupRecord) -> bool {
+    ["ダンプ", "トラック"].iter().any(|t| rec.machine_type.contains(t)) || rec.role.contains("運搬")
+}
+
+/// 運搬車両ごと・日ごとの運搬回数。車両番号は machine_id（ナンバー・黒板から読んだもの）。
+/// 撮影時刻の無い写真は数えない。
+pub fn haul_tally(records: &GroupRecords, utc_offset_secs: i64) -> Vec<HaulTallyRow> {
+    // (日付, 車両) → (グループ番号の集合, 行)
+    let mut rows: BTreeMap<(_, &str), (BTreeSet<u32>, HaulTallyRow)> = BTreeMap::new();
+    for rec in records.values().filter(|r| is_haul_record(r)) {
+        let Some(ts) = rec.captured_at else { continue };
+        let at = LocalDateTime::from_epoch(ts, utc_offset_secs);
+        let vehicle = if rec.machine_id.is_empty() { "不明" } else { rec.machine_id.as_str() };
+        let (groups, row) = rows.entry((at.date(), vehicle)).or_insert_with(|| {
+            let row = HaulTallyRow {
+                date: at.date(),
+                vehicle: vehicle.to_string(),
+                trips: 0,
+                first: at,
+                last: at,
+                photos: 0,
+            };
+            (BTreeSet::new(), row)
+        });
+        groups.insert(rec.group);
+        row.photos += 1;
+        row.first = row.first.min(at);
+        row.last = row.last.max(at);
+    }
+    rows.into_values()
+        .map(|(groups, mut row)| {
+            row.trips = groups.len();
+            row
+        })
+        .collect()
+}
+
+pub fn haul_tally_csv(rows: &[HaulTallyRow]) -> String {
+    to_csv(
+        &["日付", "車両番号", "運搬回数", "初回", "最終", "写真枚数"],
+        rows.iter().map(|r| {
+            vec![
+                r.first.date_string(),
+                r.vehicle.clone(),
+                r.trips.to_string(),
+                r.first.time_string(),
+                r.last.time_string(),
+                r.photos.to_string(),
+            ]
+        }),
+    )
+}
+
+/// CSV の 1 フィールド（必要なときだけ引用符で囲む）
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Excel で文字化けしないよう BOM 付き UTF-8 の CSV を組み立てる
+pub fn to_csv<R: AsRef<[String]>>(header: &[&str], rows: impl IntoIterator<Item = R>) -> String {
+    let mut out = String::from("\u{feff}");
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    for row in rows {
+        let fields: Vec<String> = row.as_ref().iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// to_csv の逆。BOM・CRLF・引用符付きフィールド（改行を含むものも）を読む。空行は飛ばす。
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+const MACHINE_LIST_HEADER: [&str; 7] =
+    ["機械名", "型式", "特定自主検査証票", "排ガス・低騒音証票", "初回撮影日", "写真枚数", "不足の写真"];
+
+fn mark(b: bool) -> &'static str {
+    if b {
+        "有"
+    } else {
+        "無"
+    }
+}
+
+pub fn machine_list_csv(rows: &[MachineListRow]) -> String {
+    to_csv(
+        &MACHINE_LIST_HEADER,
+        rows.iter().map(|r| {
+            vec![
+                r.machine_type.clone(),
+                r.machine_id.clone(),
+                mark(r.has_inspection).to_string(),
+                mark(r.has_emission).to_string(),
+                r.first_seen.map(|d| d.date_string()).unwrap_or_default(),
+                r.photos.to_string(),
+                r.missing.join("・"),
+            ]
+        }),
+    )
+}
+
+/// machine_list_csv と同じ表の xlsx シート（写真枚数は数値のセル）
+pub fn machine_list_sheet(rows: &[MachineListRow]) -> Sheet {
+    let mut sheet = Sheet::new("使用機械一覧");
+    sheet.push(MACHINE_LIST_HEADER);
+    for r in rows {
+        sheet.push([
+            Cell::from(r.machine_type.as_str()),
+            Cell::from(r.machine_id.as_str()),
+            Cell::from(mark(r.has_inspection)),
+            Cell::from(mark(r.has_emission)),
+            Cell::from(r.first_seen.map(|d| d.date_string()).unwrap_or_default()),
+            Cell::from(r.photos),
+            Cell::from(r.missing.join("・")),
+        ]);
+    }
+    sheet
+}
+
+/// 使用機械の稼働記録の 1 行（1 台・1 日分）
+#[derive(Debug, Clone)]
+pub struct MachineDayRow {
+    pub machine_type: String,
+    pub machine_id: String,
+    pub date: (i32, u32, u32),
+    pub first: LocalDateTime,
+    pub last: LocalDateTime,
+    pub photos: usize,
+}
+
+/// machine_list と同じ機械について、写っていた日ごとの初回・最終の撮影時刻。
+/// 使用機械届の使用期間の確認用。撮影時刻の無い写真は数えない。機械の初回撮影日順、同じ機械は日付順。
+pub fn machine_timeline(records: &GroupRecords, utc_offset_secs: i64) -> Vec<MachineDayRow> {
+    let machines: BTreeSet<(&str, &str)> = records
+        .values()
+        .filter(|r| is_machine_role(&r.role))
+        .map(|r| (r.machine_type.as_str(), r.machine_id.as_str()))
+        .collect();
+    // ((機械名, 型式), 日付) → 行
+    let mut rows: BTreeMap<(_, (i32, u32, u32)), MachineDayRow> = BTreeMap::new();
+    for rec in records.values() {
+        let key = (rec.machine_type.as_str(), rec.machine_id.as_str());
+        let Some(ts) = rec.captured_at.filter(|_| machines.contains(&key)) else { continue };
+        let at = LocalDateTime::from_epoch(ts, utc_offset_secs);
+        let row = rows.entry((key, at.date())).or_insert_with(|| MachineDayRow {
+            machine_type: rec.machine_type.clone(),
+            machine_id: rec.machine_id.clone(),
+            date: at.date(),
+            first: at,
+            last: at,
+            photos: 0,
+        });
+        row.photos += 1;
+        row.first = row.first.min(at);
+        row.last = row.last.max(at);
+    }
+
+    let mut first_day: BTreeMap<(&str, &str), (i32, u32, u32)> = BTreeMap::new();
+    for (key, date) in rows.keys() {
+        first_day.entry(*key).or_insert(*date);
+    }
+    let mut out: Vec<MachineDayRow> = rows.into_values().collect();
+    out.sort_by(|a, b| {
+        let key = |r: &MachineDayRow| {
+            let m = (r.machine_type.as_str(), r.machine_id.as_str());
+            (first_day[&m], r.machine_type.clone(), r.machine_id.clone(), r.date)
+        };
+        key(a).cmp(&key(b))
+    });
+    out
+}
+
+pub fn machine_timeline_csv(rows: &[MachineDayRow]) -> String {
+    to_csv(
+        &["機械名", "型式", "日付", "初回", "最終", "写真枚数"],
+        rows.iter().map(|r| {
+            vec![
+                r.machine_type.clone(),
+                r.machine_id.clone(),
+                r.first.date_string(),
+                r.first.time_string(),
+                r.last.time_string(),
+                r.photos.to_string(),
+            ]
+        }),
+    )
+}
+
+/// 機械ごとの稼働日を横棒で並べた HTML（SVG のガントチャート）。
+/// 薄い帯が初回〜最終の期間、濃いマスが実際に写っていた日。マスにカーソルを置くと時刻と枚数を出す。
+pub fn machine_timeline_html(folder: &str, rows: &[MachineDayRow]) -> String {
+    const LABEL_W: i64 = 220;
+    const DAY_W: i64 = 22;
+    const ROW_H: i64 = 24;
+    const HEAD_H: i64 = 40;
+
+    let day = |d: (i32, u32, u32)| days_from_civil(d.0 as i64, d.1, d.2);
+    let title = format!("使用機械の稼働日: {folder}");
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+         <body>\n<h1>{0}</h1>",
+        html_escape(&title)
+    );
+    let (Some(start), Some(end)) = (rows.iter().map(|r| day(r.date)).min(), rows.iter().map(|r| day(r.date)).max())
+    else {
+        let _ = writeln!(out, "<p>撮影時刻のある機械の写真がありません。</p>\n</body>\n</html>");
+        return out;
+    };
+
+    let mut machines: Vec<(&str, &str)> = Vec::new();
+    for r in rows {
+        let key = (r.machine_type.as_str(), r.machine_id.as_str());
+        if !machines.contains(&key) {
+            machines.push(key);
+        }
+    }
+    let width = LABEL_W + (end - start + 1) * DAY_W + 10;
+    let height = HEAD_H + machines.len() as i64 * ROW_H + 10;
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         font-family=\"sans-serif\" font-size=\"12\">"
+    );
+    // 日付の目盛り（日数が多いときは月曜だけ）
+    let every_day = end - start < 45;
+    for d in start..=end {
+        let (_, m, dd) = civil_from_days(d);
+        let x = LABEL_W + (d - start) * DAY_W;
+        if every_day || (d + 3).rem_euclid(7) == 0 {
+            let _ = writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\" transform=\"rotate(-45 {0} {1})\">{m}/{dd}</text>",
+                x + 4,
+                HEAD_H - 6
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<line x1=\"{x}\" y1=\"{HEAD_H}\" x2=\"{x}\" y2=\"{}\" stroke=\"#eee\"/>",
+            height - 10
+        );
+    }
+    for (i, key) in machines.iter().enumerate() {
+        let y = HEAD_H + i as i64 * ROW_H;
+        let days: Vec<&MachineDayRow> =
+            rows.iter().filter(|r| (r.machine_type.as_str(), r.machine_id.as_str()) == *key).collect();
+        let label = if key.1.is_empty() { key.0.to_string() } else { format!("{} {}", key.0, key.1) };
+        let _ = writeln!(out, "<text x=\"4\" y=\"{}\">{}</text>", y + 16, html_escape(&label));
+        let first = day(days[0].date);
+        let last = day(days[days.len() - 1].date);
+        let _ = writeln!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#cfe0f3\"/>",
+            LABEL_W + (first - start) * DAY_W,
+            y + 6,
+            (last - first + 1) * DAY_W,
+            ROW_H - 12
+        );
+        for r in days {
+            let _ = writeln!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#2f6db3\">\
+                 <title>{} {}–{}（{} 枚）</title></rect>",
+                LABEL_W + (day(r.date) - start) * DAY_W + 1,
+                y + 3,
+                DAY_W - 2,
+                ROW_H - 6,
+                r.first.date_string(),
+                r.first.time_string(),
+                r.last.time_string(),
+                r.photos
+            );
+        }
+    }
+    let _ = writeln!(out, "</svg>\n</body>\n</html>");
+    out
+}
+
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+impl DailyReport {
+    pub fn to_markdown(&self) -> String {
+        let (y, m, d) = self.date;
+        let mut out = String::new();
+        let _ = writeln!(out, "# 日報 {y:04}-{m:02}-{d:02}\n");
+        let _ = writeln!(out, "- 写真枚数: {}", self.photos);
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let _ = writeln!(out, "- 撮影時間: {} 〜 {}", first.time_string(), last.time_string());
+        }
+
+        let _ = writeln!(out, "\n## 作業・使用機械\n");
+        let _ = writeln!(out, "| 種類 | 識別 | 枚数 | 最初 | 最後 |");
+        let _ = writeln!(out, "|---|---|---:|---|---|");
+        for s in &self.subjects {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                cell(&s.machine_type),
+                cell(&s.machine_id),
+                s.photos,
+                s.first.time_string(),
+                s.last.time_string()
+            );
+        }
+
+        let _ = writeln!(out, "\n## 写真区分\n");
+        let _ = writeln!(out, "| 区分 | 枚数 |");
+        let _ = writeln!(out, "|---|---:|");
+        for (role, n) in &self.roles {
+            let _ = writeln!(out, "| {} | {n} |", cell(role));
+        }
+
+        if !self.boards.is_empty() {
+            let _ = writeln!(out, "\n## 黒板記載\n");
+            for b in &self.boards {
+                let _ = writeln!(out, "- {} `{}` {}", b.at.time_string(), b.file, cell(&b.text));
+            }
+        }
+
+        if !self.reviews.is_empty() {
+            let _ = writeln!(out, "\n## グループの確認状況\n");
+            let _ = writeln!(out, "| グループ | 種類 | 識別 | 枚数 | 状態 | メモ |");
+            let _ = writeln!(out, "|---:|---|---|---:|---|---|");
+            for r in &self.reviews {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} |",
+                    r.group,
+                    cell(&r.machine_type),
+                    cell(&r.machine_id),
+                    r.photos,
+                    r.status.label(),
+                    cell(&r.notes)
+                );
+            }
+        }
+        out
+    }
+
+    /// to_markdown と同じ内容を xlsx のシートにする（表ごとに 1 枚）
+    pub fn to_sheets(&self) -> Vec<Sheet> {
+        let (y, m, d) = self.date;
+        let mut day = Sheet::new("日報");
+        day.push(["日付".to_string(), format!("{y:04}-{m:02}-{d:02}")]);
+        day.push([Cell::from("写真枚数"), Cell::from(self.photos)]);
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            day.push(["撮影時間".to_string(), format!("{} 〜 {}", first.time_string(), last.time_string())]);
+        }
+        day.push([""]);
+        day.push(["種類", "識別", "枚数", "最初", "最後"]);
+        for s in &self.subjects {
+            day.push([
+                Cell::from(s.machine_type.as_str()),
+                Cell::from(s.machine_id.as_str()),
+                Cell::from(s.photos),
+                Cell::from(s.first.time_string()),
+                Cell::from(s.last.time_string()),
+            ]);
+        }
+
+        let mut roles = Sheet::new("写真区分");
+        roles.push(["区分", "枚数"]);
+        for (role, n) in &self.roles {
+            roles.push([Cell::from(role.as_str()), Cell::from(*n)]);
+        }
+        let mut sheets = vec![day, roles];
+
+        if !self.boards.is_empty() {
+            let mut boards = Sheet::new("黒板記載");
+            boards.push(["時刻", "ファイル", "記載"]);
+            for b in &self.boards {
+                boards.push([b.at.time_string(), b.file.clone(), b.text.clone()]);
+            }
+            sheets.push(boards);
+        }
+
+        if !self.reviews.is_empty() {
+            let mut reviews = Sheet::new("グループの確認状況");
+            reviews.push(["グループ", "種類", "識別", "枚数", "状態", "メモ"]);
+            for r in &self.reviews {
+                reviews.push([
+                    Cell::from(r.group),
+                    Cell::from(r.machine_type.as_str()),
+                    Cell::from(r.machine_id.as_str()),
+                    Cell::from(r.photos),
+                    Cell::from(r.status.label()),
+                    Cell::from(r.notes.as_str()),
+                ]);
+            }
+            sheets.push(reviews);
+        }
+        sheets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(role: &str, machine_type: &str, machine_id: &str, group: u32, captured_at: Option<i64>) -> GroupRecord {
+        serde_json::from_value(serde_json::json!({
+            "role": role,
+            "machine_type": machine_type,
+            "machine_id": machine_id,
+            "group": group,
+            "captured_at": captured_at,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn csv_round_trips_quotes_commas_and_newlines() {
+        let rows = vec![
+            vec!["IMG_0001.JPG".to_string(), "舗装工, 1 層目".to_string(), "黒板\r\n\"No.3\"".to_string()],
+            vec!["IMG_0002.JPG".to_string(), String::new(), "改行\nだけ".to_string()],
+        ];
+        let csv = to_csv(&["file", "role", "text"], &rows);
+        assert!(csv.starts_with('\u{feff}'));
+        assert!(csv.contains("\"舗装工, 1 層目\""));
+        assert!(csv.contains("\"黒板\r\n\"\"No.3\"\"\""));
+
+        let parsed = parse_csv(&csv);
+        assert_eq!(parsed[0], ["file", "role", "text"]);
+        assert_eq!(parsed[1..], rows[..]);
+    }
+
+    #[test]
+    fn parse_csv_skips_blank_lines_and_reads_a_last_line_without_newline() {
+        assert_eq!(parse_csv("a,b\r\n\r\n,\r\nc,\"d\""), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn haul_tally_counts_each_group_as_one_trip() {
+        let day = 20_000 * 86_400;
+        let haul = |id, group, at| record("運搬状況", "ダンプトラック", id, group, at);
+        let records: GroupRecords = [
+            ("a1.jpg", haul("12-34", 1, Some(day + 3600))),
+            ("a2.jpg", haul("12-34", 1, Some(day + 3660))),
+            ("a3.jpg", haul("12-34", 4, Some(day + 7200))),
+            ("b1.jpg", haul("", 2, Some(day + 5400))),
+            ("c1.jpg", haul("12-34", 7, Some(day + 86_400 + 3600))),
+            ("d1.jpg", haul("12-34", 9, None)),
+            ("e1.jpg", record("機械全景", "タイヤローラー", "12-34", 3, Some(day + 4000))),
+        ]
+        .into_iter()
+        .map(|(f, r)| (f.to_string(), r))
+        .collect();
+
+        let rows = haul_tally(&records, 0);
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|r| (r.first.date_string(), r.vehicle.as_str(), r.trips, r.photos, r.first.time_string(), r.last.time_string()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("2024-10-04".to_string(), "12-34", 2, 3, "01:00".to_string(), "02:00".to_string()),
+                ("2024-10-04".to_string(), "不明", 1, 1, "01:30".to_string(), "01:30".to_string()),
+                ("2024-10-05".to_string(), "12-34", 1, 1, "01:00".to_string(), "01:00".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fs_ops::{long_path, record_key, state_dir, write_atomic};
+
+/// 実行中のバッチの送信・応答の状態（.photo-tagger/ 内）。実行が最後まで終わり、すべて応答済みなら削除する。
+pub const RETRY_STATE_FILE: &str = "batch-state.json";
+
+/// バッチの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// まだ送っていない
+    Planned,
+    /// 送ったが応答が無い（実行が途中で止まった）
+    Sent,
+    /// 応答を読み取り、ジャーナルに書いた
+    Answered,
+    /// 応答はあったが JSON として読み取れなかった
+    Unparsed,
+    /// 送信に失敗した（バックエンドのエラーなど）
+    Failed,
+}
+
+/// バッチ 1 件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchState {
+    pub batch: usize,
+    /// レコードのキー（ファイル名）
+    pub files: Vec<String>,
+    pub status: BatchStatus,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetryState {
+    pub batches: Vec<BatchState>,
+}
+
+/// 前回の実行で応答を得られなかった写真の内訳
+#[derive(Debug, Clone, Default)]
+pub struct Unfinished {
+    pub never_sent: Vec<String>,
+    pub unanswered: Vec<String>,
+    pub unparsed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl Unfinished {
+    pub fn is_empty(&self) -> bool {
+        self.never_sent.is_empty() && self.unanswered.is_empty() && self.unparsed.is_empty() && self.failed.is_empty()
+    }
+}
+
+pub fn retry_state_path(base: &Path) -> PathBuf {
+    state_dir(base).join(RETRY_STATE_FILE)
+}
+
+impl RetryState {
+    /// 送る予定のバッチ（1 番から）をすべて Planned で作る
+    pub fn planned(batches: &[Vec<PathBuf>]) -> Self {
+        let batches = batches
+            .iter()
+            .enumerate()
+            .map(|(i, batch)| BatchState {
+                batch: i + 1,
+                files: batch
+                    .iter()
+                    .filter_map(|p| p.file_name()?.to_str().map(record_key))
+                    .collect(),
+                status: BatchStatus::Planned,
+                error: String::new(),
+            })
+            .collect();
+        RetryState { batches }
+    }
+
+    /// 前回の状態。無い・読めなければ None（読めない場合は次の実行で上書きされる）
+    pub fn load(base: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(long_path(&retry_state_path(base))).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self, base: &Path) -> Result<()> {
+        let dir = state_dir(base);
+        std::fs::create_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Parse {
+            message: "Failed to serialize batch state".into(),
+            source: Some(e),
+        })?;
+        write_atomic(&retry_state_path(base), json.as_bytes())
+    }
+
+    pub fn remove(base: &Path) -> Result<()> {
+        let path = retry_state_path(base);
+        match std::fs::remove_file(long_path(&path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::io(path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set(&mut self, batch: usize, status: BatchStatus, error: &str) {
+        if let Some(b) = self.batches.iter_mut().find(|b| b.batch == batch) {
+            b.status = status;
+            b.error = error.to_string();
+        }
+    }
+
+    pub fn all_answered(&self) -> bool {
+        self.batches.iter().all(|b| b.status == BatchStatus::Answered)
+    }
+
+    /// 今回も未分類（pending）の写真を、前回の状態ごとに分ける。応答済みの写真は含めない。
+    pub fn unfinished(&self, pending: &HashSet<String>) -> Unfinished {
+        let mut out = Unfinished::default();
+        for b in &self.batches {
+            let list = match b.status {
+                BatchStatus::Planned => &mut out.never_sent,
+                BatchStatus::Sent => &mut out.unanswered,
+                BatchStatus::Unparsed => &mut out.unparsed,
+                BatchStatus::Failed => &mut out.failed,
+                BatchStatus::Answered => continue,
+            };
+            list.extend(b.files.iter().filter(|f| pending.contains(*f)).cloned());
+        }
+        out
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::domain::{is_machine_role, GroupRecord, GroupRecords, ReviewStatus};
+use crate::error::{Error, Result};
+use crate::exif::CameraInfo;
+use crate::fs_ops::record_key;
+use crate::machine_set::MachineSets;
+use crate::report::{parse_csv, to_csv};
+use crate::validate::{validate_group_records, IssueKind, WorkingHours};
+
+/// 確認キュー CSV の列。new_* 列に記入された値だけを取り込む。
+pub const REVIEW_COLUMNS: [&str; 10] = [
+    "file",
+    "issue",
+    "detail",
+    "suggestion",
+    "role",
+    "machine_type",
+    "machine_id",
+    "new_role",
+    "new_machine_type",
+    "new_machine_id",
+];
+
+/// 確認キューの 1 行（写真 1 枚 × 問題 1 件）
+#[derive(Debug, Clone)]
+pub struct ReviewRow {
+    pub file: String,
+    pub kind: IssueKind,
+    pub detail: String,
+    pub suggestion: String,
+    pub record: Option<GroupRecord>,
+}
+
+/// 人が記入した修正内容（空欄は変更なし）
+#[derive(Debug, Clone, Default)]
+pub struct ReviewDecision {
+    pub file: String,
+    pub role: Option<String>,
+    pub machine_type: Option<String>,
+    pub machine_id: Option<String>,
+}
+
+impl ReviewDecision {
+    fn is_empty(&self) -> bool {
+        self.role.is_none() && self.machine_type.is_none() && self.machine_id.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReviewOutcome {
+    pub applied: usize,
+    /// レコードが無く、役割・機械種別・型式のすべてが記入されていなかった写真
+    pub skipped: Vec<String>,
+}
+
+fn suggestion(kind: IssueKind, detail: &str) -> String {
+    match kind {
+        IssueKind::ClassificationError => "再分類する".into(),
+        IssueKind::Unassigned if detail == "no record" => {
+            "再分類するか、new_role / new_machine_type / new_machine_id を記入".into()
+        }
+        IssueKind::Unassigned => "グループ分けを再実行する".into(),
+        IssueKind::IncompleteMachineSet => format!("役割の誤りを確認（{detail}）"),
+        IssueKind::DateMismatch => "撮影日を確認し、必要なら該当日のフォルダへ移動".into(),
+        IssueKind::OffHours => "カメラの時計を確認し、撮影時刻がずれていればファイル名を直す".into(),
+    }
+}
+
+/// 検査結果を写真単位の確認キューに展開する。機械単位の問題はその機械の写真ごとに 1 行。
+pub fn review_queue(
+    folder: &Path,
+    records: &GroupRecords,
+    images: &[PathBuf],
+    utc_offset_secs: i64,
+    working_hours: Option<&WorkingHours>,
+    machine_sets: &MachineSets,
+) -> Vec<ReviewRow> {
+    let mut rows = Vec::new();
+    for issue in validate_group_records(folder, records, images, utc_offset_secs, working_hours, machine_sets) {
+        let files: Vec<&String> = if issue.kind == IssueKind::IncompleteMachineSet {
+            records
+                .iter()
+                .filter(|(_, rec)| {
+                    is_machine_role(&rec.role)
+                        && issue.subject == format!("{} ({})", rec.machine_type, rec.machine_id)
+                })
+                .map(|(fname, _)| fname)
+                .collect()
+        } else {
+            vec![&issue.subject]
+        };
+        for file in files {
+            rows.push(ReviewRow {
+                file: file.clone(),
+                kind: issue.kind,
+                detail: issue.detail.clone(),
+                suggestion: suggestion(issue.kind, &issue.detail),
+                record: records.get(file).cloned(),
+            });
+        }
+    }
+    rows
+}
+
+pub fn review_csv(rows: &[ReviewRow]) -> String {
+    to_csv(
+        &REVIEW_COLUMNS,
+        rows.iter().map(|r| {
+            let (role, machine_type, machine_id) = match &r.record {
+                Some(rec) => (rec.role.clone(), rec.machine_type.clone(), rec.machine_id.clone()),
+                None => Default::default(),
+            };
+            vec![
+                r.file.clone(),
+                r.kind.label().to_string(),
+                r.detail.clone(),
+                r.suggestion.clone(),
+                role,
+                machine_type,
+                machine_id,
+                String::new(),
+                String::new(),
+                String::new(),
+            ]
+        }),
+    )
+}
+
+/// 記入済みの確認キューを読む。列は見出し名で探すので並べ替え・列追加をしてもよい。
+pub fn parse_review_csv(text: &str) -> Result<Vec<ReviewDecision>> {
+    let mut rows = parse_csv(text).into_iter();
+    let header = rows.next().unwrap_or_default();
+    let col = |name: &str| header.iter().position(|h| h.trim() == name);
+    let file_col = col("file").ok_or_else(|| Error::Validation("review queue: missing column `file`".into()))?;
+    let (role_col, type_col, id_col) = (col("new_role"), col("new_machine_type"), col("new_machine_id"));
+
+    let value = |row: &[String], c: Option<usize>| {
+        c.and_then(|c| row.get(c))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    Ok(rows
+        .filter_map(|row| {
+            let decision = ReviewDecision {
+                file: record_key(row.get(file_col)?.trim()),
+                role: value(&row, role_col),
+                machine_type: value(&row, type_col),
+                machine_id: value(&row, id_col),
+            };
+            (!decision.file.is_empty() && !decision.is_empty()).then_some(decision)
+        })
+        .collect())
+}
+
+/// 修正内容をレコードに反映してロックする。同じ写真の行が複数あれば後の行が優先。
+/// グループ番号は呼び出し側で付け直すこと。
+pub fn apply_review(records: &mut GroupRecords, decisions: &[ReviewDecision]) -> ReviewOutcome {
+    let mut merged: HashMap<&str, ReviewDecision> = HashMap::new();
+    for d in decisions {
+        let entry = merged.entry(d.file.as_str()).or_insert_with(|| ReviewDecision {
+            file: d.file.clone(),
+            ..Default::default()
+        });
+        entry.role = d.role.clone().or(entry.role.take());
+        entry.machine_type = d.machine_type.clone().or(entry.machine_type.take());
+        entry.machine_id = d.machine_id.clone().or(entry.machine_id.take());
+    }
+
+    let mut outcome = ReviewOutcome::default();
+    let mut files: Vec<&str> = merged.keys().copied().collect();
+    files.sort();
+    for file in files {
+        let d = &merged[file];
+        match records.get_mut(file) {
+            Some(rec) => {
+                rec.locked = true;
+                if let Some(v) = &d.role {
+                    rec.role = v.clone();
+                }
+                if let Some(v) = &d.machine_type {
+                    rec.machine_type = v.clone();
+                }
+                if let Some(v) = &d.machine_id {
+                    rec.machine_id = v.clone();
+                }
+            }
+            None => match (&d.role, &d.machine_type, &d.machine_id) {
+                (Some(role), Some(machine_type), Some(machine_id)) => {
+                    records.insert(
+                        file.to_string(),
+                        GroupRecord {
+                            role: role.clone(),
+                            machine_type: machine_type.clone(),
+                            machine_id: machine_id.clone(),
+                            group: 0,
+                            has_board: false,
+                            detected_text: String::new(),
+                            description: String::new(),
+                            captured_at: None,
+                            clock_offset: 0,
+                            camera: CameraInfo::default(),
+                            content_hash: String::new(),
+                            caption: String::new(),
+                            locked: true,
+                            group_notes: String::new(),
+                            group_status: ReviewStatus::Unreviewed,
+                        },
+                    );
+                }
+                _ => {
+                    outcome.skipped.push(file.to_string());
+                    continue;
+                }
+            },
+        }
+        outcome.applied += 1;
+    }
+    outcome
+}
@@ -0,0 +1,97 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::fs_ops::{fnv1a64, state_dir};
+use crate::grouping::GROUP_GAP_SECS;
+
+const RUN_META_FILE: &str = "run-meta.json";
+
+/// 実行条件の記録（.photo-tagger/run-meta.json）。
+/// 同じフォルダで結果が変わったとき、どの版・設定で実行したかを後から確認するためのもの。
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMeta {
+    pub tool_version: &'static str,
+    pub mode: &'static str,
+    pub backend: String,
+    pub batch_size: usize,
+    pub concurrency: usize,
+    pub auto_concurrency: bool,
+    pub force_reclassify: bool,
+    pub renumber: bool,
+    /// 前のバッチの結果をプロンプトに添えたか（--batch-context）
+    pub batch_context: bool,
+    pub group_gap_secs: i64,
+    /// 語彙リストの FNV-1a ハッシュ（語彙指定なしは None）
+    pub vocabulary_hash: Option<String>,
+    pub timestamp_patterns: Vec<String>,
+    pub utc_offset: String,
+    pub images: usize,
+    pub pending: usize,
+    /// Unix 秒
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+impl RunMeta {
+    /// グループ分けの実行開始時点の記録を作る
+    pub fn group(config: &Config, backend: &str, batch_size: usize, concurrency: usize) -> Self {
+        RunMeta {
+            tool_version: env!("CARGO_PKG_VERSION"),
+            mode: "group",
+            backend: backend.to_string(),
+            batch_size,
+            concurrency,
+            auto_concurrency: false,
+            force_reclassify: crate::force_reclassify_enabled(),
+            renumber: false,
+            batch_context: false,
+            group_gap_secs: GROUP_GAP_SECS,
+            vocabulary_hash: None,
+            timestamp_patterns: config.timestamp.patterns.clone(),
+            utc_offset: config.timestamp.utc_offset.clone(),
+            images: 0,
+            pending: 0,
+            started_at: now_secs(),
+            finished_at: None,
+        }
+    }
+
+    pub fn with_vocabulary(mut self, vocabulary: Option<&[String]>) -> Self {
+        self.vocabulary_hash = vocabulary.map(vocabulary_hash);
+        self
+    }
+
+    /// 終了時刻を記録して保存する
+    pub fn finish(&mut self, base: &Path) -> Result<PathBuf> {
+        self.finished_at = Some(now_secs());
+        self.save(base)
+    }
+
+    pub fn save(&self, base: &Path) -> Result<PathBuf> {
+        let dir = state_dir(base);
+        std::fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        let path = dir.join(RUN_META_FILE);
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Parse {
+            message: "Failed to serialize run metadata".into(),
+            source: Some(e),
+        })?;
+        std::fs::write(&path, json).map_err(|e| Error::io(&path, e))?;
+        Ok(path)
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// 語彙の順序込みで安定なハッシュ（16 桁の 16 進）
+pub fn vocabulary_hash(vocabulary: &[String]) -> String {
+    let joined: String = vocabulary.iter().map(|w| format!("{w}\n")).collect();
+    format!("{:016x}", fnv1a64(joined.as_bytes()))
+}
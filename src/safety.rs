@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::domain::GroupRecord;
+use crate::project::{week_start, FolderRecords};
+use crate::summary::html_escape;
+use crate::timestamp::{civil_from_days, days_from_civil, LocalDateTime};
+
+/// 安全管理の写真の場面。文言の並び順に判定する。
+const SCENES: [(&str, &[&str]); 4] = [
+    ("保安施設設置", &["保安施設", "保安設備", "バリケード", "カラーコーン", "工事看板", "交通誘導"]),
+    ("KY活動", &["KY", "危険予知", "TBM", "ツールボックス"]),
+    ("巡視", &["巡視", "パトロール", "点検巡回"]),
+    ("安全管理", &["安全", "朝礼", "保安"]),
+];
+
+/// 役割・黒板・説明から安全管理の写真の場面を判定する。安全管理の写真でなければ None。
+pub fn safety_scene(rec: &GroupRecord) -> Option<&'static str> {
+    safety_scene_in(&format!("{} {} {}", rec.role, rec.detected_text, rec.description))
+}
+
+/// 文字から安全管理の写真の場面を判定する（safety_scene の本体）
+pub fn safety_scene_in(text: &str) -> Option<&'static str> {
+    let text: String = text.nfkc().collect();
+    let text = text.to_uppercase();
+    SCENES.iter().find(|(_, words)| words.iter().any(|w| text.contains(w))).map(|(scene, _)| *scene)
+}
+
+/// ISO 8601 の (年, 週番号)。週は月曜始まりで、その年の最初の木曜日を含む週が第 1 週。
+pub fn iso_week(date: (i32, u32, u32)) -> (i32, u32) {
+    let days = days_from_civil(date.0 as i64, date.1, date.2);
+    // 同じ週の木曜日の年がその週の年（1970-01-01 は木曜）
+    let thursday = days - (days + 3).rem_euclid(7) + 3;
+    let year = civil_from_days(thursday).0;
+    let jan1 = days_from_civil(year as i64, 1, 1);
+    (year, ((thursday - jan1) / 7 + 1) as u32)
+}
+
+/// アルバムの写真 1 枚
+#[derive(Debug, Clone)]
+pub struct SafetyPhoto {
+    /// 日付フォルダの名前
+    pub folder: String,
+    pub file: String,
+    pub scene: &'static str,
+    pub date: (i32, u32, u32),
+    /// 撮影時刻が無ければ None（日付はフォルダ名の日付）
+    pub at: Option<LocalDateTime>,
+    /// caption があればそれ、無ければ役割
+    pub caption: String,
+    pub board: String,
+}
+
+/// 1 週分の安全管理写真
+#[derive(Debug, Clone)]
+pub struct SafetyWeek {
+    pub year: i32,
+    pub week: u32,
+    /// 週の月曜日
+    pub monday: (i32, u32, u32),
+    pub photos: Vec<SafetyPhoto>,
+}
+
+impl SafetyWeek {
+    /// 出力ファイル名（安全管理写真_2026-W07.html）
+    pub fn file_name(&self) -> String {
+        format!("安全管理写真_{}-W{:02}.html", self.year, self.week)
+    }
+}
+
+/// 日付フォルダのレコードから安全管理の写真を集め、ISO 週ごと・撮影順にまとめる
+pub fn weekly_albums(folders: &[FolderRecords]) -> Vec<SafetyWeek> {
+    let mut weeks: BTreeMap<(i32, u32), Vec<SafetyPhoto>> = BTreeMap::new();
+    for f in folders {
+        for (file, rec) in &f.records {
+            let Some(scene) = safety_scene(rec) else { continue };
+            let at = rec.captured_at.map(|ts| LocalDateTime::from_epoch(ts, f.utc_offset_secs));
+            let date = at.map_or(f.date, |a| a.date());
+            weeks.entry(iso_week(date)).or_default().push(SafetyPhoto {
+                folder: f.folder.clone(),
+                file: file.clone(),
+                scene,
+                date,
+                at,
+                caption: if rec.caption.is_empty() { rec.role.clone() } else { rec.caption.clone() },
+                board: rec.detected_text.replace(['\n', '\r'], " "),
+            });
+        }
+    }
+    weeks
+        .into_iter()
+        .map(|((year, week), mut photos)| {
+            photos.sort_by(|a, b| a.date.cmp(&b.date).then(a.at.cmp(&b.at)).then(a.file.cmp(&b.file)));
+            let monday = week_start(photos[0].date);
+            SafetyWeek { year, week, monday, photos }
+        })
+        .collect()
+}
+
+/// 写真へのリンクに使えない文字だけを符号化する
+fn href(path: &str) -> String {
+    path.chars()
+        .map(|c| match c {
+            '%' => "%25".to_string(),
+            '#' => "%23".to_string(),
+            '?' => "%3F".to_string(),
+            ' ' => "%20".to_string(),
+            '\\' => "/".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// 1 週分のアルバムページ。写真は root_href（出力先から工事フォルダへの相対パス）/日付フォルダ/ファイル で参照する。
+pub fn album_html(week: &SafetyWeek, project: &str, root_href: &str) -> String {
+    let sunday = {
+        let d = days_from_civil(week.monday.0 as i64, week.monday.1, week.monday.2);
+        civil_from_days(d + 6)
+    };
+    let title = format!(
+        "安全管理写真 {}年 第{}週（{}/{}〜{}/{}）",
+        week.year, week.week, week.monday.1, week.monday.2, sunday.1, sunday.2
+    );
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head><meta charset=\"utf-8\"><title>{0}</title>\n<style>\
+         figure{{display:inline-block;width:320px;margin:8px;vertical-align:top}}\
+         img{{width:320px}}figcaption{{font-size:13px}}\
+         </style></head>\n<body>\n<h1>{0}</h1>\n<p>{1} / {2} 枚</p>",
+        html_escape(&title),
+        html_escape(project),
+        week.photos.len()
+    );
+    let mut day = None;
+    for p in &week.photos {
+        if day != Some(p.date) {
+            if day.is_some() {
+                let _ = writeln!(out, "</section>");
+            }
+            day = Some(p.date);
+            let _ = writeln!(out, "<section>\n<h2>{}/{}/{}</h2>", p.date.0, p.date.1, p.date.2);
+        }
+        let src = href(&format!("{root_href}/{}/{}", p.folder, p.file));
+        let time = p.at.map(|a| a.time_string()).unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "<figure><img src=\"{}\" alt=\"{}\" loading=\"lazy\"><figcaption>{} {}<br>{}<br><small>{}</small></figcaption></figure>",
+            html_escape(&src),
+            html_escape(&p.file),
+            html_escape(&time),
+            html_escape(p.scene),
+            html_escape(&p.caption),
+            html_escape(&p.board)
+        );
+    }
+    if day.is_some() {
+        let _ = writeln!(out, "</section>");
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
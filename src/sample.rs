@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// --sample で写真を選ぶ方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleStrategy {
+    /// 撮影時刻（時:分）順に並べて等間隔に選ぶ。朝礼・作業中・片付けなど 1 日の場面がまんべんなく入る。
+    TimeOfDay,
+    /// 無作為に選ぶ（同じ seed なら同じ写真）
+    Random { seed: u64 },
+}
+
+/// images から n 枚を選ぶ。n が枚数以上なら全部。結果はファイル名順。
+/// capture_times はファイル名（レコードのキー）→ 撮影時刻（Unix 秒）。時刻の無い写真は 1 日の最後として扱う。
+pub fn sample_images(
+    images: &[PathBuf],
+    n: usize,
+    capture_times: &HashMap<String, i64>,
+    utc_offset_secs: i64,
+    strategy: SampleStrategy,
+) -> Vec<PathBuf> {
+    if n >= images.len() {
+        return images.to_vec();
+    }
+    let mut picked: Vec<PathBuf> = match strategy {
+        SampleStrategy::TimeOfDay => {
+            let time_of_day = |p: &PathBuf| {
+                crate::fs_ops::image_key(p)
+                    .and_then(|k| capture_times.get(&k).copied())
+                    .map(|ts| (ts + utc_offset_secs).rem_euclid(86_400))
+            };
+            let mut order: Vec<&PathBuf> = images.iter().collect();
+            order.sort_by_key(|p| (time_of_day(p).is_none(), time_of_day(p), *p));
+            // n 等分した区間の真ん中を取る
+            (0..n).map(|i| order[(2 * i + 1) * order.len() / (2 * n)].clone()).collect()
+        }
+        SampleStrategy::Random { seed } => {
+            let mut rng = SplitMix64(seed);
+            let mut pool = images.to_vec();
+            for i in 0..n {
+                let j = i + (rng.next() % (pool.len() - i) as u64) as usize;
+                pool.swap(i, j);
+            }
+            pool.truncate(n);
+            pool
+        }
+    };
+    picked.sort();
+    picked
+}
+
+/// 依存を増やさないための小さな擬似乱数（SplitMix64）
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,21 @@
+use schemars::schema_for;
+use serde_json::{json, Value};
+
+use crate::domain::{GroupItem, GroupRecord, GroupRecords};
+use crate::tag::{TagItem, TagRecords};
+
+/// 出力・入力ファイル形式の JSON Schema を名前付きで返す
+/// - `GroupRecords`: photo-groups.json 全体（ファイル名 → GroupRecord）
+/// - `GroupRecord`: 1 写真分のレコード
+/// - `GroupItem`: AI が返すバッチ分類結果の 1 要素
+/// - `TagRecords`: photo-tags.json 全体（ファイル名 → TagRecord）
+/// - `TagItem`: タグ付けで AI が返す 1 要素
+pub fn json_schemas() -> Value {
+    json!({
+        "GroupRecords": schema_for!(GroupRecords),
+        "GroupRecord": schema_for!(GroupRecord),
+        "GroupItem": schema_for!(GroupItem),
+        "TagRecords": schema_for!(TagRecords),
+        "TagItem": schema_for!(TagItem),
+    })
+}
@@ -0,0 +1,83 @@
+use std::collections::{BTreeMap, HashMap};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::domain::GroupRecords;
+use crate::exif::CameraInfo;
+
+/// 基準の機材に対する 1 台分の時計のずれ
+#[derive(Debug, Clone)]
+pub struct CameraSkew {
+    /// CameraInfo::key()
+    pub camera: String,
+    pub photos: usize,
+    /// 基準の機材と共通の黒板の数
+    pub shared_boards: usize,
+    /// 撮影時刻に足すと基準に揃う秒数（共通の黒板が無ければ None）
+    pub offset_secs: Option<i64>,
+}
+
+/// `skew` の結果。基準は写真の最も多い機材。
+#[derive(Debug, Clone, Default)]
+pub struct SkewReport {
+    pub reference: String,
+    pub reference_photos: usize,
+    pub cameras: Vec<CameraSkew>,
+}
+
+fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    Some(values[values.len() / 2])
+}
+
+/// 同じ黒板（読み取った文字が同じ）を複数の機材で撮った写真から、機材ごとの時計のずれを推定する。
+/// 黒板ごとに撮影時刻の中央値の差をとり、その中央値を補正値にする。
+/// times は補正前の撮影時刻（ファイル名 → Unix 秒）、cameras はファイル名 → 機材。
+pub fn estimate_skews(
+    records: &GroupRecords,
+    cameras: &HashMap<String, CameraInfo>,
+    times: &HashMap<String, i64>,
+) -> SkewReport {
+    // 機材 → 黒板の文字 → 撮影時刻
+    let mut boards: BTreeMap<String, BTreeMap<String, Vec<i64>>> = BTreeMap::new();
+    let mut photos: BTreeMap<String, usize> = BTreeMap::new();
+    for (fname, camera) in cameras {
+        let camera = camera.key();
+        *photos.entry(camera.clone()).or_default() += 1;
+        let (Some(rec), Some(&ts)) = (records.get(fname), times.get(fname)) else { continue };
+        let text: String = rec.detected_text.nfkc().filter(|c| !c.is_whitespace()).collect();
+        if rec.has_board && !text.is_empty() {
+            boards.entry(camera).or_default().entry(text).or_default().push(ts);
+        }
+    }
+    let Some((reference, &reference_photos)) = photos.iter().max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0))) else {
+        return SkewReport::default();
+    };
+    let empty = BTreeMap::new();
+    let ref_boards = boards.get(reference).unwrap_or(&empty);
+
+    let cameras = photos
+        .iter()
+        .filter(|(camera, _)| *camera != reference)
+        .map(|(camera, &n)| {
+            let mut diffs: Vec<i64> = boards
+                .get(camera)
+                .unwrap_or(&empty)
+                .iter()
+                .filter_map(|(text, ts)| {
+                    let reference_at = median(&mut ref_boards.get(text)?.clone())?;
+                    Some(reference_at - median(&mut ts.clone())?)
+                })
+                .collect();
+            CameraSkew {
+                camera: camera.clone(),
+                photos: n,
+                shared_boards: diffs.len(),
+                offset_secs: median(&mut diffs),
+            }
+        })
+        .collect();
+    SkewReport { reference: reference.clone(), reference_photos, cameras }
+}
@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::domain::GroupRecords;
+use crate::error::{Error, Result};
+use crate::validate::Issue;
+
+/// 要約に載せる警告の上限（残りは件数だけ書く）
+pub const SUMMARY_MAX_WARNINGS: usize = 10;
+
+/// 今回の実行で新しくできたグループ
+#[derive(Debug, Clone)]
+pub struct SummaryGroup {
+    pub group: u32,
+    pub machine_type: String,
+    pub machine_id: String,
+    pub photos: usize,
+}
+
+/// メールに貼れる実行結果の要約（--summary-out）。標準出力の進捗表示とは別に書く。
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    pub folder: String,
+    /// 実行終了時刻（現地時刻の表示用文字列）
+    pub finished_at: String,
+    pub images: usize,
+    pub classified: usize,
+    pub skipped: usize,
+    /// ファイル名が UTF-8 でないため対象外にした枚数
+    pub unreadable: usize,
+    pub groups: usize,
+    pub new_groups: Vec<SummaryGroup>,
+    /// AI 分類に失敗した写真
+    pub errors: Vec<Issue>,
+    /// validate で見つかった問題
+    pub warnings: Vec<Issue>,
+    pub elapsed_secs: f64,
+    pub dry_run: bool,
+}
+
+/// previous に無かったグループ番号のグループ（番号順）
+pub fn new_groups(previous: &GroupRecords, records: &GroupRecords) -> Vec<SummaryGroup> {
+    let known: BTreeSet<u32> = previous.values().map(|r| r.group).collect();
+    let mut out: BTreeMap<u32, SummaryGroup> = BTreeMap::new();
+    for rec in records.values().filter(|r| r.group != 0 && !known.contains(&r.group)) {
+        out.entry(rec.group)
+            .or_insert_with(|| SummaryGroup {
+                group: rec.group,
+                machine_type: rec.machine_type.clone(),
+                machine_id: rec.machine_id.clone(),
+                photos: 0,
+            })
+            .photos += 1;
+    }
+    out.into_values().collect()
+}
+
+fn fmt_elapsed(secs: f64) -> String {
+    if secs >= 60.0 {
+        format!("{}m{:02}s", (secs / 60.0) as u64, (secs % 60.0) as u64)
+    } else {
+        format!("{secs:.1}s")
+    }
+}
+
+fn group_label(g: &SummaryGroup) -> String {
+    if g.machine_id.is_empty() {
+        format!("Group {}: {} ({} photo(s))", g.group, g.machine_type, g.photos)
+    } else {
+        format!("Group {}: {} ({}) ({} photo(s))", g.group, g.machine_type, g.machine_id, g.photos)
+    }
+}
+
+fn issue_line(issue: &Issue) -> String {
+    format!("[{}] {}: {}", issue.kind.label(), issue.subject, issue.detail)
+}
+
+/// 数字・件数の行（テキストと HTML で共通）
+fn count_lines(s: &RunSummary) -> Vec<String> {
+    let mut lines = vec![
+        format!("Photos: {}", s.images),
+        format!("Classified: {}", s.classified),
+        format!("Already grouped: {}", s.skipped),
+    ];
+    if s.unreadable > 0 {
+        lines.push(format!("Skipped (file name not UTF-8): {}", s.unreadable));
+    }
+    lines.push(format!("Groups: {} ({} new)", s.groups, s.new_groups.len()));
+    lines.push(format!("Errors: {}", s.errors.len()));
+    lines.push(format!("Warnings: {}", s.warnings.len()));
+    lines.push(format!("Elapsed: {}", fmt_elapsed(s.elapsed_secs)));
+    lines
+}
+
+fn title(s: &RunSummary) -> String {
+    let dry = if s.dry_run { " (dry-run)" } else { "" };
+    format!("photo-tagger: {} — {}{dry}", s.folder, s.finished_at)
+}
+
+/// エラーは全件、警告は SUMMARY_MAX_WARNINGS 件まで
+fn listed_warnings(s: &RunSummary) -> (&[Issue], usize) {
+    let shown = s.warnings.len().min(SUMMARY_MAX_WARNINGS);
+    (&s.warnings[..shown], s.warnings.len() - shown)
+}
+
+pub fn summary_text(s: &RunSummary) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}\n", title(s));
+    for line in count_lines(s) {
+        let _ = writeln!(out, "{line}");
+    }
+    if !s.new_groups.is_empty() {
+        let _ = writeln!(out, "\nNew groups:");
+        for g in &s.new_groups {
+            let _ = writeln!(out, "  {}", group_label(g));
+        }
+    }
+    if !s.errors.is_empty() {
+        let _ = writeln!(out, "\nErrors:");
+        for issue in &s.errors {
+            let _ = writeln!(out, "  {}", issue_line(issue));
+        }
+    }
+    let (warnings, more) = listed_warnings(s);
+    if !warnings.is_empty() {
+        let _ = writeln!(out, "\nWarnings:");
+        for issue in warnings {
+            let _ = writeln!(out, "  {}", issue_line(issue));
+        }
+        if more > 0 {
+            let _ = writeln!(out, "  ... and {more} more");
+        }
+    }
+    out
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn html_list(out: &mut String, heading: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "<h2>{}</h2>\n<ul>", html_escape(heading));
+    for item in items {
+        let _ = writeln!(out, "<li>{}</li>", html_escape(item));
+    }
+    let _ = writeln!(out, "</ul>");
+}
+
+pub fn summary_html(s: &RunSummary) -> String {
+    let title = title(s);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>",
+        html_escape(&title)
+    );
+    let _ = writeln!(out, "<table>");
+    for line in count_lines(s) {
+        let (label, value) = line.split_once(": ").unwrap_or((&line, ""));
+        let _ = writeln!(out, "<tr><th align=\"left\">{}</th><td>{}</td></tr>", html_escape(label), html_escape(value));
+    }
+    let _ = writeln!(out, "</table>");
+    html_list(&mut out, "New groups", &s.new_groups.iter().map(group_label).collect::<Vec<_>>());
+    html_list(&mut out, "Errors", &s.errors.iter().map(issue_line).collect::<Vec<_>>());
+    let (warnings, more) = listed_warnings(s);
+    let mut items: Vec<String> = warnings.iter().map(issue_line).collect();
+    if more > 0 {
+        items.push(format!("... and {more} more"));
+    }
+    html_list(&mut out, "Warnings", &items);
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+/// 拡張子が .html / .htm なら HTML、それ以外はテキストで書く
+pub fn write_summary(path: &Path, summary: &RunSummary) -> Result<()> {
+    let html = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"));
+    let text = if html { summary_html(summary) } else { summary_text(summary) };
+    std::fs::write(path, text).map_err(|e| Error::io(path, e))
+}
@@ -0,0 +1,307 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::backend::Backend;
+use crate::domain::extract_json_array;
+use crate::error::{Error, Result};
+use crate::fs_ops::{long_path, mark_sorted_dir, nested_subdirs, record_key, sanitize_dir_name, sorted_dir_tag};
+use crate::witness::witness_kind;
+
+/// カテゴリ一覧のどれにも当てはまらなかった写真のタグ
+pub const UNMATCHED_TAG: &str = "_未分類";
+/// 確信度がしきい値未満で、人の確認が必要な写真のタグ
+pub const REVIEW_TAG: &str = "_要確認";
+/// 黒板に立会・段階確認とある写真のタグ。カテゴリ・確信度より優先する
+pub const WITNESS_TAG: &str = "_立会";
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TagItem {
+    pub file: String,
+    pub tag: String,
+    #[serde(default)]
+    pub detected_text: String,
+    /// 0.0〜1.0
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TagRecord {
+    pub tag: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detected_text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// tag が REVIEW_TAG・WITNESS_TAG のとき、AI が選んだカテゴリ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggested_tag: Option<String>,
+}
+
+impl TagRecord {
+    /// AI が選んだカテゴリ（要確認に回したものも含む）
+    pub fn category(&self) -> &str {
+        self.suggested_tag.as_deref().unwrap_or(&self.tag)
+    }
+
+    /// 確信度が min 未満なら REVIEW_TAG に回し、min 以上なら元のカテゴリに戻す。
+    /// 確信度の無いレコードと立会（WITNESS_TAG）に回したレコードは変更しない。
+    pub fn apply_min_confidence(&mut self, min: f32) {
+        let Some(confidence) = self.confidence.filter(|_| self.tag != WITNESS_TAG) else { return };
+        if confidence < min {
+            if self.suggested_tag.is_none() {
+                self.suggested_tag = Some(std::mem::replace(&mut self.tag, REVIEW_TAG.to_string()));
+            }
+        } else if let Some(tag) = self.suggested_tag.take() {
+            self.tag = tag;
+        }
+    }
+
+    /// 黒板に立会・段階確認とあれば WITNESS_TAG に回す（AI のカテゴリは suggested_tag に残す）
+    pub fn apply_witness_routing(&mut self) {
+        if self.tag == WITNESS_TAG || witness_kind(&self.detected_text).is_none() {
+            return;
+        }
+        let tag = std::mem::replace(&mut self.tag, WITNESS_TAG.to_string());
+        if self.suggested_tag.is_none() {
+            self.suggested_tag = Some(tag);
+        }
+    }
+}
+
+/// カテゴリごとの確信度の分布
+#[derive(Debug, Clone, Default)]
+pub struct ConfidenceStats {
+    pub count: usize,
+    /// 確信度のあるレコード数
+    pub scored: usize,
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+    pub below_threshold: usize,
+}
+
+/// AI が選んだカテゴリごとに確信度を集計する
+pub fn confidence_stats(records: &TagRecords, min_confidence: Option<f32>) -> BTreeMap<String, ConfidenceStats> {
+    let mut scores: BTreeMap<String, (usize, Vec<f32>)> = BTreeMap::new();
+    for rec in records.values() {
+        let entry = scores.entry(rec.category().to_string()).or_default();
+        entry.0 += 1;
+        entry.1.extend(rec.confidence);
+    }
+    scores
+        .into_iter()
+        .map(|(category, (count, values))| {
+            let mut stats = ConfidenceStats { count, scored: values.len(), ..Default::default() };
+            if !values.is_empty() {
+                stats.min = values.iter().copied().fold(f32::INFINITY, f32::min);
+                stats.max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                stats.mean = values.iter().sum::<f32>() / values.len() as f32;
+                stats.below_threshold = min_confidence.map_or(0, |m| values.iter().filter(|&&v| v < m).count());
+            }
+            (category, stats)
+        })
+        .collect()
+}
+
+impl From<TagItem> for TagRecord {
+    fn from(item: TagItem) -> Self {
+        TagRecord {
+            tag: item.tag,
+            detected_text: item.detected_text,
+            confidence: item.confidence.map(|c| c.clamp(0.0, 1.0)),
+            suggested_tag: None,
+        }
+    }
+}
+
+/// ファイル名 → タグ（photo-tags.json）
+pub type TagRecords = BTreeMap<String, TagRecord>;
+
+/// タグ付けの分類先。names がフォルダ名になり、aliases（別名 → カテゴリ）は
+/// プロンプトでの補足と AI が別名で答えたときの読み替えに使う。
+#[derive(Debug, Clone, Default)]
+pub struct Categories {
+    pub names: Vec<String>,
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Categories {
+    /// カテゴリファイルを読む。1 行 1 カテゴリ、空行と `#` で始まる行は無視。
+    pub fn load(path: &Path) -> Result<Categories> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let mut categories = Categories::default();
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                categories.add(line);
+            }
+        }
+        Ok(categories)
+    }
+
+    /// 整理済みのフォルダ直下のサブフォルダ名をカテゴリにする。
+    /// `--move` で作ったフォルダは目印に残した元のタグを使う（フォルダ名は使えない文字を置き換えてあるため）。
+    /// [sort] layout で階層を分けた場合は、下の階層の目印のタグを使う。
+    pub fn from_subdirs(base: &Path) -> Result<Categories> {
+        let mut categories = Categories::default();
+        for name in collect_subdirs(base)? {
+            let dir = base.join(&name);
+            let tags: Vec<String> =
+                std::iter::once(dir.clone()).chain(nested_subdirs(&dir)).filter_map(|d| sorted_dir_tag(&d)).collect();
+            if tags.is_empty() {
+                categories.add(&name);
+            }
+            for tag in &tags {
+                categories.add(tag);
+            }
+        }
+        Ok(categories)
+    }
+
+    pub fn add(&mut self, name: &str) {
+        if !self.names.iter().any(|c| c == name) {
+            self.names.push(name.to_string());
+        }
+    }
+
+    pub fn merge(&mut self, other: Categories) {
+        for name in &other.names {
+            self.add(name);
+        }
+        self.aliases.extend(other.aliases);
+    }
+
+    /// 別名ファイルを読む。1 行に `カテゴリ = 別名1, 別名2`。一覧に無いカテゴリは追加する。
+    pub fn load_aliases(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        for (no, line) in text.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((category, aliases)) = line.split_once('=') else {
+                return Err(Error::Config {
+                    path: path.to_path_buf(),
+                    message: format!("line {}: expected `カテゴリ = 別名, ...`", no + 1),
+                });
+            };
+            let category = category.trim();
+            self.add(category);
+            for alias in aliases.split([',', '、']).map(str::trim).filter(|a| !a.is_empty()) {
+                self.aliases.insert(alias.to_string(), category.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// AI の答えをカテゴリ名に直す。一覧にも別名にも無ければ None。
+    pub fn resolve(&self, tag: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|c| *c == tag)
+            .map(String::as_str)
+            .or_else(|| self.aliases.get(tag).map(String::as_str))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// dir 直下のサブフォルダ名（名前順）。隠しフォルダと `_` で始まるツールのフォルダは除く。
+pub fn collect_subdirs(dir: &Path) -> Result<Vec<String>> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
+    let mut out: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| e.file_name().to_str().map(str::to_string))
+        .filter(|name| !name.starts_with('.') && !name.starts_with('_'))
+        .collect();
+    out.sort();
+    Ok(out)
+}
+
+pub fn tag_prompt(filenames: &[&str], categories: &Categories) -> String {
+    let list = filenames.join(", ");
+    let mut prompt = format!(
+        r#"工事写真の黒板に書かれた工種・種別を読み取り、次のカテゴリのどれか 1 つに分類せよ。Output ONLY JSON array: [{{"file":"filename","tag":"?","detected_text":"","confidence":0.0}}, ...]
+ファイル: {list}
+カテゴリ: {}
+tag: 上のカテゴリから 1 つをそのまま書け。黒板が無い・読めない・どれにも当てはまらない場合は "{UNMATCHED_TAG}"
+detected_text: 黒板から読み取ったテキスト
+confidence: その tag で正しい確信度（0.0〜1.0）。黒板が不鮮明・複数のカテゴリに当てはまりそうなら低くせよ"#,
+        categories.names.join(", ")
+    );
+    if !categories.aliases.is_empty() {
+        let aliases: Vec<String> = categories
+            .aliases
+            .iter()
+            .map(|(alias, category)| format!("{alias} → {category}"))
+            .collect();
+        prompt.push_str(&format!("\n黒板での別表記（右のカテゴリとして扱え）: {}", aliases.join(", ")));
+    }
+    prompt
+}
+
+/// 黒板の文字からカテゴリを判定する。別名はカテゴリ名に読み替え、一覧に無いタグは UNMATCHED_TAG に置き換える。
+pub fn classify_tag_batch_with(
+    backend: &dyn Backend,
+    images: &[PathBuf],
+    categories: &Categories,
+) -> Result<Vec<(String, TagItem)>> {
+    let names: Vec<&str> = images
+        .iter()
+        .map(|p| p.file_name().and_then(|n| n.to_str()).unwrap_or("unknown"))
+        .collect();
+
+    let prompt = tag_prompt(&names, categories);
+    let raw = backend.analyze(&prompt, images)?;
+
+    let json_str = extract_json_array(&raw).ok_or_else(|| Error::Parse {
+        message: format!("No JSON array in: {raw}"),
+        source: None,
+    })?;
+
+    let items: Vec<TagItem> = serde_json::from_str(json_str).map_err(|e| Error::Parse {
+        message: "Failed to parse tag JSON".into(),
+        source: Some(e),
+    })?;
+
+    Ok(items
+        .into_iter()
+        .map(|mut item| {
+            item.tag = categories.resolve(&item.tag).unwrap_or(UNMATCHED_TAG).to_string();
+            (record_key(&item.file), item)
+        })
+        .collect())
+}
+
+/// タグの移動先フォルダ名（レコードの tag はそのまま、フォルダ名だけ安全な名前にする）
+pub fn tag_dir_name(tag: &str) -> String {
+    sanitize_dir_name(tag)
+}
+
+/// base/<tag>/ に写真を移動し、移動先を返す（既定の [sort] layout と同じ）
+pub fn move_to_tag_dir(base: &Path, file: &str, tag: &str) -> Result<PathBuf> {
+    move_to_sorted_dir(base, file, Path::new(&tag_dir_name(tag)), Some(tag))
+}
+
+/// base/<dir>/ に写真を移動し、移動先を返す。dir は [sort] layout で作った相対パス。
+/// mark_tag が Some なら移動先に目印を置く。移動先に同名ファイルがあれば上書きせずエラー。
+pub fn move_to_sorted_dir(base: &Path, file: &str, dir: &Path, mark_tag: Option<&str>) -> Result<PathBuf> {
+    let dir = base.join(dir);
+    std::fs::create_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+    if let Some(tag) = mark_tag {
+        mark_sorted_dir(&dir, tag)?;
+    }
+    let dest = dir.join(file);
+    if long_path(&dest).exists() {
+        return Err(Error::io(
+            &dest,
+            std::io::Error::new(std::io::ErrorKind::AlreadyExists, "destination already exists"),
+        ));
+    }
+    let src = base.join(file);
+    std::fs::rename(long_path(&src), long_path(&dest)).map_err(|e| Error::io(&src, e))?;
+    Ok(dest)
+}
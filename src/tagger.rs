@@ -0,0 +1,848 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Backend, GeminiBackend};
+use crate::cluster::{self, Duplicate, Followers, OnnxEmbedder};
+use crate::concurrency::{AimdController, CircuitBreaker};
+use crate::config::{ClusterConfig, Config, CONFIG_FILE};
+use crate::domain::{
+    classify_group_batch_in_context_with, classify_group_batch_no_text_with, BatchContext, GroupRecord, GroupRecords,
+};
+use crate::error::{Error, Result};
+use crate::error_report::{self, FailedBatch};
+use crate::exif::CameraInfo;
+use crate::fs_ops::{
+    self, append_group_journal, collect_image_entries, load_group_records, save_group_records, save_group_records_as,
+    RecordFormat, DEFAULT_FLUSH_INTERVAL_SECS,
+};
+use crate::history;
+use crate::hooks::{Hook, HookEvent, Hooks};
+use crate::meta::{self, DEFAULT_SCAN_WORKERS};
+use crate::profile::{ms, BatchProfile, RunProfile};
+use crate::retry_state::{BatchStatus, RetryState, Unfinished};
+use crate::run_meta::RunMeta;
+use crate::sample::{self, SampleStrategy};
+use crate::text_detect;
+use crate::timestamp::{self, ClockOffsets};
+
+/// 進捗の通知先（バッチの処理中は分類スレッドからも呼ぶ）
+type Progress = Box<dyn Fn(&GroupProgress<'_>) + Send + Sync>;
+
+/// ライブラリ利用者向けのファサード。
+/// 収集 → 未処理抽出 → 分類 → グループ付与 → 保存 を一括で行う（CLI もこれを呼ぶ）。
+pub struct PhotoTagger {
+    folder: PathBuf,
+    batch_size: usize,
+    concurrency: usize,
+    auto_concurrency: bool,
+    scan_workers: usize,
+    vocabulary: Option<Vec<String>>,
+    batch_context: bool,
+    flush_interval: Duration,
+    trust_names: bool,
+    dry_run: bool,
+    renumber: bool,
+    record_format: Option<RecordFormat>,
+    clock_offsets: ClockOffsets,
+    cameras: Vec<String>,
+    sample: Option<(usize, SampleStrategy)>,
+    cluster: bool,
+    text_filter: Option<f32>,
+    circuit_breaker: Option<(usize, f64)>,
+    backend: Box<dyn Backend>,
+    hooks: Hooks,
+    progress: Option<Progress>,
+}
+
+pub struct PhotoTaggerBuilder {
+    folder: Option<PathBuf>,
+    batch_size: usize,
+    concurrency: usize,
+    auto_concurrency: bool,
+    scan_workers: usize,
+    vocabulary: Option<Vec<String>>,
+    batch_context: bool,
+    flush_interval: Duration,
+    trust_names: bool,
+    dry_run: bool,
+    renumber: bool,
+    record_format: Option<RecordFormat>,
+    clock_offsets: ClockOffsets,
+    cameras: Vec<String>,
+    sample: Option<(usize, SampleStrategy)>,
+    cluster: bool,
+    text_filter: Option<f32>,
+    circuit_breaker: Option<(usize, f64)>,
+    backend: Option<Box<dyn Backend>>,
+    hooks: Hooks,
+    progress: Option<Progress>,
+}
+
+impl Default for PhotoTaggerBuilder {
+    fn default() -> Self {
+        PhotoTaggerBuilder {
+            folder: None,
+            batch_size: 10,
+            concurrency: 1,
+            auto_concurrency: false,
+            scan_workers: DEFAULT_SCAN_WORKERS,
+            vocabulary: None,
+            batch_context: false,
+            flush_interval: Duration::from_secs(DEFAULT_FLUSH_INTERVAL_SECS),
+            trust_names: false,
+            dry_run: false,
+            renumber: false,
+            record_format: None,
+            clock_offsets: ClockOffsets::default(),
+            cameras: Vec::new(),
+            sample: None,
+            cluster: false,
+            text_filter: None,
+            circuit_breaker: None,
+            backend: None,
+            hooks: Hooks::default(),
+            progress: None,
+        }
+    }
+}
+
+impl PhotoTaggerBuilder {
+    pub fn folder(mut self, folder: impl Into<PathBuf>) -> Self {
+        self.folder = Some(folder.into());
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// 同時に送るバッチ数（auto_concurrency では上限）
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// 並列数を 1 から始め、応答時間とエラーに合わせて増減する（AIMD）
+    pub fn auto_concurrency(mut self, enabled: bool) -> Self {
+        self.auto_concurrency = enabled;
+        self
+    }
+
+    /// フォルダ走査でメタデータ・EXIF・内容のハッシュを読むスレッド数
+    pub fn scan_workers(mut self, workers: usize) -> Self {
+        self.scan_workers = workers;
+        self
+    }
+
+    pub fn vocabulary(mut self, vocabulary: Vec<String>) -> Self {
+        self.vocabulary = Some(vocabulary);
+        self
+    }
+
+    /// 前のバッチで分類した機械をプロンプトに添える（バッチをまたいだ machine_id の揺れを減らす）
+    pub fn batch_context(mut self, enabled: bool) -> Self {
+        self.batch_context = enabled;
+        self
+    }
+
+    /// 分類の途中でジャーナルを photo-groups.json に畳み込む間隔（ZERO ならバッチごと）
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// 内容のハッシュを計算せず、ファイル名だけでレコードと照合する（遅いストレージ向け）
+    pub fn trust_names(mut self, enabled: bool) -> Self {
+        self.trust_names = enabled;
+        self
+    }
+
+    /// 分類はするが、レコード・履歴・キャッシュなどを何も書かない（フックも呼ばない）
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// 変わった機械だけでなく、すべてのグループの番号を振り直す
+    pub fn renumber(mut self, enabled: bool) -> Self {
+        self.renumber = enabled;
+        self
+    }
+
+    /// photo-groups.json の書き方（指定しなければ既存ファイルと同じ形式）
+    pub fn record_format(mut self, format: RecordFormat) -> Self {
+        self.record_format = Some(format);
+        self
+    }
+
+    /// 時計のずれた機材の撮影時刻の補正
+    pub fn clock_offsets(mut self, offsets: ClockOffsets) -> Self {
+        self.clock_offsets = offsets;
+        self
+    }
+
+    /// この機材（機種名・シリアル番号）で撮った写真だけを分類する。ほかの写真は次の実行に残す。
+    pub fn camera(mut self, camera: impl Into<String>) -> Self {
+        self.cameras.push(camera.into());
+        self
+    }
+
+    /// 未処理の写真のうち n 枚だけを分類して .photo-tagger/photo-groups.sample.json に書く
+    /// （photo-groups.json・履歴・フックには触れない）
+    pub fn sample(mut self, n: usize, strategy: SampleStrategy) -> Self {
+        self.sample = Some((n, strategy));
+        self
+    }
+
+    /// [cluster] のモデルで見た目の似た写真をまとめ、代表の 1 枚だけを送る
+    pub fn cluster(mut self, enabled: bool) -> Self {
+        self.cluster = enabled;
+        self
+    }
+
+    /// 黒板・文字の無い写真（文字らしさが min_score 未満）を文字の読み取りを省いた短いプロンプトで送る
+    pub fn text_filter(mut self, min_score: f32) -> Self {
+        self.text_filter = Some(min_score);
+        self
+    }
+
+    /// 直近 window バッチのうち max_error_rate 以上が失敗したら残りを送らずに打ち切る
+    pub fn circuit_breaker(mut self, window: usize, max_error_rate: f64) -> Self {
+        self.circuit_breaker = (window > 0).then_some((window, max_error_rate));
+        self
+    }
+
+    pub fn backend(mut self, backend: impl Backend + 'static) -> Self {
+        self.backend = Some(Box::new(backend));
+        self
+    }
+
+    /// 分類したレコードごとと実行の終わりに呼ぶフック（photo-tagger.toml の [hooks] に加えて）
+    pub fn hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// 途中経過の通知先（進捗表示用）
+    pub fn progress(mut self, progress: impl Fn(&GroupProgress<'_>) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn build(self) -> Result<PhotoTagger> {
+        let Some(folder) = self.folder else {
+            return Err(Error::Validation("PhotoTagger: folder is required".into()));
+        };
+        if self.batch_size == 0 {
+            return Err(Error::Validation("PhotoTagger: batch_size must be at least 1".into()));
+        }
+        if self.concurrency == 0 {
+            return Err(Error::Validation("PhotoTagger: concurrency must be at least 1".into()));
+        }
+        Ok(PhotoTagger {
+            folder,
+            batch_size: self.batch_size,
+            concurrency: self.concurrency,
+            auto_concurrency: self.auto_concurrency,
+            scan_workers: self.scan_workers.max(1),
+            vocabulary: self.vocabulary,
+            batch_context: self.batch_context,
+            flush_interval: self.flush_interval,
+            trust_names: self.trust_names,
+            dry_run: self.dry_run,
+            renumber: self.renumber,
+            record_format: self.record_format,
+            clock_offsets: self.clock_offsets,
+            cameras: self.cameras,
+            sample: self.sample,
+            cluster: self.cluster,
+            text_filter: self.text_filter,
+            circuit_breaker: self.circuit_breaker,
+            backend: self.backend.unwrap_or_else(|| Box::new(GeminiBackend)),
+            hooks: self.hooks,
+            progress: self.progress,
+        })
+    }
+}
+
+/// PhotoTagger::run の途中経過
+#[derive(Debug)]
+pub enum GroupProgress<'a> {
+    /// 分類する写真が決まった（分類の前に 1 回）
+    Pending {
+        images: usize,
+        pending: usize,
+        /// 分類済みとして飛ばした写真
+        skipped: usize,
+        /// 分類したときから内容が変わったので再分類する写真
+        changed: usize,
+        /// ファイル名が UTF-8 でなく分類できない画像
+        unreadable: &'a [PathBuf],
+        /// 内容のハッシュを計算できなかった写真（名前だけで照合する）
+        hash_errors: &'a [Error],
+        /// 撮影機材の指定に当てはまらず、次の実行に残した写真
+        other_cameras: usize,
+        /// サンプルを選ぶ前の未処理の写真（サンプルを取ったときだけ）
+        sampled_from: Option<usize>,
+    },
+    /// 前回途中で止まった実行の内訳
+    Unfinished(&'a Unfinished),
+    /// 見た目の似た写真をまとめた
+    Clustered {
+        photos: usize,
+        clusters: usize,
+        duplicates: &'a [Duplicate],
+        /// 埋め込みを計算できず単独で送る写真の数と、最初のエラー
+        embed_failures: usize,
+        first_embed_error: Option<&'a Error>,
+    },
+    /// 黒板・文字の無い写真を短いプロンプトに回した
+    TextFiltered { no_text: usize },
+    /// バッチに分けた
+    Planned { photos: usize, batches: usize },
+    BatchStarted { batch: usize, batches: usize, photos: usize },
+    /// バッチの結果。records はクラスタの代表から写したレコード（followers）も含む。
+    BatchFinished {
+        batch: usize,
+        records: &'a GroupRecords,
+        followers: &'a [String],
+        failure: Option<&'a FailedBatch>,
+        stats: &'a BatchProfile,
+    },
+    /// auto_concurrency で並列数を変えた
+    ConcurrencyChanged { from: usize, to: usize },
+    /// エラー率が高いので残りのバッチを送らずに打ち切った
+    Stopped { cause: &'a str, unsent: usize },
+}
+
+/// PhotoTagger::run の結果
+#[derive(Debug, Default)]
+pub struct GroupRun {
+    /// 保存した全レコード（サンプルのときはサンプルのレコードだけ）
+    pub records: GroupRecords,
+    /// 実行前に保存されていたレコード
+    pub previous: GroupRecords,
+    pub images: Vec<PathBuf>,
+    /// 分類に回した写真の数（サンプル・クラスタにまとめる前の数）
+    pub pending: usize,
+    /// 分類済みとして飛ばした写真
+    pub skipped: usize,
+    /// ファイル名が UTF-8 でなく分類できない画像
+    pub unreadable: Vec<PathBuf>,
+    /// 今回分類したレコードのキー
+    pub classified: Vec<String>,
+    /// 失敗したバッチ（番号順）と、それぞれのエラー
+    pub failed: Vec<FailedBatch>,
+    pub errors: Vec<Error>,
+    pub hash_errors: Vec<Error>,
+    pub hook_errors: Vec<Error>,
+    /// エラー率で打ち切った理由
+    pub aborted: Option<String>,
+    /// 書き出した errors.html
+    pub error_report: Option<PathBuf>,
+    /// サンプルを書き出したファイル
+    pub sample_file: Option<PathBuf>,
+    /// 段ごとの所要時間とバッチごとの計測（total は呼び出し側で足す）
+    pub profile: RunProfile,
+}
+
+impl PhotoTagger {
+    pub fn builder() -> PhotoTaggerBuilder {
+        PhotoTaggerBuilder::default()
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// フォルダ内の画像をグループ分けして photo-groups.json に保存
+    /// 既存のグループはスキップ。戻り値は全レコード。
+    /// 失敗したバッチは飛ばして残りを処理し（その写真は次回の実行で再分類される）、
+    /// バッチやフックが失敗した場合は、保存とフックの呼び出しを済ませたうえで最初のエラーを返す。
+    pub fn group(&self) -> Result<GroupRecords> {
+        let run = self.run()?;
+        match run.errors.into_iter().chain(run.hash_errors).chain(run.hook_errors).next() {
+            Some(e) => Err(e),
+            None => Ok(run.records),
+        }
+    }
+
+    fn notify(&self, event: GroupProgress<'_>) {
+        if let Some(progress) = &self.progress {
+            progress(&event);
+        }
+    }
+
+    fn save(&self, records: &GroupRecords) -> Result<()> {
+        match self.record_format {
+            Some(format) => save_group_records_as(&self.folder, records, format),
+            None => save_group_records(&self.folder, records),
+        }
+    }
+
+    /// group と同じ処理を行い、失敗したバッチ・フックのエラーや計測をまとめて返す。
+    /// Err になるのは続けられない失敗（レコードが読めない・保存できないなど）だけ。
+    pub fn run(&self) -> Result<GroupRun> {
+        let folder = self.folder.as_path();
+        let mut run = GroupRun {
+            profile: RunProfile {
+                batch_size: self.batch_size,
+                concurrency: self.concurrency,
+                scan_workers: self.scan_workers,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = Config::load(folder)?;
+        let parser = config.timestamp_parser()?;
+        let mut run_meta = RunMeta::group(&config, self.backend.name(), self.batch_size, self.concurrency)
+            .with_vocabulary(self.vocabulary.as_deref());
+        run_meta.auto_concurrency = self.auto_concurrency;
+        run_meta.renumber = self.renumber;
+        run_meta.batch_context = self.batch_context;
+
+        let t = Instant::now();
+        let mut records = load_group_records(folder)?;
+        run.previous = records.clone();
+        run.profile.stage("io", t.elapsed());
+
+        let t = Instant::now();
+        let entries = collect_image_entries(folder, self.scan_workers);
+        run.profile.stage("scan", t.elapsed());
+        let t = Instant::now();
+        run.images = entries.iter().map(|e| e.path.clone()).collect();
+        let scanned = if self.trust_names {
+            meta::scan_cached(folder, &entries, !self.dry_run, self.scan_workers)
+        } else {
+            let (scanned, hash_errors) = meta::scan_cached_hashed(folder, &entries, !self.dry_run, self.scan_workers);
+            run.hash_errors = hash_errors;
+            scanned
+        };
+        let capture_times = meta::capture_times(&scanned, &parser);
+        let cameras = meta::cameras(&scanned);
+        let hashes = if self.trust_names { HashMap::new() } else { meta::content_hashes(&scanned) };
+        run.profile.stage("meta", t.elapsed());
+        run.profile.images = run.images.len();
+        if run.images.is_empty() {
+            run.records = records;
+            return Ok(run);
+        }
+
+        let force = crate::force_reclassify_enabled();
+        let mut pending = crate::pending_images_with_hashes(&run.images, &records, force, &hashes);
+        let changed = if force {
+            0
+        } else {
+            let index = fs_ops::KeyIndex::new(records.keys());
+            pending
+                .iter()
+                .filter(|p| fs_ops::image_key(p).is_some_and(|k| index.find(&records, &k).is_some()))
+                .count()
+        };
+        let mut other_cameras = 0;
+        if !self.cameras.is_empty() {
+            let total = pending.len();
+            pending = filter_by_camera(pending, &cameras, &self.cameras);
+            other_cameras = total - pending.len();
+        }
+        // 抽出前に数える（サンプルから外れた未処理の写真を分類済みに数えない）
+        run.unreadable = fs_ops::non_utf8_images(&run.images).into_iter().map(Path::to_path_buf).collect();
+        run.skipped = run.images.len() - pending.len() - run.unreadable.len() - other_cameras;
+        let mut sampled_from = None;
+        if let Some((n, strategy)) = self.sample.filter(|_| !pending.is_empty()) {
+            sampled_from = Some(pending.len());
+            pending = sample::sample_images(&pending, n, &capture_times, parser.utc_offset_secs(), strategy);
+        }
+        run.pending = pending.len();
+        run.profile.pending = pending.len();
+        run_meta.images = run.images.len();
+        run_meta.pending = pending.len();
+        self.notify(GroupProgress::Pending {
+            images: run.images.len(),
+            pending: pending.len(),
+            skipped: run.skipped,
+            changed,
+            unreadable: &run.unreadable,
+            hash_errors: &run.hash_errors,
+            other_cameras,
+            sampled_from,
+        });
+        if self.sample.is_some() && pending.is_empty() {
+            return Ok(run);
+        }
+
+        let (pending, followers) = if self.cluster && !pending.is_empty() {
+            let t = Instant::now();
+            let clustered = self.cluster_pending(&config.cluster, &pending, &capture_times)?;
+            run.profile.stage("cluster", t.elapsed());
+            clustered
+        } else {
+            (pending, Followers::new())
+        };
+
+        // 文字ありの写真を先に、文字なしの写真は後ろのバッチにまとめて短いプロンプトで送る
+        let (pending, no_text) = match self.text_filter.filter(|_| !pending.is_empty()) {
+            Some(min_score) => {
+                let t = Instant::now();
+                let (with_text, no_text) = text_detect::split_by_text(&pending, min_score, self.scan_workers)?;
+                run.profile.stage("text", t.elapsed());
+                self.notify(GroupProgress::TextFiltered { no_text: no_text.len() });
+                (with_text, no_text)
+            }
+            None => (pending, Vec::new()),
+        };
+        let pending_count = pending.len() + no_text.len();
+        // 前回途中で止まった実行の内訳を知らせ、応答を読み取れなかった写真は 1 枚ずつ送る
+        // （読めない応答を返す写真が 1 枚あるだけでバッチ全体を失わないように）
+        let unparsed: HashSet<String> = match RetryState::load(folder) {
+            Some(state) => {
+                let keys: HashSet<String> = pending.iter().chain(&no_text).filter_map(|p| fs_ops::image_key(p)).collect();
+                let unfinished = state.unfinished(&keys);
+                if !unfinished.is_empty() {
+                    self.notify(GroupProgress::Unfinished(&unfinished));
+                }
+                unfinished.unparsed.into_iter().collect()
+            }
+            None => HashSet::new(),
+        };
+        let is_unparsed = |p: &PathBuf| fs_ops::image_key(p).is_some_and(|k| unparsed.contains(&k));
+        let (single, pending): (Vec<PathBuf>, Vec<PathBuf>) = pending.into_iter().partition(is_unparsed);
+        let (single_no_text, no_text): (Vec<PathBuf>, Vec<PathBuf>) = no_text.into_iter().partition(is_unparsed);
+        // 撮影の間隔が空いたところで区切り、1 台分の写真が 2 つのバッチに分かれないようにする
+        let mut batches: Vec<Vec<PathBuf>> = single.into_iter().map(|p| vec![p]).collect();
+        batches.extend(crate::time_batches(&pending, &capture_times, self.batch_size));
+        let no_text_from = batches.len();
+        batches.extend(single_no_text.into_iter().map(|p| vec![p]));
+        batches.extend(crate::time_batches(&no_text, &capture_times, self.batch_size));
+        let num_batches = batches.len();
+        self.notify(GroupProgress::Planned { photos: pending_count, batches: num_batches });
+
+        let classify_start = Instant::now();
+        let vocabulary = self.vocabulary.as_deref();
+        let sampling = self.sample.is_some();
+        let keep_state = !self.dry_run && !sampling;
+        let mut aimd = AimdController::new(if self.auto_concurrency { 1 } else { self.concurrency }, self.concurrency);
+        let mut breaker = self.circuit_breaker.map(|(window, rate)| CircuitBreaker::new(window, rate));
+        let mut context = BatchContext::default();
+        let mut last_flush = Instant::now();
+        let mut retry = RetryState::planned(&batches);
+        if keep_state {
+            retry.save(folder)?;
+        }
+        let mut errors: Vec<(usize, Error)> = Vec::new();
+        let mut next = 0;
+        while next < num_batches {
+            let level = aimd.level();
+            let wave = &batches[next..(next + level).min(num_batches)];
+            let wave_start = next;
+            next += wave.len();
+            if keep_state {
+                for batch_num in wave_start + 1..=next {
+                    retry.set(batch_num, BatchStatus::Sent, "");
+                }
+                retry.save(folder)?;
+            }
+
+            let results: Vec<_> = thread::scope(|s| {
+                let context = &context;
+                let handles: Vec<_> = wave
+                    .iter()
+                    .enumerate()
+                    .map(|(i, batch)| {
+                        let batch_num = wave_start + i + 1;
+                        s.spawn(move || {
+                            self.notify(GroupProgress::BatchStarted {
+                                batch: batch_num,
+                                batches: num_batches,
+                                photos: batch.len(),
+                            });
+                            let start = Instant::now();
+                            let backend = self.backend.as_ref();
+                            let result = if batch_num > no_text_from {
+                                classify_group_batch_no_text_with(backend, batch, vocabulary, context)
+                            } else {
+                                classify_group_batch_in_context_with(backend, batch, vocabulary, context)
+                            };
+                            let stats = BatchProfile {
+                                batch: batch_num,
+                                images: batch.len(),
+                                queue_wait_ms: ms(start - classify_start),
+                                analyze_ms: ms(start.elapsed()),
+                                ok: result.is_ok(),
+                            };
+                            (batch_num, result, stats)
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().expect("batch thread panicked")).collect()
+            });
+
+            let mut wave_errors = 0;
+            let mut wave_latency = Vec::new();
+            for (batch_num, result, stats) in results {
+                let (items, failure) = match result {
+                    Ok(items) => (items, None),
+                    Err(e) => {
+                        let failure = FailedBatch {
+                            batch: batch_num,
+                            files: batches[batch_num - 1].iter().map(|img| file_label(img)).collect(),
+                            kind: e.kind().to_string(),
+                            message: e.to_string(),
+                        };
+                        errors.push((batch_num, e));
+                        (Vec::new(), Some(failure))
+                    }
+                };
+                if let Some(breaker) = breaker.as_mut() {
+                    breaker.record(failure.as_ref().map(|f| f.message.clone()));
+                }
+                if stats.ok {
+                    wave_latency.push(stats.analyze_ms);
+                } else {
+                    wave_errors += 1;
+                }
+                match &failure {
+                    Some(f) if f.kind == "parse" => retry.set(batch_num, BatchStatus::Unparsed, &f.message),
+                    Some(f) => retry.set(batch_num, BatchStatus::Failed, &f.message),
+                    None => retry.set(batch_num, BatchStatus::Answered, ""),
+                }
+
+                if self.batch_context {
+                    items.iter().for_each(|(_, item)| context.record(item));
+                }
+                let mut batch_records: GroupRecords =
+                    items.into_iter().map(|(fname, item)| (fname, GroupRecord::from(item))).collect();
+                let spread = cluster::spread_to_followers(&mut batch_records, &followers);
+                crate::drop_locked(&records, &mut batch_records);
+                self.notify(GroupProgress::BatchFinished {
+                    batch: batch_num,
+                    records: &batch_records,
+                    followers: &spread,
+                    failure: failure.as_ref(),
+                    stats: &stats,
+                });
+                run.classified.extend(batch_records.keys().cloned());
+                if keep_state {
+                    let t = Instant::now();
+                    append_group_journal(folder, &batch_records)?;
+                    run.profile.stage("io", t.elapsed());
+                }
+                records.extend(batch_records);
+                run.failed.extend(failure);
+                run.profile.batches.push(stats);
+            }
+
+            if keep_state {
+                retry.save(folder)?;
+            }
+            // ジャーナルを本体に畳み込み、実行中でも photo-groups.json に分類済みの分が載るようにする
+            if keep_state && last_flush.elapsed() >= self.flush_interval {
+                let t = Instant::now();
+                self.save(&records)?;
+                run.profile.stage("io", t.elapsed());
+                last_flush = Instant::now();
+            }
+
+            // 分類済みの分はジャーナルに残っているので、打ち切っても次回はその続きから
+            if let Some(breaker) = breaker.as_ref().filter(|b| b.is_open()) {
+                let cause = breaker.cause();
+                self.notify(GroupProgress::Stopped { cause: &cause, unsent: num_batches - next });
+                run.aborted = Some(cause);
+                break;
+            }
+
+            if self.auto_concurrency {
+                run.profile.concurrency_levels.push(level);
+                let mean = (!wave_latency.is_empty()).then(|| wave_latency.iter().sum::<f64>() / wave_latency.len() as f64);
+                let new_level = aimd.observe(wave_errors, mean);
+                if new_level != level {
+                    self.notify(GroupProgress::ConcurrencyChanged { from: level, to: new_level });
+                }
+            }
+        }
+        run.profile.stage("classify", classify_start.elapsed());
+        run.failed.sort_by_key(|f| f.batch);
+        errors.sort_by_key(|(batch, _)| *batch);
+        run.errors = errors.into_iter().map(|(_, e)| e).collect();
+
+        if sampling {
+            return self.finish_sample(run, &records, &capture_times);
+        }
+
+        let t = Instant::now();
+        let classified = records.clone();
+        crate::apply_capture_times_with_offsets(&mut records, &capture_times, &cameras, &self.clock_offsets);
+        crate::apply_content_hashes(&mut records, &hashes);
+        if self.renumber {
+            crate::assign_groups(&mut records);
+        } else {
+            crate::assign_groups_incremental(&mut records, &run.previous);
+        }
+        run.profile.stage("assign", t.elapsed());
+        run.records = records;
+        if self.dry_run {
+            return Ok(run);
+        }
+
+        let t = Instant::now();
+        self.save(&run.records)?;
+        history::append_history(folder, &run.previous, &classified, history::SOURCE_AI)?;
+        history::append_history(folder, &classified, &run.records, history::SOURCE_NORMALIZE)?;
+        run_meta.finish(folder)?;
+        // 失敗・打ち切りが無ければ次の実行に引き継ぐものは無い
+        if retry.all_answered() {
+            RetryState::remove(folder)?;
+        }
+        let finished_at = timestamp::local_now(parser.utc_offset_secs());
+        run.error_report = error_report::write_error_report(folder, &finished_at, &run.failed)?;
+        run.profile.stage("io", t.elapsed());
+
+        let config_hooks = Hooks::from_config(&config.hooks, folder);
+        let folder = folder.display().to_string();
+        let failed_photos: usize = run.failed.iter().map(|f| f.files.len()).sum();
+        let end = HookEvent::RunEnd {
+            folder: &folder,
+            mode: "group",
+            images: run.images.len(),
+            classified: run.classified.len(),
+            errors: failed_photos,
+            aborted: run.aborted.as_deref(),
+        };
+        for hooks in [&config_hooks, &self.hooks] {
+            let done = run.classified.iter().filter_map(|k| run.records.get_key_value(k));
+            run.hook_errors.extend(hooks.emit_records(&folder, "group", done));
+            run.hook_errors.extend(hooks.emit(&end));
+        }
+        Ok(run)
+    }
+
+    /// サンプルの結果だけを別ファイルに書く（本体のレコード・履歴・フックには触れない）
+    fn finish_sample(
+        &self,
+        mut run: GroupRun,
+        records: &GroupRecords,
+        capture_times: &HashMap<String, i64>,
+    ) -> Result<GroupRun> {
+        let mut sampled: GroupRecords = run
+            .classified
+            .iter()
+            .filter_map(|k| records.get_key_value(k))
+            .map(|(k, r)| (k.clone(), r.clone()))
+            .collect();
+        crate::apply_capture_times(&mut sampled, capture_times);
+        crate::assign_groups(&mut sampled);
+        if !self.dry_run {
+            run.sample_file = Some(fs_ops::save_sample_records(&self.folder, &sampled)?);
+        }
+        run.records = sampled;
+        Ok(run)
+    }
+
+    /// 見た目の似た写真をまとめ、各まとまりの代表だけを返す（残りは代表の結果を写す）
+    fn cluster_pending(
+        &self,
+        config: &ClusterConfig,
+        pending: &[PathBuf],
+        capture_times: &HashMap<String, i64>,
+    ) -> Result<(Vec<PathBuf>, Followers)> {
+        if config.model.is_empty() {
+            return Err(Error::Validation(format!(
+                "clustering needs [cluster] model = \"<image encoder>.onnx\" in {CONFIG_FILE}"
+            )));
+        }
+        let embedder = OnnxEmbedder::load(&self.folder.join(&config.model), config.input_size)?;
+        let embeddings = cluster::embed_images(&embedder, pending, self.scan_workers);
+        let (clusters, duplicates) =
+            cluster::cluster_images(pending, &embeddings, capture_times, config.threshold, config.duplicate_threshold);
+        self.notify(GroupProgress::Clustered {
+            photos: pending.len(),
+            clusters: clusters.len(),
+            duplicates: &duplicates,
+            embed_failures: embeddings.iter().filter(|e| e.is_err()).count(),
+            first_embed_error: embeddings.iter().find_map(|e| e.as_ref().err()),
+        });
+        let followers = cluster::followers(&clusters);
+        let reps = clusters.iter().map(|c| c.representative().to_path_buf()).collect();
+        Ok((reps, followers))
+    }
+}
+
+/// 指定した機材で撮った写真だけを残す。機材の読めない写真は外す。
+fn filter_by_camera(pending: Vec<PathBuf>, cameras: &HashMap<String, CameraInfo>, names: &[String]) -> Vec<PathBuf> {
+    let index = fs_ops::KeyIndex::new(cameras.keys());
+    pending
+        .into_iter()
+        .filter(|img| {
+            fs_ops::image_key(img)
+                .and_then(|key| index.get(cameras, &key))
+                .is_some_and(|camera| names.iter().any(|name| camera.matches(name)))
+        })
+        .collect()
+}
+
+fn file_label(img: &Path) -> String {
+    img.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// bad.jpg を含むバッチだけ失敗するバックエンド
+    struct FailOnBad;
+
+    impl Backend for FailOnBad {
+        fn analyze(&self, _prompt: &str, images: &[PathBuf]) -> Result<String> {
+            let names: Vec<String> = images.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+            if names.iter().any(|n| n == "bad.jpg") {
+                return Err(Error::backend("quota exceeded"));
+            }
+            let items: Vec<String> = names
+                .iter()
+                .map(|n| format!(r#"{{"file":"{n}","role":"機械全景","machine_type":"roller","machine_id":"{n}"}}"#))
+                .collect();
+            Ok(format!("[{}]", items.join(",")))
+        }
+    }
+
+    #[derive(Default)]
+    struct RunEndErrors(Mutex<Option<usize>>);
+
+    impl Hook for std::sync::Arc<RunEndErrors> {
+        fn on_event(&self, event: &HookEvent<'_>) -> Result<()> {
+            if let HookEvent::RunEnd { errors, .. } = event {
+                *self.0.lock().unwrap() = Some(*errors);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_batch_does_not_stop_the_rest() {
+        let dir = std::env::temp_dir().join(format!("photo-tagger-group-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.jpg", "bad.jpg", "c.jpg"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        let seen = std::sync::Arc::new(RunEndErrors::default());
+        let tagger = PhotoTagger::builder()
+            .folder(&dir)
+            .batch_size(1)
+            .backend(FailOnBad)
+            .hook(seen.clone())
+            .build()
+            .unwrap();
+
+        let result = tagger.group();
+        let saved = load_group_records(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err(), "the batch error is still reported");
+        assert_eq!(saved.keys().collect::<Vec<_>>(), ["a.jpg", "c.jpg"]);
+        assert_eq!(*seen.0.lock().unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+#[cfg(feature = "text-filter")]
+use std::thread;
+
+#[cfg(not(feature = "text-filter"))]
+use crate::error::Error;
+use crate::error::Result;
+
+/// text_score がこれ未満の写真は「黒板・文字なし」とみなす。
+/// 見逃すと黒板の文字が記録されないので、迷う写真は文字ありに倒す低めの値にしている。
+pub const DEFAULT_MIN_TEXT_SCORE: f32 = 0.02;
+
+/// 縮小後の長辺（ピクセル）
+#[cfg(feature = "text-filter")]
+const WORK_SIZE: u32 = 320;
+/// 文字らしさを見る格子の 1 辺（ピクセル）
+#[cfg(feature = "text-filter")]
+const CELL: u32 = 16;
+
+/// 文字らしい領域の割合（0〜1）。エッジを使った簡易判定で、AI に送るプロンプトの振り分けにだけ使う。
+///
+/// 縮小したグレースケール画像を格子に分け、細かいエッジが適度な密度で並ぶマス（黒板の文字・
+/// 銘板・証票など）の割合を返す。空や路面のように平坦なマス、木の葉のようにエッジだらけのマスは数えない。
+#[cfg(feature = "text-filter")]
+pub fn text_score(image: &Path) -> Result<f32> {
+    let img = crate::image_io::open_upright(image)?;
+    let gray = img.thumbnail(WORK_SIZE, WORK_SIZE).to_luma8();
+    let (w, h) = gray.dimensions();
+    if w < CELL + 2 || h < CELL + 2 {
+        return Ok(1.0);
+    }
+    let px = |x: u32, y: u32| gray[(x, y)][0] as i32;
+
+    let (mut cells, mut texty) = (0u32, 0u32);
+    for cy in (1..h - CELL).step_by(CELL as usize) {
+        for cx in (1..w - CELL).step_by(CELL as usize) {
+            let (mut edges, mut min, mut max) = (0u32, 255, 0);
+            for y in cy..cy + CELL {
+                for x in cx..cx + CELL {
+                    let v = px(x, y);
+                    min = min.min(v);
+                    max = max.max(v);
+                    let grad = (px(x + 1, y) - px(x - 1, y)).abs() + (px(x, y + 1) - px(x, y - 1)).abs();
+                    if grad > 60 {
+                        edges += 1;
+                    }
+                }
+            }
+            cells += 1;
+            let density = edges as f32 / (CELL * CELL) as f32;
+            if (0.08..=0.45).contains(&density) && max - min >= 80 {
+                texty += 1;
+            }
+        }
+    }
+    Ok(texty as f32 / cells.max(1) as f32)
+}
+
+#[cfg(not(feature = "text-filter"))]
+pub fn text_score(_image: &Path) -> Result<f32> {
+    Err(disabled())
+}
+
+#[cfg(not(feature = "text-filter"))]
+pub fn split_by_text(_images: &[PathBuf], _min_score: f32, _workers: usize) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    Err(disabled())
+}
+
+#[cfg(not(feature = "text-filter"))]
+fn disabled() -> Error {
+    Error::Validation("--text-filter needs a build with the `text-filter` feature".into())
+}
+
+/// images を文字あり・文字なしに分ける。判定できなかった写真は文字ありに入れる。
+#[cfg(feature = "text-filter")]
+pub fn split_by_text(images: &[PathBuf], min_score: f32, workers: usize) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let chunk = images.len().div_ceil(workers.max(1)).max(1);
+    let scores: Vec<Option<f32>> = thread::scope(|s| {
+        let handles: Vec<_> = images
+            .chunks(chunk)
+            .map(|part| s.spawn(move || part.iter().map(|p| text_score(p).ok()).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("text detection thread panicked"))
+            .collect()
+    });
+    let (mut with_text, mut no_text) = (Vec::new(), Vec::new());
+    for (img, score) in images.iter().zip(scores) {
+        match score {
+            Some(s) if s < min_score => no_text.push(img.clone()),
+            _ => with_text.push(img.clone()),
+        }
+    }
+    Ok((with_text, no_text))
+}
@@ -0,0 +1,413 @@
+use crate::error::{Error, Result};
+use crate::exif::CameraInfo;
+
+/// 既定で認識するファイル名中の日時。前後に任意の文字があってよい。
+/// 20260211_235409.jpg, IMG_20260211_235409.jpg, PXL_20260211_235409123.jpg など。
+/// DSC_0001.JPG や RICOH (R0010001.JPG)、OLYMPUS (P2110001.JPG) は年・時刻を含まないため
+/// 一致せず、ファイル更新時刻にフォールバックする。
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    "YYYYMMDD_hhmmss",
+    "YYYYMMDD-hhmmss",
+    "YYYY-MM-DD_hh-mm-ss",
+    "YYYY-MM-DD hh.mm.ss",
+];
+
+/// 既定のタイムゾーン（ファイル名の日時はカメラの現地時刻）
+pub const DEFAULT_UTC_OFFSET: &str = "+09:00";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Lit(char),
+}
+
+impl Token {
+    fn width(self) -> usize {
+        match self {
+            Token::Year => 4,
+            Token::Lit(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// 現地時刻（タイムゾーン無し）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LocalDateTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl LocalDateTime {
+    pub fn to_epoch(&self, utc_offset_secs: i64) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * 86_400
+            + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+            - utc_offset_secs
+    }
+
+    pub fn from_epoch(epoch: i64, utc_offset_secs: i64) -> Self {
+        let local = epoch + utc_offset_secs;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let secs = local.rem_euclid(86_400) as u32;
+        LocalDateTime {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            minute: secs / 60 % 60,
+            second: secs % 60,
+        }
+    }
+
+    pub fn date(&self) -> (i32, u32, u32) {
+        (self.year, self.month, self.day)
+    }
+
+    /// "YYYY-MM-DD"
+    pub fn date_string(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// "hh:mm"
+    pub fn time_string(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// 現在時刻（現地時刻の表示用文字列 "YYYY-MM-DD hh:mm"）
+pub fn local_now(utc_offset_secs: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let at = LocalDateTime::from_epoch(now, utc_offset_secs);
+    format!("{} {}", at.date_string(), at.time_string())
+}
+
+/// "YYYY-MM-DD" を読む
+pub fn parse_date(s: &str) -> Option<(i32, u32, u32)> {
+    let mut parts = s.trim().splitn(3, '-');
+    let y: i32 = parts.next()?.parse().ok()?;
+    let m: u32 = parts.next()?.parse().ok()?;
+    let d: u32 = parts.next()?.parse().ok()?;
+    ((1..=12).contains(&m) && (1..=days_in_month(y, m)).contains(&d)).then_some((y, m, d))
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// 1970-01-01 からの日数（proleptic Gregorian）
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// days_from_civil の逆変換
+pub fn civil_from_days(z: i64) -> (i32, u32, u32) {
+    let z = z + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y as i32, m, d)
+}
+
+/// "YYYYMMDD_hhmmss" 形式のパターン。YYYY/MM/DD/hh/mm/ss 以外の文字はそのまま一致させる。
+#[derive(Debug, Clone)]
+pub struct TimestampPattern {
+    tokens: Vec<Token>,
+}
+
+impl TimestampPattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            let (tok, len) = [
+                ("YYYY", Token::Year),
+                ("MM", Token::Month),
+                ("DD", Token::Day),
+                ("hh", Token::Hour),
+                ("mm", Token::Minute),
+                ("ss", Token::Second),
+            ]
+            .iter()
+            .find(|(key, _)| rest.starts_with(key))
+            .map(|(key, tok)| (*tok, key.len()))
+            .unwrap_or_else(|| {
+                let c = rest.chars().next().unwrap_or_default();
+                (Token::Lit(c), c.len_utf8())
+            });
+            tokens.push(tok);
+            rest = &rest[len..];
+        }
+        for required in [Token::Year, Token::Month, Token::Day] {
+            if !tokens.contains(&required) {
+                return Err(Error::Validation(format!(
+                    "timestamp pattern {pattern:?} needs YYYY, MM and DD"
+                )));
+            }
+        }
+        Ok(TimestampPattern { tokens })
+    }
+
+    fn match_at(&self, chars: &[char], start: usize) -> Option<LocalDateTime> {
+        let mut dt = LocalDateTime { year: 0, month: 0, day: 0, hour: 0, minute: 0, second: 0 };
+        let mut pos = start;
+        for &tok in &self.tokens {
+            let field = chars.get(pos..pos + tok.width())?;
+            pos += tok.width();
+            if let Token::Lit(c) = tok {
+                if field[0] != c {
+                    return None;
+                }
+                continue;
+            }
+            let mut value = 0u32;
+            for c in field {
+                value = value * 10 + c.to_digit(10)?;
+            }
+            match tok {
+                Token::Year => dt.year = value as i32,
+                Token::Month => dt.month = value,
+                Token::Day => dt.day = value,
+                Token::Hour => dt.hour = value,
+                Token::Minute => dt.minute = value,
+                Token::Second => dt.second = value,
+                Token::Lit(_) => unreachable!(),
+            }
+        }
+        let valid = (1..=12).contains(&dt.month)
+            && (1..=days_in_month(dt.year, dt.month)).contains(&dt.day)
+            && dt.hour < 24
+            && dt.minute < 60
+            && dt.second < 60;
+        valid.then_some(dt)
+    }
+
+    /// 文字列中の最初の一致を返す（直前が数字の位置からは始めない）
+    pub fn find(&self, text: &str) -> Option<LocalDateTime> {
+        let chars: Vec<char> = text.chars().collect();
+        (0..chars.len())
+            .filter(|&i| i == 0 || !chars[i - 1].is_ascii_digit())
+            .find_map(|i| self.match_at(&chars, i))
+    }
+}
+
+/// "+09:00" / "-05:30" / "Z" を秒に変換
+pub fn parse_utc_offset(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("z") {
+        return Some(0);
+    }
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (h, m) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (h, m): (i64, i64) = (h.parse().ok()?, m.parse().ok()?);
+    (h <= 14 && m < 60).then_some(sign * (h * 3600 + m * 60))
+}
+
+/// 時計の補正 "+00:03:20" / "-03:20"（[+-][hh:]mm:ss）を秒に変換
+pub fn parse_clock_offset(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => (1, s),
+    };
+    let parts: Vec<i64> = rest.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (h, m, sec) = match parts[..] {
+        [h, m, sec] => (h, m, sec),
+        [m, sec] => (0, m, sec),
+        _ => return None,
+    };
+    (h >= 0 && (0..60).contains(&m) && (0..60).contains(&sec)).then_some(sign * (h * 3600 + m * 60 + sec))
+}
+
+/// 時計のずれた機材の補正（--time-offset）。機材（機種名・シリアル番号）→ 撮影時刻に足す秒数。
+#[derive(Debug, Clone, Default)]
+pub struct ClockOffsets {
+    offsets: Vec<(String, i64)>,
+}
+
+impl ClockOffsets {
+    /// 同じ機材に当てはまるものが複数あれば先に書いたものが優先
+    pub fn new(offsets: Vec<(String, i64)>) -> Self {
+        ClockOffsets { offsets }
+    }
+
+    /// camera に当てはまる補正（秒）。無ければ 0。
+    pub fn offset_for(&self, camera: &CameraInfo) -> i64 {
+        self.offsets
+            .iter()
+            .find(|(name, _)| camera.matches(name))
+            .map_or(0, |(_, secs)| *secs)
+    }
+}
+
+/// 秒 → "+00:03:20"
+pub fn format_clock_offset(secs: i64) -> String {
+    let sign = if secs < 0 { '-' } else { '+' };
+    let a = secs.unsigned_abs();
+    format!("{sign}{:02}:{:02}:{:02}", a / 3600, a / 60 % 60, a % 60)
+}
+
+/// ファイル名から撮影時刻（UNIX 秒）を読み取る
+#[derive(Debug, Clone)]
+pub struct TimestampParser {
+    patterns: Vec<TimestampPattern>,
+    utc_offset_secs: i64,
+}
+
+impl Default for TimestampParser {
+    fn default() -> Self {
+        TimestampParser::new(DEFAULT_PATTERNS, DEFAULT_UTC_OFFSET).expect("default patterns are valid")
+    }
+}
+
+impl TimestampParser {
+    pub fn new<S: AsRef<str>>(patterns: &[S], utc_offset: &str) -> Result<Self> {
+        let utc_offset_secs = parse_utc_offset(utc_offset)
+            .ok_or_else(|| Error::Validation(format!("invalid utc_offset {utc_offset:?}")))?;
+        let patterns = patterns
+            .iter()
+            .map(|p| TimestampPattern::parse(p.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(TimestampParser { patterns, utc_offset_secs })
+    }
+
+    /// 最初に一致したパターンの現地時刻
+    pub fn parse_local(&self, fname: &str) -> Option<LocalDateTime> {
+        self.patterns.iter().find_map(|p| p.find(fname))
+    }
+
+    pub fn parse_photo_timestamp(&self, fname: &str) -> Option<i64> {
+        self.parse_local(fname).map(|dt| dt.to_epoch(self.utc_offset_secs))
+    }
+
+    pub fn utc_offset_secs(&self) -> i64 {
+        self.utc_offset_secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(p: &str) -> TimestampPattern {
+        TimestampPattern::parse(p).unwrap()
+    }
+
+    fn dt(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> LocalDateTime {
+        LocalDateTime { year, month, day, hour, minute, second }
+    }
+
+    #[test]
+    fn find_accepts_leap_days_only_in_leap_years() {
+        let p = pattern("YYYYMMDD_hhmmss");
+        assert_eq!(p.find("20240229_120000.jpg"), Some(dt(2024, 2, 29, 12, 0, 0)));
+        assert_eq!(p.find("20000229_120000.jpg"), Some(dt(2000, 2, 29, 12, 0, 0)));
+        assert_eq!(p.find("20260229_120000.jpg"), None);
+        assert_eq!(p.find("19000229_120000.jpg"), None);
+    }
+
+    #[test]
+    fn find_rejects_invalid_dates_and_times() {
+        let p = pattern("YYYYMMDD_hhmmss");
+        let invalid = [
+            "20260230_120000",
+            "20260431_120000",
+            "20261301_120000",
+            "20260100_120000",
+            "20260211_240000",
+            "20260211_236000",
+            "20260211_235960",
+        ];
+        for name in invalid {
+            assert_eq!(p.find(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn find_starts_only_at_a_digit_boundary() {
+        let p = pattern("YYYYMMDD_hhmmss");
+        assert_eq!(p.find("IMG_20260211_235409.jpg"), Some(dt(2026, 2, 11, 23, 54, 9)));
+        // 後ろに数字が続くのは構わない（ミリ秒付きの PXL_…）
+        assert_eq!(p.find("PXL_20260211_235409123.jpg"), Some(dt(2026, 2, 11, 23, 54, 9)));
+        // 数字の途中からは読まない: 120260211_… の "20260211_…" には一致させない
+        assert_eq!(p.find("X120260211_235409.jpg"), None);
+        // 一致しない位置を飛ばして後ろの日時を拾う
+        assert_eq!(p.find("20261399_000000 20260211_235409"), Some(dt(2026, 2, 11, 23, 54, 9)));
+        assert_eq!(p.find("DSC_0001.JPG"), None);
+    }
+
+    #[test]
+    fn parse_clock_offset_handles_signs_and_short_forms() {
+        assert_eq!(parse_clock_offset("+00:03:20"), Some(200));
+        assert_eq!(parse_clock_offset("-00:03:20"), Some(-200));
+        assert_eq!(parse_clock_offset("-03:20"), Some(-200));
+        assert_eq!(parse_clock_offset("03:20"), Some(200));
+        assert_eq!(parse_clock_offset("-1:00:00"), Some(-3600));
+        for bad in ["", "+", "20", "00:60:00", "00:00:60", "+-1:00:00", "1:2:3:4", "a:00"] {
+            assert_eq!(parse_clock_offset(bad), None, "{bad:?}");
+        }
+        for secs in [-86_399, -3_600, -200, -1, 0, 1, 200, 3_600, 86_399] {
+            assert_eq!(parse_clock_offset(&format_clock_offset(secs)), Some(secs));
+        }
+    }
+
+    #[test]
+    fn days_from_civil_known_days_and_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29) + 1, days_from_civil(2024, 3, 1));
+        assert_eq!(days_from_civil(2026, 2, 28) + 1, days_from_civil(2026, 3, 1));
+        for days in (-800_000..800_000).step_by(97) {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y as i64, m, d), days);
+        }
+    }
+
+    #[test]
+    fn epoch_round_trip() {
+        assert_eq!(dt(1970, 1, 1, 9, 0, 0).to_epoch(9 * 3600), 0);
+        assert_eq!(LocalDateTime::from_epoch(0, -5 * 3600), dt(1969, 12, 31, 19, 0, 0));
+        for offset in [-12 * 3600, -5 * 3600 - 1800, 0, 9 * 3600, 14 * 3600] {
+            for epoch in (-2_000_000_000i64..4_000_000_000).step_by(7_777_777) {
+                assert_eq!(LocalDateTime::from_epoch(epoch, offset).to_epoch(offset), epoch);
+            }
+        }
+        let parser = TimestampParser::default();
+        let ts = parser.parse_photo_timestamp("20240229_235959.jpg").unwrap();
+        assert_eq!(LocalDateTime::from_epoch(ts, parser.utc_offset_secs()), dt(2024, 2, 29, 23, 59, 59));
+    }
+}
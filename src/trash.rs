@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::fs_ops::{long_path, state_dir};
+use crate::timestamp::LocalDateTime;
+
+/// .photo-tagger/ 内のごみ箱。ツールが消すファイルはここへ移し、`purge` でだけ削除する。
+pub(crate) const TRASH_DIR: &str = "trash";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// ごみ箱 1 回分（.photo-tagger/trash/<YYYYMMDD-hhmmss>/manifest.json）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashManifest {
+    /// 移した理由（restore など）
+    pub reason: String,
+    pub trashed_at: i64,
+    /// base からの相対パス。ごみ箱内も同じ相対パスに置く。
+    pub files: Vec<PathBuf>,
+}
+
+pub fn trash_dir(base: &Path) -> PathBuf {
+    state_dir(base).join(TRASH_DIR)
+}
+
+/// base からの相対パスで指定したファイルをごみ箱へ移し、移した先のフォルダを返す（対象が無ければ None）
+pub fn move_to_trash(base: &Path, files: &[PathBuf], reason: &str, utc_offset_secs: i64) -> Result<Option<PathBuf>> {
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let stamp = LocalDateTime::from_epoch(now, utc_offset_secs);
+    let (y, mo, d) = stamp.date();
+    let name = format!("{y:04}{mo:02}{d:02}-{:02}{:02}{:02}", stamp.hour, stamp.minute, stamp.second);
+    let mut dir = trash_dir(base).join(&name);
+    let mut n = 2;
+    while dir.exists() {
+        dir = trash_dir(base).join(format!("{name}-{n}"));
+        n += 1;
+    }
+
+    // 移す前に目録を書く（途中で失敗しても何を移そうとしたか残る）
+    std::fs::create_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+    let manifest = TrashManifest { reason: reason.to_string(), trashed_at: now, files: files.to_vec() };
+    let path = dir.join(MANIFEST_FILE);
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| Error::Parse {
+        message: "Failed to serialize trash manifest".into(),
+        source: Some(e),
+    })?;
+    std::fs::write(long_path(&path), json).map_err(|e| Error::io(&path, e))?;
+
+    for rel in files {
+        let src = base.join(rel);
+        let dest = dir.join(rel);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(long_path(parent)).map_err(|e| Error::io(parent, e))?;
+        }
+        std::fs::rename(long_path(&src), long_path(&dest)).map_err(|e| Error::io(&src, e))?;
+    }
+    Ok(Some(dir))
+}
+
+/// ごみ箱の中身（古い順）。目録が読めないフォルダは除く。
+pub fn list_trash(base: &Path) -> Vec<(PathBuf, TrashManifest)> {
+    let Ok(entries) = std::fs::read_dir(trash_dir(base)) else { return Vec::new() };
+    let mut out: Vec<(PathBuf, TrashManifest)> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter_map(|dir| {
+            let text = std::fs::read_to_string(dir.join(MANIFEST_FILE)).ok()?;
+            let manifest = serde_json::from_str(&text).ok()?;
+            Some((dir, manifest))
+        })
+        .collect();
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    out
+}
+
+/// ごみ箱から完全に削除する。older_than_secs を指定するとそれより古いものだけ。削除したフォルダを返す。
+pub fn purge_trash(base: &Path, older_than_secs: Option<i64>) -> Result<Vec<PathBuf>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut purged = Vec::new();
+    for (dir, manifest) in list_trash(base) {
+        if older_than_secs.is_some_and(|age| now - manifest.trashed_at < age) {
+            continue;
+        }
+        std::fs::remove_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+        purged.push(dir);
+    }
+    Ok(purged)
+}
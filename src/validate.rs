@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use crate::domain::{is_machine_role, GroupRecords};
+use crate::fs_ops::KeyIndex;
+use crate::machine_set::MachineSets;
+use crate::project::parse_folder_date;
+use crate::timestamp::LocalDateTime;
+
+/// 作業時間帯と休日（photo-tagger.toml の [working_hours]）
+#[derive(Debug, Clone, Default)]
+pub struct WorkingHours {
+    /// 0 時からの分。(開始, 終了)。開始 > 終了 なら日をまたぐ夜間作業。None なら終日。
+    pub window: Option<(u32, u32)>,
+    pub holidays: BTreeSet<(i32, u32, u32)>,
+}
+
+impl WorkingHours {
+    /// 作業時間外・休日なら理由を返す
+    pub fn check(&self, at: LocalDateTime) -> Option<String> {
+        if self.holidays.contains(&at.date()) {
+            return Some(format!("captured {} {} on a holiday", at.date_string(), at.time_string()));
+        }
+        let (start, end) = self.window?;
+        let minute = at.hour * 60 + at.minute;
+        let inside = if start <= end { (start..end).contains(&minute) } else { minute >= start || minute < end };
+        let hhmm = |m: u32| format!("{:02}:{:02}", m / 60, m % 60);
+        (!inside).then(|| {
+            format!(
+                "captured {} {} outside {}-{}; check the camera clock",
+                at.date_string(),
+                at.time_string(),
+                hhmm(start),
+                hhmm(end)
+            )
+        })
+    }
+}
+
+/// 検査で見つかった問題の種類。並び順がそのまま --strict の優先順位になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IssueKind {
+    /// AI 分類が失敗した写真がある
+    ClassificationError,
+    /// レコードが無い・グループ未付与の写真がある
+    Unassigned,
+    /// 機械 1 台分の写真（既定は全景・検査証票・排ガス/低騒音証票、[machine_sets] で変更可）が揃っていない
+    IncompleteMachineSet,
+    /// 撮影日がフォルダ名の日付と違う
+    DateMismatch,
+    /// 作業時間外・休日に撮影されている（カメラの時計ずれのことが多い）
+    OffHours,
+}
+
+impl IssueKind {
+    /// --strict 時の終了コード（clap の 2、anyhow エラーの 1 と重ならない値）
+    pub fn exit_code(self) -> i32 {
+        match self {
+            IssueKind::ClassificationError => 10,
+            IssueKind::Unassigned => 11,
+            IssueKind::IncompleteMachineSet => 12,
+            IssueKind::DateMismatch => 13,
+            IssueKind::OffHours => 14,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            IssueKind::ClassificationError => "classification error",
+            IssueKind::Unassigned => "unassigned",
+            IssueKind::IncompleteMachineSet => "incomplete machine set",
+            IssueKind::DateMismatch => "date mismatch",
+            IssueKind::OffHours => "off hours",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// ファイル名、または「機械種別 (型式)」
+    pub subject: String,
+    pub detail: String,
+}
+
+/// 最も優先度の高い問題の終了コード。問題が無ければ None。
+pub fn strict_exit_code(issues: &[Issue]) -> Option<i32> {
+    issues.iter().map(|i| i.kind).min().map(IssueKind::exit_code)
+}
+
+/// フォルダの画像一覧とレコードを突き合わせて問題を洗い出す（AI 呼び出し無し）
+pub fn validate_group_records(
+    folder: &std::path::Path,
+    records: &GroupRecords,
+    images: &[PathBuf],
+    utc_offset_secs: i64,
+    working_hours: Option<&WorkingHours>,
+    machine_sets: &MachineSets,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let index = KeyIndex::new(records.keys());
+
+    for img in images {
+        let Some(fname) = img.file_name().and_then(|n| n.to_str()) else {
+            issues.push(Issue {
+                kind: IssueKind::Unassigned,
+                subject: img.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+                detail: "file name is not valid UTF-8; rename it".into(),
+            });
+            continue;
+        };
+        match index.find(records, fname).map(|(_, rec)| rec) {
+            None => issues.push(Issue {
+                kind: IssueKind::Unassigned,
+                subject: fname.to_string(),
+                detail: "no record".into(),
+            }),
+            Some(rec) if rec.group == 0 => issues.push(Issue {
+                kind: IssueKind::Unassigned,
+                subject: fname.to_string(),
+                detail: "no group".into(),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    // 機械の役割の写真が 1 枚でもある機械について、同じ機械の全写真の役割（保険証などテンプレートで足した役割を含む）
+    let machine_keys: BTreeSet<(&str, &str)> = records
+        .values()
+        .filter(|r| is_machine_role(&r.role))
+        .map(|r| (r.machine_type.as_str(), r.machine_id.as_str()))
+        .collect();
+    let mut machines: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for rec in records.values() {
+        let key = (rec.machine_type.as_str(), rec.machine_id.as_str());
+        if machine_keys.contains(&key) {
+            machines.entry(key).or_default().push(rec.role.as_str());
+        }
+    }
+    for ((machine_type, machine_id), roles) in &machines {
+        let missing = machine_sets.missing(machine_type, roles);
+        if !missing.is_empty() {
+            issues.push(Issue {
+                kind: IssueKind::IncompleteMachineSet,
+                subject: format!("{machine_type} ({machine_id})"),
+                detail: format!("missing {}", missing.join(", ")),
+            });
+        }
+    }
+
+    let folder_date = folder
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(parse_folder_date);
+    if let Some(date) = folder_date {
+        for (fname, rec) in records {
+            let Some(ts) = rec.captured_at else { continue };
+            let at = LocalDateTime::from_epoch(ts, utc_offset_secs);
+            if at.date() != date {
+                issues.push(Issue {
+                    kind: IssueKind::DateMismatch,
+                    subject: fname.clone(),
+                    detail: format!("captured {} but folder is {:04}-{:02}-{:02}", at.date_string(), date.0, date.1, date.2),
+                });
+            }
+        }
+    }
+
+    if let Some(hours) = working_hours {
+        issues.extend(off_hours_issues(records, hours, utc_offset_secs));
+    }
+
+    issues.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.subject.cmp(&b.subject)));
+    issues
+}
+
+/// 作業時間外・休日に撮影された写真。撮影時刻の無い写真は対象外。
+pub fn off_hours_issues(records: &GroupRecords, hours: &WorkingHours, utc_offset_secs: i64) -> Vec<Issue> {
+    records
+        .iter()
+        .filter_map(|(fname, rec)| {
+            let at = LocalDateTime::from_epoch(rec.captured_at?, utc_offset_secs);
+            Some(Issue { kind: IssueKind::OffHours, subject: fname.clone(), detail: hours.check(at)? })
+        })
+        .collect()
+}
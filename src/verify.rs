@@ -0,0 +1,337 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::domain::GroupRecords;
+use crate::error::{Error, Result};
+use crate::fs_ops::{
+    collect_images_flat, long_path, nested_subdirs, record_key, sorted_dir_tag, state_dir, KeyIndex, FOLD_CASE,
+};
+use crate::tag::{tag_dir_name, TagRecords};
+use crate::timestamp::LocalDateTime;
+
+/// `gc --archive` で取り除いたレコードを残す .photo-tagger/ 内のフォルダ
+const ARCHIVE_DIR: &str = "archive";
+
+/// フォルダの実態とレコードの食い違いの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FindingKind {
+    /// レコードはあるがファイルがどこにも無い（--fix で削除できる）
+    DanglingRecord,
+    /// グループのレコードがあるが、ファイルはサブフォルダに移動されている
+    MovedPhoto,
+    /// タグと違うサブフォルダに置かれている
+    MisplacedTag,
+    /// どのレコードにも無い画像
+    UntrackedImage,
+}
+
+impl FindingKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            FindingKind::DanglingRecord => "dangling record",
+            FindingKind::MovedPhoto => "moved photo",
+            FindingKind::MisplacedTag => "misplaced tag",
+            FindingKind::UntrackedImage => "untracked image",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub file: String,
+    /// 対象のレコードファイル（photo-groups.json / photo-tags.json）
+    pub source: &'static str,
+    pub detail: String,
+}
+
+/// base からの相対パス（区切りは `/`）
+fn place_name(base: &Path, dir: &Path) -> String {
+    let rel = dir.strip_prefix(base).unwrap_or(dir);
+    rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// base 直下とその下のサブフォルダ（隠しフォルダを除く）の画像: ファイル名 → 置き場所（直下は空）
+fn image_locations(base: &Path) -> BTreeMap<String, Vec<String>> {
+    let mut out: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut add = |dir: &Path, place: &str| {
+        for img in collect_images_flat(dir) {
+            if let Some(name) = img.file_name().and_then(|n| n.to_str()) {
+                out.entry(record_key(name)).or_default().push(place.to_string());
+            }
+        }
+    };
+    add(base, "");
+    for dir in nested_subdirs(base) {
+        add(&dir, &place_name(base, &dir));
+    }
+    out
+}
+
+/// `tag --move` が作ったサブフォルダ: 置き場所 → 振り分けたタグ
+fn sorted_places(base: &Path) -> BTreeMap<String, String> {
+    nested_subdirs(base)
+        .iter()
+        .filter_map(|dir| Some((place_name(base, dir), sorted_dir_tag(dir)?)))
+        .collect()
+}
+
+/// レコードとフォルダ内の画像を突き合わせる（読み取りのみ）。
+/// Windows ではレコードと大文字小文字だけ違うファイルも同じ写真とみなす。
+pub fn verify_folder(base: &Path, groups: &GroupRecords, tags: &TagRecords) -> Vec<Finding> {
+    verify_folder_with(base, groups, tags, FOLD_CASE)
+}
+
+fn verify_folder_with(base: &Path, groups: &GroupRecords, tags: &TagRecords, fold_case: bool) -> Vec<Finding> {
+    let locations = image_locations(base);
+    let location_index = KeyIndex::with_fold_case(locations.keys(), fold_case);
+    let group_index = KeyIndex::with_fold_case(groups.keys(), fold_case);
+    let tag_index = KeyIndex::with_fold_case(tags.keys(), fold_case);
+    let sorted = sorted_places(base);
+    // 目印のあるフォルダは元のタグで、無いフォルダは今のフォルダ名の規則で照合する
+    let in_tag_dir = |place: &str, tag: &str| match sorted.get(place) {
+        Some(sorted_tag) => sorted_tag == tag,
+        None => place == tag_dir_name(tag),
+    };
+    let mut findings = Vec::new();
+
+    for fname in groups.keys() {
+        match location_index.find(&locations, fname).map(|(_, places)| places) {
+            None => findings.push(Finding {
+                kind: FindingKind::DanglingRecord,
+                file: fname.clone(),
+                source: "photo-groups.json",
+                detail: "file not found".into(),
+            }),
+            Some(places) if !places.iter().any(|p| p.is_empty()) => findings.push(Finding {
+                kind: FindingKind::MovedPhoto,
+                file: fname.clone(),
+                source: "photo-groups.json",
+                detail: format!("found in {}/", places.join("/, ")),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (fname, rec) in tags {
+        match location_index.find(&locations, fname).map(|(_, places)| places) {
+            None => findings.push(Finding {
+                kind: FindingKind::DanglingRecord,
+                file: fname.clone(),
+                source: "photo-tags.json",
+                detail: "file not found".into(),
+            }),
+            Some(places) if !places.iter().any(|p| p.is_empty() || in_tag_dir(p, &rec.tag)) => findings.push(Finding {
+                kind: FindingKind::MisplacedTag,
+                file: fname.clone(),
+                source: "photo-tags.json",
+                detail: format!("tagged {} but found in {}/", rec.tag, places.join("/, ")),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (fname, places) in &locations {
+        if group_index.find(groups, fname).is_none() && tag_index.find(tags, fname).is_none() {
+            let detail = match sorted.get(&places[0]) {
+                _ if places[0].is_empty() => "no record".to_string(),
+                Some(tag) => format!("no record (in {}/, sorted as {tag})", places[0]),
+                None => format!("no record (in {}/)", places[0]),
+            };
+            findings.push(Finding {
+                kind: FindingKind::UntrackedImage,
+                file: fname.clone(),
+                source: "",
+                detail,
+            });
+        }
+    }
+
+    findings.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.file.cmp(&b.file)));
+    findings
+}
+
+/// 取り除いたレコード（記録ファイル名 → レコード）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RemovedRecords {
+    #[serde(rename = "photo-groups.json", skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: GroupRecords,
+    #[serde(rename = "photo-tags.json", skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: TagRecords,
+}
+
+impl RemovedRecords {
+    pub fn len(&self) -> usize {
+        self.groups.len() + self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 安全に直せるもの（ファイルがフォルダのどこにも無いレコード）だけを取り除いて返す。
+/// サブフォルダへ移した写真のレコードは残す。
+pub fn drop_dangling(groups: &mut GroupRecords, tags: &mut TagRecords, findings: &[Finding]) -> RemovedRecords {
+    let mut removed = RemovedRecords::default();
+    for f in findings.iter().filter(|f| f.kind == FindingKind::DanglingRecord) {
+        if f.source == "photo-groups.json" {
+            if let Some(rec) = groups.remove(&f.file) {
+                removed.groups.insert(f.file.clone(), rec);
+            }
+        } else if let Some(rec) = tags.remove(&f.file) {
+            removed.tags.insert(f.file.clone(), rec);
+        }
+    }
+    removed
+}
+
+/// 取り除いたレコードを .photo-tagger/archive/gc-YYYYMMDD-hhmmss.json に残し、そのパスを返す
+pub fn archive_removed(base: &Path, removed: &RemovedRecords, utc_offset_secs: i64) -> Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let at = LocalDateTime::from_epoch(now, utc_offset_secs);
+    let (y, mo, d) = at.date();
+    let dir = state_dir(base).join(ARCHIVE_DIR);
+    std::fs::create_dir_all(long_path(&dir)).map_err(|e| Error::io(&dir, e))?;
+    let name = format!("gc-{y:04}{mo:02}{d:02}-{:02}{:02}{:02}", at.hour, at.minute, at.second);
+    let mut path = dir.join(format!("{name}.json"));
+    let mut n = 2;
+    while long_path(&path).exists() {
+        path = dir.join(format!("{name}-{n}.json"));
+        n += 1;
+    }
+    let json = serde_json::to_string_pretty(removed).map_err(|e| Error::Parse {
+        message: "Failed to serialize removed records".into(),
+        source: Some(e),
+    })?;
+    std::fs::write(long_path(&path), json).map_err(|e| Error::io(&path, e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::GroupRecord;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("photo-tagger-verify-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn group_record(machine_id: &str) -> GroupRecord {
+        serde_json::from_value(serde_json::json!({
+            "role": "機械全景", "machine_type": "roller", "machine_id": machine_id, "group": 1
+        }))
+        .unwrap()
+    }
+
+    fn tag_record(tag: &str) -> crate::tag::TagRecord {
+        serde_json::from_value(serde_json::json!({ "tag": tag })).unwrap()
+    }
+
+    fn touch(path: PathBuf) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"x").unwrap();
+    }
+
+    fn kinds(findings: &[Finding]) -> Vec<(FindingKind, &str)> {
+        findings.iter().map(|f| (f.kind, f.file.as_str())).collect()
+    }
+
+    #[test]
+    fn record_differing_only_by_case_is_not_dangling_when_folding() {
+        let dir = scratch("case");
+        std::fs::write(dir.join("img_0001.jpg"), b"x").unwrap();
+        let mut groups: GroupRecords = [("IMG_0001.JPG".to_string(), group_record("M1"))].into_iter().collect();
+        let mut tags = TagRecords::new();
+
+        let folded = verify_folder_with(&dir, &groups, &tags, true);
+        assert!(folded.is_empty(), "{:?}", kinds(&folded));
+        assert!(drop_dangling(&mut groups, &mut tags, &folded).is_empty());
+        assert!(groups.contains_key("IMG_0001.JPG"));
+
+        // 大文字小文字を区別するファイルシステムでは別の写真
+        let exact = verify_folder_with(&dir, &groups, &tags, false);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            kinds(&exact),
+            [(FindingKind::DanglingRecord, "IMG_0001.JPG"), (FindingKind::UntrackedImage, "img_0001.jpg")]
+        );
+    }
+
+    #[test]
+    fn verify_reports_each_kind_of_mismatch() {
+        let dir = scratch("kinds");
+        touch(dir.join("here.jpg"));
+        touch(dir.join("sub").join("moved.jpg"));
+        touch(dir.join("舗装工").join("paved.jpg"));
+        touch(dir.join("擁壁工").join("wrong.jpg"));
+        touch(dir.join("残り").join("stray.jpg"));
+        // tag --move が作ったフォルダは目印の元のタグで照合する
+        touch(dir.join("sorted").join("marked.jpg"));
+        std::fs::write(dir.join("sorted").join(crate::fs_ops::SORTED_MARKER_FILE), "区画線工").unwrap();
+
+        let groups: GroupRecords = ["here.jpg", "moved.jpg", "gone.jpg"]
+            .into_iter()
+            .map(|f| (f.to_string(), group_record("M1")))
+            .collect();
+        let tags: TagRecords = [
+            ("paved.jpg", "舗装工"),
+            ("wrong.jpg", "舗装工"),
+            ("marked.jpg", "区画線工"),
+            ("lost.jpg", "舗装工"),
+        ]
+        .into_iter()
+        .map(|(f, t)| (f.to_string(), tag_record(t)))
+        .collect();
+
+        let findings = verify_folder_with(&dir, &groups, &tags, false);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            kinds(&findings),
+            [
+                (FindingKind::DanglingRecord, "gone.jpg"),
+                (FindingKind::DanglingRecord, "lost.jpg"),
+                (FindingKind::MovedPhoto, "moved.jpg"),
+                (FindingKind::MisplacedTag, "wrong.jpg"),
+                (FindingKind::UntrackedImage, "stray.jpg"),
+            ]
+        );
+        let source = |file: &str| findings.iter().find(|f| f.file == file).unwrap().source;
+        assert_eq!(source("gone.jpg"), "photo-groups.json");
+        assert_eq!(source("lost.jpg"), "photo-tags.json");
+    }
+
+    #[test]
+    fn drop_dangling_keeps_moved_and_misplaced_photos() {
+        let dir = scratch("drop");
+        touch(dir.join("sub").join("moved.jpg"));
+        touch(dir.join("擁壁工").join("wrong.jpg"));
+        let mut groups: GroupRecords = ["moved.jpg", "gone.jpg"]
+            .into_iter()
+            .map(|f| (f.to_string(), group_record("M1")))
+            .collect();
+        let mut tags: TagRecords = [("wrong.jpg", "舗装工"), ("lost.jpg", "舗装工")]
+            .into_iter()
+            .map(|(f, t)| (f.to_string(), tag_record(t)))
+            .collect();
+
+        let findings = verify_folder_with(&dir, &groups, &tags, false);
+        let removed = drop_dangling(&mut groups, &mut tags, &findings);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(removed.groups.keys().collect::<Vec<_>>(), ["gone.jpg"]);
+        assert_eq!(removed.tags.keys().collect::<Vec<_>>(), ["lost.jpg"]);
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["moved.jpg"]);
+        assert_eq!(tags.keys().collect::<Vec<_>>(), ["wrong.jpg"]);
+        // 2 回目は何も消さない
+        assert!(drop_dangling(&mut groups, &mut tags, &findings).is_empty());
+    }
+}
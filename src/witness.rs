@@ -0,0 +1,144 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::report::to_csv;
+use crate::timestamp::{days_in_month, LocalDateTime};
+
+/// 立会・段階確認の写真の区別
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessKind {
+    /// 段階確認
+    StageCheck,
+    /// 立会
+    Attendance,
+}
+
+impl WitnessKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            WitnessKind::StageCheck => "段階確認",
+            WitnessKind::Attendance => "立会",
+        }
+    }
+}
+
+/// 黒板の文字から立会・段階確認の写真かを判定する。両方あれば段階確認（立会の上で行うため）。
+pub fn witness_kind(text: &str) -> Option<WitnessKind> {
+    let text: String = text.nfkc().filter(|c| !c.is_whitespace()).collect();
+    if text.contains("段階確認") {
+        Some(WitnessKind::StageCheck)
+    } else if ["立会", "立ち会", "立合"].iter().any(|w| text.contains(w)) {
+        Some(WitnessKind::Attendance)
+    } else {
+        None
+    }
+}
+
+/// 立会者を書く欄の見出し
+const ATTENDEE_LABELS: [&str; 5] = ["立会者", "立会人", "立ち会い者", "確認者", "監督員"];
+
+/// 黒板の「立会者：山田、佐藤」のような欄から立会者を読む。見出しの後ろ（行末まで）を区切り文字で分ける。
+pub fn parse_attendees(text: &str) -> Vec<String> {
+    let mut attendees = Vec::new();
+    for line in text.nfkc().collect::<String>().lines() {
+        let Some(rest) = ATTENDEE_LABELS.iter().find_map(|label| line.split_once(label).map(|(_, r)| r)) else {
+            continue;
+        };
+        let rest = rest.trim_start_matches([':', ' ', '　']);
+        for name in rest.split([',', '、', '・', '/']) {
+            let name = name.trim();
+            if !name.is_empty() && !attendees.iter().any(|a| a == name) {
+                attendees.push(name.to_string());
+            }
+        }
+    }
+    attendees
+}
+
+/// 黒板の日付（2026年2月11日・2026/2/11・2026-02-11・令和8年2月11日・R8.2.11）を読む
+pub fn parse_board_date(text: &str) -> Option<(i32, u32, u32)> {
+    let text: String = text.nfkc().collect();
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len()).find_map(|i| {
+        let rest: String = chars[i..].iter().collect();
+        let (era_base, rest) = if let Some(r) = rest.strip_prefix("令和") {
+            (2018, r)
+        } else if let Some(r) = rest.strip_prefix('R').or_else(|| rest.strip_prefix('r')) {
+            (2018, r)
+        } else if i > 0 && chars[i - 1].is_ascii_digit() {
+            return None;
+        } else {
+            (0, rest.as_str())
+        };
+        let (y, m, d) = three_numbers(rest)?;
+        let year = if era_base > 0 { (y < 100).then_some(era_base + y)? } else { (y >= 1000).then_some(y)? };
+        let (month, day) = (m as u32, d as u32);
+        ((1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day)).then_some((year, month, day))
+    })
+}
+
+/// 先頭から「数字 区切り 数字 区切り 数字」を読む。区切りは 年/月・/・-・.
+fn three_numbers(s: &str) -> Option<(i32, i32, i32)> {
+    let mut numbers = Vec::new();
+    let mut chars = s.chars().peekable();
+    while numbers.len() < 3 {
+        let digits: String = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect();
+        numbers.push(digits.parse::<i32>().ok()?);
+        if numbers.len() < 3 {
+            let sep = chars.next()?;
+            if !matches!((numbers.len(), sep), (1, '年') | (2, '月') | (_, '/') | (_, '-') | (_, '.')) {
+                return None;
+            }
+        }
+    }
+    Some((numbers[0], numbers[1], numbers[2]))
+}
+
+/// 立会一覧の 1 行（写真 1 枚）
+#[derive(Debug, Clone)]
+pub struct WitnessRow {
+    pub file: String,
+    pub kind: WitnessKind,
+    /// 黒板の日付。読めなければ撮影日
+    pub date: Option<(i32, u32, u32)>,
+    pub attendees: Vec<String>,
+    pub board: String,
+}
+
+/// (ファイル名, 黒板の文字, 撮影時刻) から立会・段階確認の写真を日付順に集める
+pub fn witness_rows<'a>(
+    photos: impl IntoIterator<Item = (&'a String, &'a str, Option<i64>)>,
+    utc_offset_secs: i64,
+) -> Vec<WitnessRow> {
+    let mut rows: Vec<WitnessRow> = photos
+        .into_iter()
+        .filter_map(|(file, text, captured_at)| {
+            let kind = witness_kind(text)?;
+            let date = parse_board_date(text)
+                .or_else(|| captured_at.map(|ts| LocalDateTime::from_epoch(ts, utc_offset_secs).date()));
+            Some(WitnessRow {
+                file: file.clone(),
+                kind,
+                date,
+                attendees: parse_attendees(text),
+                board: text.replace(['\n', '\r'], " "),
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| a.date.is_none().cmp(&b.date.is_none()).then(a.date.cmp(&b.date)).then(a.file.cmp(&b.file)));
+    rows
+}
+
+pub fn witness_csv(rows: &[WitnessRow]) -> String {
+    to_csv(
+        &["日付", "区分", "立会者", "ファイル", "黒板"],
+        rows.iter().map(|r| {
+            vec![
+                r.date.map(|(y, m, d)| format!("{y:04}-{m:02}-{d:02}")).unwrap_or_default(),
+                r.kind.label().to_string(),
+                r.attendees.join("、"),
+                r.file.clone(),
+                r.board.clone(),
+            ]
+        }),
+    )
+}
@@ -0,0 +1,252 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use zip::write::SimpleFileOptions;
+
+use crate::error::{Error, Result};
+use crate::fs_ops::long_path;
+
+/// Excel のシート名に使えない文字と最大長
+const SHEET_NAME_FORBIDDEN: [char; 7] = ['[', ']', ':', '*', '?', '/', '\\'];
+const SHEET_NAME_MAX: usize = 31;
+
+/// セル 1 つ分の値
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+impl From<&str> for Cell {
+    fn from(s: &str) -> Self {
+        Cell::Text(s.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(s: String) -> Self {
+        Cell::Text(s)
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(n: usize) -> Self {
+        Cell::Number(n as f64)
+    }
+}
+
+impl From<u32> for Cell {
+    fn from(n: u32) -> Self {
+        Cell::Number(f64::from(n))
+    }
+}
+
+/// ワークシート 1 枚（行ごとのセル。先頭行を見出しにする想定）
+#[derive(Debug, Clone, Default)]
+pub struct Sheet {
+    pub name: String,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Sheet {
+    pub fn new(name: &str) -> Self {
+        Sheet { name: name.to_string(), rows: Vec::new() }
+    }
+
+    pub fn push<C: Into<Cell>>(&mut self, row: impl IntoIterator<Item = C>) {
+        self.rows.push(row.into_iter().map(Into::into).collect());
+    }
+}
+
+/// 列番号（0 始まり）を A, B, …, Z, AA, … にする
+fn column_name(mut index: usize) -> String {
+    let mut out = Vec::new();
+    loop {
+        out.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    out.reverse();
+    String::from_utf8(out).expect("ASCII column name")
+}
+
+/// XML のテキストに入れられるようにする（XML で使えない制御文字は落とす）
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Excel が受け付けるシート名にする（使えない文字は `_`、31 文字まで、空なら Sheet{n}）
+fn sheet_name(name: &str, n: usize) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if SHEET_NAME_FORBIDDEN.contains(&c) { '_' } else { c })
+        .take(SHEET_NAME_MAX)
+        .collect();
+    let name = name.trim_matches('\'');
+    if name.is_empty() {
+        format!("Sheet{n}")
+    } else {
+        name.to_string()
+    }
+}
+
+fn worksheet_xml(sheet: &Sheet) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><sheetData>",
+    );
+    for (r, row) in sheet.rows.iter().enumerate() {
+        let _ = write!(out, "<row r=\"{}\">", r + 1);
+        for (c, cell) in row.iter().enumerate() {
+            let at = format!("{}{}", column_name(c), r + 1);
+            match cell {
+                Cell::Text(s) if s.is_empty() => {}
+                Cell::Text(s) => {
+                    let _ = write!(out, "<c r=\"{at}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>", xml_escape(s));
+                }
+                Cell::Number(n) if n.is_finite() => {
+                    let _ = write!(out, "<c r=\"{at}\"><v>{n}</v></c>");
+                }
+                Cell::Number(_) => {}
+            }
+        }
+        out.push_str("</row>");
+    }
+    out.push_str("</sheetData></worksheet>");
+    out
+}
+
+/// シートを並べた最小構成の xlsx（Office Open XML）を書き出す。文字列はインライン文字列で持ち、書式は付けない。
+pub fn write_xlsx(out: &Path, sheets: &[Sheet]) -> Result<()> {
+    if sheets.is_empty() {
+        return Err(Error::Validation("xlsx needs at least one sheet".into()));
+    }
+    let zip_err = |e: zip::result::ZipError| Error::io(out, e.into());
+    let io_err = |e: std::io::Error| Error::io(out, e);
+    let file = File::create(long_path(out)).map_err(io_err)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+
+    let mut content_types = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+         <Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>",
+    );
+    let mut workbook = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+         xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><sheets>",
+    );
+    let mut workbook_rels = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    );
+    let mut names: Vec<String> = Vec::new();
+    for (i, sheet) in sheets.iter().enumerate() {
+        let n = i + 1;
+        // 同じ名前のシートは Excel が開けないので番号を付けて区別する
+        let mut name = sheet_name(&sheet.name, n);
+        if names.iter().any(|prev| prev.to_lowercase() == name.to_lowercase()) {
+            let suffix = format!(" ({n})");
+            name = name.chars().take(SHEET_NAME_MAX - suffix.chars().count()).collect::<String>() + &suffix;
+        }
+        let _ = write!(
+            content_types,
+            "<Override PartName=\"/xl/worksheets/sheet{n}.xml\" \
+             ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>"
+        );
+        let _ = write!(workbook, "<sheet name=\"{}\" sheetId=\"{n}\" r:id=\"rId{n}\"/>", xml_escape(&name));
+        let _ = write!(
+            workbook_rels,
+            "<Relationship Id=\"rId{n}\" \
+             Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" \
+             Target=\"worksheets/sheet{n}.xml\"/>"
+        );
+        names.push(name);
+    }
+    content_types.push_str("</Types>");
+    workbook.push_str("</sheets></workbook>");
+    workbook_rels.push_str("</Relationships>");
+    let package_rels = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+        <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+        <Relationship Id=\"rId1\" \
+        Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" \
+        Target=\"xl/workbook.xml\"/></Relationships>";
+
+    let parts = [
+        ("[Content_Types].xml".to_string(), content_types),
+        ("_rels/.rels".to_string(), package_rels.to_string()),
+        ("xl/workbook.xml".to_string(), workbook),
+        ("xl/_rels/workbook.xml.rels".to_string(), workbook_rels),
+    ];
+    let sheet_parts = sheets
+        .iter()
+        .enumerate()
+        .map(|(i, sheet)| (format!("xl/worksheets/sheet{}.xml", i + 1), worksheet_xml(sheet)));
+    for (name, xml) in parts.into_iter().chain(sheet_parts) {
+        zip.start_file(name, options).map_err(zip_err)?;
+        zip.write_all(xml.as_bytes()).map_err(io_err)?;
+    }
+    zip.finish().map_err(zip_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn read_part(path: &Path, name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut out = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn column_names_continue_past_z() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(27 * 26 - 1), "ZZ");
+        assert_eq!(column_name(27 * 26), "AAA");
+    }
+
+    #[test]
+    fn writes_escaped_text_and_numbers() {
+        let path = std::env::temp_dir().join(format!("photo-tagger-xlsx-{}.xlsx", std::process::id()));
+        let mut sheet = Sheet::new("使用機械/一覧");
+        sheet.push(["機械名", "写真枚数"]);
+        sheet.push([Cell::from("<ローラー> & \"R1\""), Cell::from(3usize)]);
+        let mut second = Sheet::new("使用機械_一覧");
+        second.push([""]);
+        write_xlsx(&path, &[sheet, second]).unwrap();
+
+        let workbook = read_part(&path, "xl/workbook.xml");
+        assert!(workbook.contains("<sheet name=\"使用機械_一覧\" sheetId=\"1\""));
+        assert!(workbook.contains("<sheet name=\"使用機械_一覧 (2)\" sheetId=\"2\""));
+        let sheet1 = read_part(&path, "xl/worksheets/sheet1.xml");
+        assert!(sheet1.contains("<c r=\"A2\" t=\"inlineStr\"><is><t xml:space=\"preserve\">&lt;ローラー&gt; &amp; &quot;R1&quot;</t></is></c>"));
+        assert!(sheet1.contains("<c r=\"B2\"><v>3</v></c>"));
+        assert!(read_part(&path, "[Content_Types].xml").contains("/xl/worksheets/sheet2.xml"));
+        let _ = std::fs::remove_file(&path);
+    }
+}